@@ -0,0 +1,35 @@
+#ifndef XLSX2CSV_H
+#define XLSX2CSV_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The conversion succeeded.
+#define XLSX2CSV_OK 0
+
+// A path or the options are missing, not UTF-8 or not valid JSON.
+#define XLSX2CSV_INVALID_ARGUMENT 1
+
+// The conversion failed, see `xlsx2csv_last_error`.
+#define XLSX2CSV_FAILED 2
+
+// Converts the workbook at `input_path` into `output_path` with the options
+// given as a JSON object (ex. `{"sheet": "Data", "format": "jsonl"}`), which
+// may be NULL for the defaults. Returns `XLSX2CSV_OK` or an error code, with
+// the message available from `xlsx2csv_last_error`.
+//
+// # Safety
+//
+// The arguments must be NULL or point to NUL-terminated strings.
+int xlsx2csv_convert(const char *input_path, const char *options_json, const char *output_path);
+
+// The message of the last failed call on this thread, or NULL. The string
+// stays valid until the next `xlsx2csv_convert` call on the same thread.
+const char *xlsx2csv_last_error(void);
+
+#endif  /* XLSX2CSV_H */
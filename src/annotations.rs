@@ -0,0 +1,372 @@
+//! Sheet annotations read from related parts: comments, hyperlinks and data
+//! validations, and their merging into cell values.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek};
+use zip::read::ZipArchive;
+
+use crate::archive::{eq_local, open_part, part_rels_path, read_relationships, resolve_part_path};
+use crate::sheet::{cell_row, column_index, HyperlinkMode};
+use crate::{Result, Xlsx2CsvError};
+
+/// A cell comment (legacy note) read from a sheet's comments part.
+pub struct Comment {
+    pub cell_ref: String,
+    pub author: String,
+    pub text: String,
+}
+
+/// Loads the comments attached to the sheet at `sheet_path`. Notes are keyed by
+/// the `ref` attribute of the comments part, so the VML drawing that positions
+/// the note boxes is not needed.
+pub fn load_comments<R: Read + Seek>(archive: &mut ZipArchive<R>, sheet_path: &str) -> Result<Vec<Comment>> {
+    let mut comments = Vec::new();
+    let parts: Vec<String> = read_relationships(archive, &part_rels_path(sheet_path))?
+        .into_iter()
+        .filter(|rel| rel.is("comments") && !rel.external)
+        .map(|rel| resolve_part_path(sheet_path, &rel.target))
+        .collect();
+
+    for part in parts {
+        let Ok(file) = open_part(archive, &part) else {
+            continue;
+        };
+        let mut reader = Reader::from_reader(BufReader::new(file));
+        reader.trim_text(false);
+        let mut buf = Vec::new();
+        let mut authors = Vec::new();
+        let mut in_author = false;
+        let mut in_text = false;
+        let mut in_phonetic = false;
+        let mut current: Option<Comment> = None;
+
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) if eq_local(e.name().as_ref(), b"author") => {
+                    authors.push(String::new());
+                    in_author = true;
+                }
+                Event::End(e) if eq_local(e.name().as_ref(), b"author") => in_author = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"comment") => {
+                    let mut comment = Comment {
+                        cell_ref: String::new(),
+                        author: String::new(),
+                        text: String::new(),
+                    };
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        match attr.key.as_ref() {
+                            b"ref" => comment.cell_ref = attr.unescape_value()?.into_owned(),
+                            b"authorId" => {
+                                let id: usize = attr.unescape_value()?.trim().parse().unwrap_or(usize::MAX);
+                                comment.author = authors.get(id).cloned().unwrap_or_default();
+                            }
+                            _ => {}
+                        }
+                    }
+                    current = Some(comment);
+                }
+                Event::End(e) if eq_local(e.name().as_ref(), b"comment") => {
+                    if let Some(comment) = current.take() {
+                        comments.push(comment);
+                    }
+                }
+                Event::Start(e) if eq_local(e.name().as_ref(), b"text") => in_text = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"text") => in_text = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = false,
+                Event::Text(t) if in_author => {
+                    if let Some(author) = authors.last_mut() {
+                        author.push_str(&t.unescape()?);
+                    }
+                }
+                Event::Text(t) if in_text && !in_phonetic => {
+                    if let Some(comment) = current.as_mut() {
+                        comment.text.push_str(&t.unescape()?);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(comments)
+}
+
+/// A hyperlink declared in a sheet's `<hyperlinks>` block.
+pub struct Hyperlink {
+    pub cell_ref: String,
+    pub url: String,
+    pub display: String,
+}
+
+/// Collects the hyperlinks of the sheet at `sheet_path` from its XML `sheet`.
+/// The `<hyperlinks>` block follows the cell data, so this is a separate pass
+/// over the sheet. Links spanning a range are attributed to its top-left cell.
+/// A relationship id that is not defined leaves the link without a target, or
+/// fails with `strict`.
+pub fn load_hyperlinks<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheet_path: &str,
+    sheet: impl Read,
+    strict: bool,
+) -> Result<Vec<Hyperlink>> {
+    let targets: HashMap<String, String> = read_relationships(archive, &part_rels_path(sheet_path))?
+        .into_iter()
+        .filter(|rel| rel.is("hyperlink"))
+        .map(|rel| (rel.id, rel.target))
+        .collect();
+
+    let mut reader = Reader::from_reader(BufReader::new(sheet));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut links = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"hyperlink") => {
+                let mut cell_ref = String::new();
+                let mut target = String::new();
+                let mut location = String::new();
+                let mut display = String::new();
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    let key = attr.key.as_ref();
+                    if key == b"ref" {
+                        cell_ref = attr.unescape_value()?.into_owned();
+                    } else if key == b"location" {
+                        location = attr.unescape_value()?.into_owned();
+                    } else if key == b"display" {
+                        display = attr.unescape_value()?.into_owned();
+                    } else if eq_local(key, b"id") {
+                        let id = attr.unescape_value()?;
+                        target = match targets.get(id.as_ref()) {
+                            Some(target) => target.clone(),
+                            None if strict => {
+                                return Err(Xlsx2CsvError::UnresolvedRelationship {
+                                    part: sheet_path.to_string(),
+                                    id: id.into_owned(),
+                                });
+                            }
+                            None => String::new(),
+                        };
+                    }
+                }
+                let url = match (target.is_empty(), location.is_empty()) {
+                    (_, true) => target,
+                    (true, false) => format!("#{}", location),
+                    (false, false) => format!("{}#{}", target, location),
+                };
+                let cell_ref = cell_ref.split(':').next().unwrap_or_default().to_string();
+                if !url.is_empty() && !cell_ref.is_empty() {
+                    links.push(Hyperlink { cell_ref, url, display });
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(links)
+}
+
+/// A `<dataValidation>` rule, including the x14 extension form.
+pub struct DataValidation {
+    pub sqref: String,
+    pub kind: String,
+    pub operator: String,
+    pub formula1: String,
+    pub formula2: String,
+    pub allow_blank: bool,
+}
+
+/// Collects the data validation rules from the XML of a sheet.
+pub fn load_validations(sheet: impl Read) -> Result<Vec<DataValidation>> {
+    let mut reader = Reader::from_reader(BufReader::new(sheet));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut rules = Vec::new();
+    let mut current: Option<DataValidation> = None;
+    // 1 or 2 while inside <formula1>/<formula2>, 3 inside an x14 <sqref>
+    let mut field = 0u8;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if eq_local(e.name().as_ref(), b"dataValidation") => current = Some(validation_rule(&e)?),
+            // A rule without formulas, such as a list of any value
+            Event::Empty(e) if eq_local(e.name().as_ref(), b"dataValidation") => rules.push(complete_rule(validation_rule(&e)?)),
+            Event::End(e) if eq_local(e.name().as_ref(), b"dataValidation") => {
+                if let Some(rule) = current.take() {
+                    rules.push(complete_rule(rule));
+                }
+            }
+            Event::Start(e) if current.is_some() => {
+                let name = e.name();
+                field = if eq_local(name.as_ref(), b"formula1") {
+                    1
+                } else if eq_local(name.as_ref(), b"formula2") {
+                    2
+                } else if eq_local(name.as_ref(), b"sqref") {
+                    3
+                } else {
+                    field
+                };
+            }
+            Event::End(e) if current.is_some() => {
+                let name = e.name();
+                let local = name.as_ref();
+                if eq_local(local, b"formula1") || eq_local(local, b"formula2") || eq_local(local, b"sqref") {
+                    field = 0;
+                }
+            }
+            Event::Text(t) if field != 0 => {
+                if let Some(rule) = current.as_mut() {
+                    let text = t.unescape()?;
+                    match field {
+                        1 => rule.formula1.push_str(&text),
+                        2 => rule.formula2.push_str(&text),
+                        _ => rule.sqref.push_str(&text),
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(rules)
+}
+
+/// The rule started by a `<dataValidation>` element, from its attributes.
+fn validation_rule(e: &BytesStart) -> Result<DataValidation> {
+    let mut rule = DataValidation {
+        sqref: String::new(),
+        // Rules without a type accept any value
+        kind: "none".to_string(),
+        operator: String::new(),
+        formula1: String::new(),
+        formula2: String::new(),
+        allow_blank: false,
+    };
+    for attr in e.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"sqref" => rule.sqref = attr.unescape_value()?.into_owned(),
+            b"type" => rule.kind = attr.unescape_value()?.into_owned(),
+            b"operator" => rule.operator = attr.unescape_value()?.into_owned(),
+            b"allowBlank" => {
+                let value = attr.unescape_value()?;
+                rule.allow_blank = value == "1" || value == "true";
+            }
+            _ => {}
+        }
+    }
+    Ok(rule)
+}
+
+/// Fills in the operator a comparison rule leaves to the schema default.
+fn complete_rule(mut rule: DataValidation) -> DataValidation {
+    // `between` is the schema default for comparison rules
+    let compares = matches!(rule.kind.as_str(), "whole" | "decimal" | "date" | "time" | "textLength");
+    if compares && rule.operator.is_empty() {
+        rule.operator = "between".to_string();
+    }
+    rule
+}
+
+/// Text merged into cell values, keyed by 1-based row number and then 0-based
+/// column.
+#[derive(Default)]
+pub struct CellAnnotations {
+    by_row: HashMap<usize, Vec<(usize, Annotation)>>,
+}
+
+enum Annotation {
+    Append(String),
+    Replace(String),
+}
+
+impl CellAnnotations {
+    fn add(&mut self, cell_ref: &str, annotation: Annotation) {
+        if let (Some(col), Some(row)) = (column_index(cell_ref), cell_row(cell_ref)) {
+            self.by_row.entry(row).or_default().push((col, annotation));
+        }
+    }
+
+    pub fn add_hyperlinks(&mut self, links: &[Hyperlink], mode: HyperlinkMode) {
+        for link in links {
+            match mode {
+                HyperlinkMode::Url => self.add(&link.cell_ref, Annotation::Replace(link.url.clone())),
+                HyperlinkMode::Append => self.add(&link.cell_ref, Annotation::Append(format!("<{}>", link.url))),
+                HyperlinkMode::SeparateFile => {}
+            }
+        }
+    }
+
+    pub fn add_comments(&mut self, comments: &[Comment]) {
+        for comment in comments {
+            // Excel prefixes note text with "Author:", which the annotation already carries
+            let text = comment
+                .text
+                .strip_prefix(&format!("{}:", comment.author))
+                .unwrap_or(&comment.text)
+                .trim();
+            let annotation = if comment.author.is_empty() {
+                format!("[{}]", text)
+            } else {
+                format!("[{}: {}]", comment.author, text)
+            };
+            self.add(&comment.cell_ref, Annotation::Append(annotation));
+        }
+    }
+
+    pub fn apply(&self, row_number: usize, row: &mut Vec<String>) {
+        let Some(annotations) = self.by_row.get(&row_number) else {
+            return;
+        };
+        for (col, annotation) in annotations {
+            if row.len() <= *col {
+                row.resize(col + 1, String::new());
+            }
+            let cell = &mut row[*col];
+            match annotation {
+                Annotation::Replace(text) => *cell = text.clone(),
+                Annotation::Append(text) => {
+                    if !cell.is_empty() {
+                        cell.push(' ');
+                    }
+                    cell.push_str(text);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::load_validations;
+
+    #[test]
+    fn self_closing_rules_are_kept() {
+        let xml = r#"<worksheet><dataValidations count="3">
+            <dataValidation type="whole" allowBlank="1" sqref="A2:A9"><formula1>1</formula1><formula2>10</formula2></dataValidation>
+            <dataValidation type="custom" sqref="B2"/>
+            <dataValidation type="decimal" sqref="C2:C4"/>
+        </dataValidations></worksheet>"#;
+        let rules = load_validations(xml.as_bytes()).unwrap();
+        let summary: Vec<_> = rules
+            .iter()
+            .map(|rule| (rule.sqref.as_str(), rule.kind.as_str(), rule.operator.as_str(), rule.formula1.as_str(), rule.allow_blank))
+            .collect();
+        assert_eq!(
+            summary,
+            [("A2:A9", "whole", "between", "1", true), ("B2", "custom", "", "", false), ("C2:C4", "decimal", "between", "", false)]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Package-level access to the xlsx zip archive: relationship parts and
+//! part path resolution.
+
+use flate2::read::DeflateDecoder;
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, QName, ResolveResult};
+use quick_xml::{NsReader, Reader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use tracing::{debug, instrument};
+use zip::read::{ZipArchive, ZipFile};
+use zip::result::{ZipError, ZipResult};
+use zip::CompressionMethod;
+
+use crate::{Result, Xlsx2CsvError};
+
+/// Maps the relationship ids of `xl/workbook.xml` to their targets.
+#[instrument(skip_all)]
+pub fn load_relationships<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<HashMap<String, String>> {
+    Ok(read_relationships(archive, "xl/_rels/workbook.xml.rels")?
+        .into_iter()
+        .map(|rel| (rel.id, rel.target))
+        .collect())
+}
+
+/// A single `<Relationship>` entry of a `.rels` part.
+pub struct Relationship {
+    pub id: String,
+    pub rel_type: String,
+    pub target: String,
+    pub external: bool,
+}
+
+impl Relationship {
+    /// Whether the relationship type ends with `kind` (ex. "comments").
+    pub fn is(&self, kind: &str) -> bool {
+        self.rel_type.rsplit('/').next() == Some(kind)
+    }
+}
+
+pub fn read_relationships<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<Vec<Relationship>> {
+    let mut rels = Vec::new();
+    let Ok(file) = open_part(archive, path) else {
+        return Ok(rels);
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"Relationship") => {
+                let mut id = None;
+                let mut target = None;
+                let mut rel_type = String::new();
+                let mut external = false;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"Id" => id = Some(attr.unescape_value()?.into_owned()),
+                        b"Target" => target = Some(attr.unescape_value()?.into_owned()),
+                        b"Type" => rel_type = attr.unescape_value()?.into_owned(),
+                        b"TargetMode" => external = attr.unescape_value()?.eq_ignore_ascii_case("External"),
+                        _ => {}
+                    }
+                }
+                if let (Some(id), Some(target)) = (id, target) {
+                    rels.push(Relationship {
+                        id,
+                        rel_type,
+                        target,
+                        external,
+                    });
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(rels)
+}
+
+/// Fails when the parts of the archive declare more than `limit` bytes once
+/// inflated. The declared sizes can be forged, so every part is also read
+/// through [`Bounded`].
+pub fn check_uncompressed_size<R: Read + Seek>(archive: &mut ZipArchive<R>, limit: u64) -> Result<()> {
+    let mut size: u64 = 0;
+    for idx in 0..archive.len() {
+        size = size.saturating_add(archive.by_index_raw(idx)?.size());
+    }
+    debug!(size, limit, "uncompressed size of the archive");
+    if size > limit {
+        return Err(Xlsx2CsvError::UncompressedSizeLimit { size, limit });
+    }
+    Ok(())
+}
+
+/// Opens `name` to be inflated through [`Bounded`].
+pub fn open_part<'a, R: Read + Seek>(archive: &'a mut ZipArchive<R>, name: &str) -> ZipResult<Bounded<ZipFile<'a>>> {
+    Ok(Bounded::entry(archive.by_name(name)?))
+}
+
+/// A reader of an inflated part that fails once the part grows past the size
+/// its zip entry declares, so that a forged entry cannot inflate further than
+/// [`check_uncompressed_size`] allowed for.
+pub struct Bounded<P> {
+    inner: P,
+    size: u64,
+    left: u64,
+}
+
+impl<'a> Bounded<ZipFile<'a>> {
+    pub fn entry(file: ZipFile<'a>) -> Self {
+        let size = file.size();
+        Bounded::new(file, size)
+    }
+}
+
+impl<P: Read> Bounded<P> {
+    pub fn new(inner: P, size: u64) -> Self {
+        Bounded { inner, size, left: size }
+    }
+
+    /// Uncompressed size declared for the part
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<P: Read> Read for Bounded<P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.left = self.left.checked_sub(read as u64).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "a part inflates past the size declared in the archive")
+        })?;
+        Ok(read)
+    }
+}
+
+/// The compressed bytes of a part, read up front so that it can be inflated
+/// on another thread.
+pub struct RawPart {
+    data: Vec<u8>,
+    deflated: bool,
+    /// Uncompressed size
+    pub size: u64,
+}
+
+impl RawPart {
+    /// Reads `name` without inflating it. Parts compressed with methods other
+    /// than deflate are inflated right away.
+    pub fn read<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Self> {
+        let index = archive.index_for_name(name).ok_or(ZipError::FileNotFound)?;
+        let mut raw = archive.by_index_raw(index)?;
+        let size = raw.size();
+        let compression = raw.compression();
+        if matches!(compression, CompressionMethod::Stored | CompressionMethod::Deflated) {
+            let mut data = Vec::with_capacity(raw.compressed_size() as usize);
+            raw.read_to_end(&mut data)?;
+            return Ok(RawPart {
+                data,
+                deflated: compression == CompressionMethod::Deflated,
+                size,
+            });
+        }
+        drop(raw);
+        let mut data = Vec::with_capacity(size as usize);
+        Bounded::entry(archive.by_index(index)?).read_to_end(&mut data)?;
+        Ok(RawPart {
+            data,
+            deflated: false,
+            size,
+        })
+    }
+
+    /// Reads the uncompressed part.
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        if self.deflated {
+            Box::new(Bounded::new(DeflateDecoder::new(Cursor::new(self.data)), self.size))
+        } else {
+            Box::new(Cursor::new(self.data))
+        }
+    }
+}
+
+/// Inflated parts kept in memory up to this size, larger ones go to a
+/// temporary file.
+const CACHE_MEMORY: u64 = 64 * 1024 * 1024;
+
+/// Parts inflated once for several passes over them, ex. a worksheet read for
+/// its hyperlinks, validations and autofilter before its cells are converted.
+pub struct PartCache {
+    parts: HashMap<String, CachedPart>,
+    memory_limit: u64,
+}
+
+/// An inflated part held by a [`PartCache`].
+pub enum CachedPart {
+    Memory(Vec<u8>),
+    File(File),
+}
+
+impl PartCache {
+    /// A cache keeping parts up to a quarter of `max_memory` in memory, see
+    /// [`Options::max_memory`](crate::Options::max_memory).
+    pub fn new(max_memory: Option<u64>) -> Self {
+        PartCache {
+            parts: HashMap::new(),
+            memory_limit: max_memory.map_or(CACHE_MEMORY, |max| (max / 4).min(CACHE_MEMORY)),
+        }
+    }
+
+    /// Reads `name` from the start, inflating it from `archive` on the first
+    /// read only.
+    pub fn open<R: Read + Seek>(&mut self, archive: &mut ZipArchive<R>, name: &str) -> Result<Box<dyn Read + '_>> {
+        if !self.parts.contains_key(name) {
+            let mut file = open_part(archive, name)?;
+            let part = if file.size() <= self.memory_limit {
+                let mut data = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut data)?;
+                CachedPart::Memory(data)
+            } else {
+                debug!(part = name, size = file.size(), "caching inflated part in a temporary file");
+                let mut temp = tempfile::tempfile()?;
+                io::copy(&mut file, &mut temp)?;
+                CachedPart::File(temp)
+            };
+            self.parts.insert(name.to_string(), part);
+        }
+        Ok(match &self.parts[name] {
+            CachedPart::Memory(data) => Box::new(data.as_slice()),
+            CachedPart::File(file) => {
+                let mut file = file;
+                file.seek(SeekFrom::Start(0))?;
+                Box::new(BufReader::new(file))
+            }
+        })
+    }
+
+    /// Removes `name` from the cache for its last pass.
+    pub fn take(&mut self, name: &str) -> Option<CachedPart> {
+        self.parts.remove(name)
+    }
+}
+
+impl CachedPart {
+    /// Uncompressed size
+    pub fn size(&self) -> Result<u64> {
+        Ok(match self {
+            CachedPart::Memory(data) => data.len() as u64,
+            CachedPart::File(file) => file.metadata()?.len(),
+        })
+    }
+
+    /// Reads the part from the start.
+    pub fn into_reader(self) -> Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            CachedPart::Memory(data) => Box::new(Cursor::new(data)),
+            CachedPart::File(mut file) => {
+                file.seek(SeekFrom::Start(0))?;
+                Box::new(BufReader::new(file))
+            }
+        })
+    }
+}
+
+/// Path of the `.rels` part describing `part` (ex. xl/worksheets/_rels/sheet1.xml.rels).
+pub fn part_rels_path(part: &str) -> String {
+    match part.rsplit_once('/') {
+        Some((dir, file)) => format!("{}/_rels/{}.rels", dir, file),
+        None => format!("_rels/{}.rels", part),
+    }
+}
+
+/// Resolves a relationship target relative to the part that declared it.
+pub fn resolve_part_path(part: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+    let mut segments: Vec<&str> = part.split('/').collect();
+    segments.pop();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            other => segments.push(other),
+        }
+    }
+    segments.join("/")
+}
+
+/// The relationships namespace of transitional and of strict OOXML.
+const RELATIONSHIPS_NAMESPACES: [&[u8]; 2] = [
+    b"http://schemas.openxmlformats.org/officeDocument/2006/relationships",
+    b"http://purl.oclc.org/ooxml/officeDocument/relationships",
+];
+
+/// Whether the attribute `key` is the relationship id (usually `r:id`), by its
+/// namespace rather than its prefix.
+pub fn is_relationship_id<R>(reader: &NsReader<R>, key: QName) -> bool {
+    match reader.resolve_attribute(key) {
+        (ResolveResult::Bound(Namespace(ns)), local) => {
+            local.as_ref() == b"id" && RELATIONSHIPS_NAMESPACES.contains(&ns)
+        }
+        _ => false,
+    }
+}
+
+/// Compares the local part of a possibly prefixed XML name (ex. `x:row`).
+pub fn eq_local(name: &[u8], expected: &[u8]) -> bool {
+    let local = name
+        .rsplit(|&b| b == b':')
+        .next()
+        .unwrap_or(name);
+    local == expected
+}
+
@@ -0,0 +1,43 @@
+//! Async conversion for tokio runtimes, behind the `tokio` feature.
+
+use std::io::{self, Cursor, SeekFrom};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{convert, Options, Result};
+
+/// Async counterpart of [`convert`]: the workbook is read from `reader`,
+/// converted on tokio's blocking thread pool and the output written to
+/// `writer`, so the calling task never blocks its runtime thread.
+///
+/// The whole workbook and its output are held in memory while converting,
+/// which suits uploads and other bounded inputs.
+///
+/// ```no_run
+/// # async fn run() -> xlsx2csv::Result<()> {
+/// let upload = tokio::fs::File::open("input.xlsx").await?;
+/// let mut csv = Vec::new();
+/// xlsx2csv::convert_async(upload, &xlsx2csv::Options::default(), &mut csv).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn convert_async<R, W>(mut reader: R, options: &Options, mut writer: W) -> Result<()>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut workbook = Vec::new();
+    reader.seek(SeekFrom::Start(0)).await?;
+    reader.read_to_end(&mut workbook).await?;
+
+    let options = options.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        let mut output = Vec::new();
+        convert(Cursor::new(workbook), &options, &mut output).map(|_| output)
+    })
+    .await
+    .map_err(io::Error::other)??;
+
+    writer.write_all(&output).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! The `--audit` report of what the conversion dropped from or changed in the
+//! sheets it converted.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+use zip::read::ZipArchive;
+
+use crate::annotations::load_comments;
+use crate::archive::{eq_local, open_part};
+use crate::schema::json_string;
+use crate::workbook::SheetInfo;
+use crate::{HyperlinkMode, Options, Result};
+
+const SHARED_STRINGS_PART: &str = "xl/sharedStrings.xml";
+
+/// What one sheet holds that the csv output cannot carry, counted over the
+/// whole sheet.
+#[derive(Default)]
+struct SheetAudit {
+    name: String,
+    comments: usize,
+    formulas: usize,
+    hyperlinks: usize,
+    merged_regions: usize,
+    hidden_rows: usize,
+    /// Cells referring to a string past the end of the shared string table
+    missing_shared_strings: usize,
+}
+
+/// Scans the converted `sheets` again and writes the report to `path`.
+pub fn write_audit<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheets: &[SheetInfo],
+    options: &Options,
+    path: &Path,
+) -> Result<()> {
+    let shared_strings = count_shared_strings(archive)?;
+    let mut audits = Vec::with_capacity(sheets.len());
+    for sheet in sheets {
+        let mut audit = audit_sheet(archive, &sheet.path, shared_strings)?;
+        audit.name = sheet.name.clone();
+        audit.comments = load_comments(archive, &sheet.path)?.len();
+        audits.push(audit);
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    write_report(&audits, options, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn audit_sheet<R: Read + Seek>(archive: &mut ZipArchive<R>, sheet_path: &str, shared_strings: usize) -> Result<SheetAudit> {
+    let mut reader = Reader::from_reader(BufReader::new(open_part(archive, sheet_path)?));
+    let mut buf = Vec::new();
+    let mut audit = SheetAudit::default();
+    let mut shared_string_cell = false;
+    let mut in_value = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"row") => {
+                audit.hidden_rows += usize::from(is_true(&e, b"hidden")?);
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
+                shared_string_cell = false;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"t" {
+                        shared_string_cell = attr.unescape_value()? == "s";
+                    }
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"f") => audit.formulas += 1,
+            Event::Start(e) if eq_local(e.name().as_ref(), b"v") => in_value = true,
+            Event::End(e) if eq_local(e.name().as_ref(), b"v") => in_value = false,
+            Event::Text(t) if in_value && shared_string_cell => {
+                let index = t.unescape()?.trim().parse::<usize>();
+                audit.missing_shared_strings += usize::from(index.is_ok_and(|index| index >= shared_strings));
+            }
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"hyperlink") => audit.hyperlinks += 1,
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"mergeCell") => audit.merged_regions += 1,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(audit)
+}
+
+fn is_true(e: &BytesStart, key: &[u8]) -> Result<bool> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.as_ref() == key {
+            return Ok(matches!(attr.unescape_value()?.as_ref(), "1" | "true"));
+        }
+    }
+    Ok(false)
+}
+
+/// The number of `<si>` entries of the shared string table.
+pub(crate) fn count_shared_strings<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<usize> {
+    let Ok(file) = open_part(archive, SHARED_STRINGS_PART) else {
+        return Ok(0);
+    };
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    let mut count = 0;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"si") => count += 1,
+            Event::Eof => return Ok(count),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Writes one object per sheet with the count of each item and what the
+/// conversion did with it under `options`.
+fn write_report<W: Write>(audits: &[SheetAudit], options: &Options, out: &mut W) -> Result<()> {
+    let comments = match (&options.comments, options.append_comments) {
+        (Some(_), _) => "written to the comments file",
+        (None, true) => "appended to the cell values",
+        (None, false) => "dropped",
+    };
+    let formulas = if options.formulas {
+        "formula text written instead of the values"
+    } else {
+        "dropped, cached values written"
+    };
+    let hyperlinks = match options.hyperlinks {
+        None => "dropped",
+        Some(HyperlinkMode::Url) => "written in place of the cell text",
+        Some(HyperlinkMode::Append) => "appended to the cell text",
+        Some(HyperlinkMode::SeparateFile) => "written to the hyperlinks file",
+    };
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"sheets\": [")?;
+    for (i, audit) in audits.iter().enumerate() {
+        let items = [
+            ("comments", audit.comments, comments),
+            ("formulas", audit.formulas, formulas),
+            ("hyperlinks", audit.hyperlinks, hyperlinks),
+            ("merged_regions", audit.merged_regions, "value kept in the top-left cell only"),
+            ("hidden_rows", audit.hidden_rows, "written like visible rows"),
+            ("missing_shared_strings", audit.missing_shared_strings, "written as empty cells"),
+        ];
+        writeln!(out, "    {{")?;
+        writeln!(out, "      \"name\": {},", json_string(&audit.name))?;
+        for (j, (item, count, handling)) in items.iter().enumerate() {
+            writeln!(
+                out,
+                "      \"{}\": {{\"count\": {}, \"handling\": \"{}\"}}{}",
+                item,
+                count,
+                handling,
+                if j + 1 < items.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(out, "    }}{}", if i + 1 < audits.len() { "," } else { "" })?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Sheet `<autoFilter>` support for `--respect-autofilter`.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{BufReader, Read};
+
+use crate::archive::eq_local;
+use crate::sheet::{cell_row, column_index};
+use crate::Result;
+
+/// The autofilter range of a sheet and the criteria stored for its columns.
+pub struct AutoFilter {
+    first_row: usize,
+    last_row: usize,
+    columns: Vec<(usize, ColumnFilter)>,
+}
+
+/// Criteria of a `<filterColumn>`. Only value lists and custom comparisons are
+/// evaluated; other filter kinds (top 10, colors, dynamic) keep every row.
+#[derive(Default)]
+struct ColumnFilter {
+    values: Vec<String>,
+    blank: bool,
+    custom: Vec<(String, String)>,
+    custom_and: bool,
+}
+
+impl AutoFilter {
+    /// Whether the row at 1-based `row_number` lies inside the filter range and
+    /// satisfies every column criterion. The header row of the range is always kept.
+    pub fn keeps(&self, row_number: usize, row: &[String]) -> bool {
+        if row_number < self.first_row || row_number > self.last_row {
+            return false;
+        }
+        if row_number == self.first_row {
+            return true;
+        }
+        self.columns.iter().all(|(col, filter)| {
+            let value = row.get(*col).map(String::as_str).unwrap_or_default();
+            filter.matches(value)
+        })
+    }
+}
+
+impl ColumnFilter {
+    fn matches(&self, value: &str) -> bool {
+        let mut constrained = false;
+        if !self.values.is_empty() || self.blank {
+            constrained = true;
+            if (self.blank && value.is_empty()) || self.values.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+                return true;
+            }
+        }
+        if !self.custom.is_empty() {
+            let mut results = self.custom.iter().map(|(op, val)| custom_matches(op, val, value));
+            return if self.custom_and {
+                results.all(|r| r)
+            } else {
+                results.any(|r| r)
+            };
+        }
+        !constrained
+    }
+}
+
+fn custom_matches(operator: &str, expected: &str, value: &str) -> bool {
+    use std::cmp::Ordering;
+
+    let ordering = match (expected.trim().parse::<f64>(), value.trim().parse::<f64>()) {
+        (Ok(e), Ok(v)) => v.partial_cmp(&e),
+        _ => Some(value.to_lowercase().cmp(&expected.to_lowercase())),
+    };
+    match operator {
+        "notEqual" => !wildcard_matches(expected, value),
+        "lessThan" => ordering == Some(Ordering::Less),
+        "lessThanOrEqual" => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        "greaterThan" => ordering == Some(Ordering::Greater),
+        "greaterThanOrEqual" => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        _ => wildcard_matches(expected, value),
+    }
+}
+
+/// Case-insensitive match supporting Excel's `*` and `?` wildcards.
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bv)) => {
+                    p = bp + 1;
+                    v = bv + 1;
+                    backtrack = Some((bp, bv + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn attribute(e: &BytesStart, key: &[u8]) -> Result<Option<String>> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.as_ref() == key {
+            return Ok(Some(attr.unescape_value()?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the sheet-level `<autoFilter>` from the XML of a sheet, if any.
+/// Filters attached to tables live in table parts and are not considered.
+pub fn load_autofilter(sheet: impl Read) -> Result<Option<AutoFilter>> {
+    let mut reader = Reader::from_reader(BufReader::new(sheet));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut filter: Option<AutoFilter> = None;
+    let mut first_col = 0;
+    let mut current: Option<(usize, ColumnFilter)> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"autoFilter") => {
+                let Some(range) = attribute(&e, b"ref")? else {
+                    continue;
+                };
+                let (start, end) = range.split_once(':').unwrap_or((&range, &range));
+                first_col = column_index(start).unwrap_or(0);
+                filter = Some(AutoFilter {
+                    first_row: cell_row(start).unwrap_or(1),
+                    last_row: cell_row(end).unwrap_or(usize::MAX),
+                    columns: Vec::new(),
+                });
+            }
+            Event::Start(e) if filter.is_some() && eq_local(e.name().as_ref(), b"filterColumn") => {
+                let col_id: usize = attribute(&e, b"colId")?.and_then(|v| v.parse().ok()).unwrap_or(0);
+                current = Some((first_col + col_id, ColumnFilter::default()));
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"filterColumn") => {
+                if let (Some(filter), Some(column)) = (filter.as_mut(), current.take()) {
+                    filter.columns.push(column);
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if current.is_some() => {
+                let Some((_, column)) = current.as_mut() else {
+                    continue;
+                };
+                let name = e.name();
+                if eq_local(name.as_ref(), b"filters") {
+                    column.blank = attribute(&e, b"blank")?.is_some_and(|v| v == "1" || v == "true");
+                } else if eq_local(name.as_ref(), b"filter") {
+                    column.values.extend(attribute(&e, b"val")?);
+                } else if eq_local(name.as_ref(), b"customFilters") {
+                    column.custom_and = attribute(&e, b"and")?.is_some_and(|v| v == "1" || v == "true");
+                } else if eq_local(name.as_ref(), b"customFilter") {
+                    let operator = attribute(&e, b"operator")?.unwrap_or_else(|| "equal".to_string());
+                    column.custom.push((operator, attribute(&e, b"val")?.unwrap_or_default()));
+                }
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"autoFilter") => break,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(filter)
+}
@@ -0,0 +1,126 @@
+//! Throughput measurements for the `bench` subcommand.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zip::read::ZipArchive;
+
+use crate::archive::load_relationships;
+use crate::workbook::load_sheets;
+use crate::{convert, Options, Progress, Result};
+
+const SHARED_STRINGS_PART: &str = "xl/sharedStrings.xml";
+
+/// Converts every sheet of the workbook in `data` and reports how long zip
+/// inflation and XML parsing take on their own, the rest of the conversion,
+/// throughput and peak memory. The workbook is passed in memory so that disk
+/// reads are not measured; the output is discarded.
+pub fn print_bench<W: Write>(data: &[u8], options: &Options, out: &mut W) -> Result<()> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let rels = load_relationships(&mut archive)?;
+    let sheets = load_sheets(&mut archive, &rels, false)?;
+    let mut parts: Vec<String> = sheets.iter().map(|sheet| sheet.path.clone()).collect();
+    if archive.index_for_name(SHARED_STRINGS_PART).is_some() {
+        parts.push(SHARED_STRINGS_PART.to_string());
+    }
+
+    let mut xml_bytes = 0;
+    let mut inflate = Duration::ZERO;
+    let mut parse = Duration::ZERO;
+    for part in &parts {
+        let start = Instant::now();
+        let mut xml = Vec::new();
+        archive.by_name(part)?.read_to_end(&mut xml)?;
+        inflate += start.elapsed();
+        xml_bytes += xml.len() as u64;
+
+        let start = Instant::now();
+        parse_events(&xml)?;
+        parse += start.elapsed();
+    }
+
+    // Rows written per sheet, as last reported by the progress callback
+    let rows = Arc::new(Mutex::new(HashMap::new()));
+    let reported = Arc::clone(&rows);
+    let options = Options {
+        all_sheets: true,
+        progress: Some(Arc::new(move |progress: &Progress| {
+            if let Ok(mut rows) = reported.lock() {
+                rows.insert(progress.sheet.to_string(), progress.rows_written);
+            }
+        })),
+        ..options.clone()
+    };
+    let mut output = CountingSink::default();
+    let start = Instant::now();
+    convert(Cursor::new(data), &options, &mut output)?;
+    let total = start.elapsed();
+    let rows: u64 = rows.lock().map(|rows| rows.values().sum()).unwrap_or_default();
+
+    writeln!(out, "file: {} ({} sheets)", megabytes(data.len() as u64), sheets.len())?;
+    writeln!(out, "xml: {} (sheets and shared strings)", megabytes(xml_bytes))?;
+    writeln!(out, "rows: {}", rows)?;
+    writeln!(out, "output: {}", megabytes(output.bytes))?;
+    writeln!(out, "zip inflation: {}", timing(inflate, xml_bytes))?;
+    writeln!(out, "xml parsing: {}", timing(parse, xml_bytes))?;
+    writeln!(out, "cell conversion and csv writing: {:.3} s", total.saturating_sub(inflate + parse).as_secs_f64())?;
+    writeln!(out, "total: {}, {:.0} rows/s", timing(total, xml_bytes), rows as f64 / seconds(total))?;
+    match peak_rss() {
+        Some(bytes) => writeln!(out, "peak rss: {}", megabytes(bytes))?,
+        None => writeln!(out, "peak rss: unavailable on this platform")?,
+    }
+    Ok(())
+}
+
+/// Reads every XML event of `xml` without looking at it.
+fn parse_events(xml: &[u8]) -> Result<()> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    loop {
+        if let Event::Eof = reader.read_event_into(&mut buf)? {
+            return Ok(());
+        }
+        buf.clear();
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs_f64().max(f64::EPSILON)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1e6)
+}
+
+/// A duration and the throughput of `bytes` of XML in it.
+fn timing(duration: Duration, bytes: u64) -> String {
+    format!("{:.3} s ({:.1} MB/s of xml)", duration.as_secs_f64(), bytes as f64 / 1e6 / seconds(duration))
+}
+
+/// The most memory the process has held, from `/proc/self/status`.
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Discards the output, counting its bytes.
+#[derive(Default)]
+struct CountingSink {
+    bytes: u64,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,544 @@
+//! Step by step construction of conversion [`Options`].
+
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::sheet::CellRange;
+use crate::{
+    CellContext, ColumnKind, HyperlinkMode, InvalidUtf8, MaxColumnsAction, NewlineHandling, Options, OutputFormat, Phonetic, Progress,
+    QuoteStyle, Result, RichText, SqlDialect, Totals, TypeErrors, Warning, Xlsx2CsvError,
+};
+
+/// Builds the [`Options`] of a conversion, in the style of `csv::WriterBuilder`:
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io;
+///
+/// xlsx2csv::ConverterBuilder::new()
+///     .sheet("Data")
+///     .delimiter(b';')
+///     .date_format("%d.%m.%Y")
+///     .range("A1:D100")
+///     .convert(File::open("input.xlsx")?, io::stdout())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Settings left alone keep the command line defaults. Values that need
+/// parsing, such as the range, are checked by [`build`](Self::build).
+#[derive(Default)]
+pub struct ConverterBuilder {
+    options: Options,
+    range: Option<String>,
+}
+
+impl ConverterBuilder {
+    pub fn new() -> Self {
+        ConverterBuilder::default()
+    }
+
+    /// Parses the settings given as text and returns the resulting options.
+    pub fn build(&self) -> Result<Options> {
+        let mut options = self.options.clone();
+        if let Some(range) = &self.range {
+            options.range = Some(range.parse::<CellRange>().map_err(Xlsx2CsvError::InvalidOption)?);
+        }
+        Ok(options)
+    }
+
+    /// Builds the options and converts the workbook read from `reader`, see [`convert`](crate::convert).
+    pub fn convert<R: Read + Seek, W: Write>(&self, reader: R, writer: W) -> Result<()> {
+        crate::convert(reader, &self.build()?, writer)
+    }
+
+    /// Converts only the sheet with this name.
+    pub fn sheet(&mut self, name: impl Into<String>) -> &mut Self {
+        self.options.sheet_name = Some(name.into());
+        self
+    }
+
+    /// Converts only the sheet at this 1-based position.
+    pub fn sheet_index(&mut self, index: usize) -> &mut Self {
+        self.options.sheet_index = Some(index);
+        self
+    }
+
+    /// Converts every sheet instead of only the first.
+    pub fn all_sheets(&mut self, yes: bool) -> &mut Self {
+        self.options.all_sheets = yes;
+        self
+    }
+
+    /// With `all_sheets`, only converts sheets whose name matches this regex. Repeatable.
+    pub fn include_sheet_pattern(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.options.include_sheet_patterns.push(pattern.into());
+        self
+    }
+
+    /// With `all_sheets`, skips sheets whose name matches this regex. Repeatable.
+    pub fn exclude_sheet_pattern(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.options.exclude_sheet_patterns.push(pattern.into());
+        self
+    }
+
+    pub fn delimiter(&mut self, delimiter: u8) -> &mut Self {
+        self.options.delimiter = delimiter;
+        self
+    }
+
+    pub fn line_terminator(&mut self, terminator: u8) -> &mut Self {
+        self.options.line_terminator = terminator;
+        self
+    }
+
+    pub fn quote_style(&mut self, style: QuoteStyle) -> &mut Self {
+        self.options.quote_style = style;
+        self
+    }
+
+    /// Record written between sheets in csv format, `None` for no separator.
+    pub fn sheet_delimiter(&mut self, delimiter: Option<String>) -> &mut Self {
+        self.options.sheet_delimiter = delimiter;
+        self
+    }
+
+    pub fn format(&mut self, format: OutputFormat) -> &mut Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn newline_handling(&mut self, handling: NewlineHandling) -> &mut Self {
+        self.options.newline_handling = handling;
+        self
+    }
+
+    pub fn ignore_empty(&mut self, yes: bool) -> &mut Self {
+        self.options.ignore_empty = yes;
+        self
+    }
+
+    pub fn skip_empty_columns(&mut self, yes: bool) -> &mut Self {
+        self.options.skip_empty_columns = yes;
+        self
+    }
+
+    /// Stops a sheet after this many consecutive empty rows following data.
+    pub fn stop_at_blank_row(&mut self, rows: usize) -> &mut Self {
+        self.options.stop_at_blank_row = Some(rows);
+        self
+    }
+
+    /// Keeps only the first data row of each sheet and every `rows`th after it.
+    pub fn every(&mut self, rows: usize) -> &mut Self {
+        self.options.every = Some(rows).filter(|&rows| rows > 1);
+        self
+    }
+
+    pub fn max_columns(&mut self, columns: usize, action: MaxColumnsAction) -> &mut Self {
+        self.options.max_columns = columns;
+        self.options.max_columns_action = action;
+        self
+    }
+
+    /// Only converts the cells inside this range of each sheet, ex. `B2:D20`.
+    pub fn range(&mut self, range: impl Into<String>) -> &mut Self {
+        self.range = Some(range.into());
+        self
+    }
+
+    /// Emits formula text instead of the cached value for formula cells.
+    pub fn formulas(&mut self, yes: bool) -> &mut Self {
+        self.options.formulas = yes;
+        self
+    }
+
+    /// Adds the number format code, fill color and bold flag of each cell to
+    /// `--format long`, for sheets that encode meaning in formatting.
+    pub fn style_columns(&mut self, yes: bool) -> &mut Self {
+        self.options.style_columns = yes;
+        self
+    }
+
+    pub fn rich_text(&mut self, rich_text: RichText) -> &mut Self {
+        self.options.rich_text = rich_text;
+        self
+    }
+
+    pub fn phonetic(&mut self, phonetic: Phonetic) -> &mut Self {
+        self.options.phonetic = phonetic;
+        self
+    }
+
+    /// Writes typed JSON values, requires json or jsonl format.
+    pub fn infer_types(&mut self, yes: bool) -> &mut Self {
+        self.options.infer_types = yes;
+        self
+    }
+
+    /// Formats date cells with a strftime style pattern, ex. `%Y/%m/%d %H:%M`.
+    pub fn date_format(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.options.date_format = Some(pattern.into());
+        self
+    }
+
+    pub fn hyperlinks(&mut self, mode: HyperlinkMode) -> &mut Self {
+        self.options.hyperlinks = Some(mode);
+        self
+    }
+
+    pub fn append_comments(&mut self, yes: bool) -> &mut Self {
+        self.options.append_comments = yes;
+        self
+    }
+
+    pub fn respect_autofilter(&mut self, yes: bool) -> &mut Self {
+        self.options.respect_autofilter = yes;
+        self
+    }
+
+    pub fn report_external_links(&mut self, yes: bool) -> &mut Self {
+        self.options.report_external_links = yes;
+        self
+    }
+
+    /// Renames the header column `old` to `new` in the output. Repeatable.
+    /// Options naming columns keep using `old`.
+    pub fn rename(&mut self, old: impl Into<String>, new: impl Into<String>) -> &mut Self {
+        self.options.rename.push((old.into(), new.into()));
+        self
+    }
+
+    pub fn snake_case_headers(&mut self, yes: bool) -> &mut Self {
+        self.options.snake_case_headers = yes;
+        self
+    }
+
+    /// Only keeps data rows matching this expression, see `--where`.
+    pub fn where_expr(&mut self, expr: impl Into<String>) -> &mut Self {
+        self.options.where_expr = Some(expr.into());
+        self
+    }
+
+    /// Adds a `Col1,Col2=trim|upper` cleanup of the named columns, see `--map-column`. Repeatable.
+    pub fn map_column(&mut self, spec: impl Into<String>) -> &mut Self {
+        self.options.map_columns.push(spec.into());
+        self
+    }
+
+    /// Runs `transformer` on every data cell, for cleanups the `map_column`
+    /// steps do not cover. Repeatable, transformers run in the order added.
+    pub fn transform(&mut self, transformer: impl Fn(&CellContext, String) -> String + Send + Sync + 'static) -> &mut Self {
+        self.options.transformers.push((Vec::new(), Arc::new(transformer)));
+        self
+    }
+
+    /// Like [`transform`](Self::transform), limited to the column with this header name.
+    pub fn transform_column(
+        &mut self,
+        column: impl Into<String>,
+        transformer: impl Fn(&CellContext, String) -> String + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.options.transformers.push((vec![column.into()], Arc::new(transformer)));
+        self
+    }
+
+    /// Replaces whole cell values by the `column,from,to` rows of this csv file, see `--map-values`.
+    pub fn map_values(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.map_values = Some(path.into());
+        self
+    }
+
+    /// Adds a `[Cols::]regex=>replacement` rule, see `--replace`. Repeatable.
+    pub fn replace(&mut self, rule: impl Into<String>) -> &mut Self {
+        self.options.replace.push(rule.into());
+        self
+    }
+
+    pub fn dedup(&mut self, yes: bool) -> &mut Self {
+        self.options.dedup = yes;
+        self
+    }
+
+    /// Only compares these header columns when deduplicating, implies `dedup`.
+    pub fn dedup_key(&mut self, columns: Vec<String>) -> &mut Self {
+        self.options.dedup_key = columns;
+        self
+    }
+
+    /// Pads whole numbers formatted like `00000` to the width of their format
+    /// and keeps them, numbers formatted as text and values with leading
+    /// zeros from being typed as numbers.
+    pub fn preserve_leading_zeros(&mut self, yes: bool) -> &mut Self {
+        self.options.preserve_leading_zeros = yes;
+        self
+    }
+
+    /// Adds a `Column=type` rule coercing the data cells of the column to
+    /// string, int, float, bool or date, see `--types`. Repeatable.
+    pub fn column_type(&mut self, rule: impl Into<String>) -> &mut Self {
+        self.options.types.push(rule.into());
+        self
+    }
+
+    /// Adds a `Column=delimiter` rule writing a data row once per piece of
+    /// the column's cell, see `--explode`. Repeatable.
+    pub fn explode(&mut self, rule: impl Into<String>) -> &mut Self {
+        self.options.explode.push(rule.into());
+        self
+    }
+
+    /// Keeps only the columns whose sampled data cells are mostly of these kinds.
+    pub fn only_types(&mut self, kinds: Vec<ColumnKind>) -> &mut Self {
+        self.options.only_types = kinds;
+        self
+    }
+
+    /// What happens to cells that do not fit the type of their column.
+    pub fn type_errors(&mut self, action: TypeErrors) -> &mut Self {
+        self.options.type_errors = action;
+        self
+    }
+
+    pub fn add_row_number(&mut self, yes: bool) -> &mut Self {
+        self.options.add_row_number = yes;
+        self
+    }
+
+    pub fn add_source_ref(&mut self, yes: bool) -> &mut Self {
+        self.options.add_source_ref = yes;
+        self
+    }
+
+    /// Fails a sheet that yields no data rows besides its header.
+    pub fn fail_if_empty(&mut self, yes: bool) -> &mut Self {
+        self.options.fail_if_empty = yes;
+        self
+    }
+
+    /// Fails a sheet with fewer than `min` or more than `max` data rows.
+    pub fn expect_rows(&mut self, min: Option<u64>, max: Option<u64>) -> &mut Self {
+        self.options.expect_rows_min = min;
+        self.options.expect_rows_max = max;
+        self
+    }
+
+    /// Fails a sheet whose header row does not have this many columns.
+    pub fn expect_columns(&mut self, columns: usize) -> &mut Self {
+        self.options.expect_columns = Some(columns);
+        self
+    }
+
+    /// Fails a sheet whose header row, after renames, differs from this one.
+    pub fn expect_header(&mut self, header: Vec<String>) -> &mut Self {
+        self.options.expect_header = Some(header);
+        self
+    }
+
+    pub fn hyperlinks_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.hyperlinks_file = Some(path.into());
+        self
+    }
+
+    pub fn comments(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.comments = Some(path.into());
+        self
+    }
+
+    pub fn validations(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.validations = Some(path.into());
+        self
+    }
+
+    pub fn schema(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.schema = Some(path.into());
+        self
+    }
+
+    /// Writes a report of what the conversion dropped or changed, see [`Options::audit`].
+    pub fn audit(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.options.audit = Some(path.into());
+        self
+    }
+
+    pub fn ddl(&mut self, dialect: SqlDialect) -> &mut Self {
+        self.options.ddl = Some(dialect);
+        self
+    }
+
+    /// Converts up to `threads` sheets at once, 0 (the default) for one per available core.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// Flushes the output every `rows` rows, see [`Options::flush_every`].
+    pub fn flush_every(&mut self, rows: Option<u64>) -> &mut Self {
+        self.options.flush_every = rows.filter(|&rows| rows > 0);
+        self
+    }
+
+    /// Splits each sheet into chunks of rows converted in parallel, see [`Options::parallel_rows`].
+    pub fn parallel_rows(&mut self, yes: bool) -> &mut Self {
+        self.options.parallel_rows = yes;
+        self
+    }
+
+    /// Keeps large shared string tables and buffered parallel output in
+    /// temporary files to stay within about `bytes`, see [`Options::max_memory`].
+    pub fn max_memory(&mut self, bytes: u64) -> &mut Self {
+        self.options.max_memory = Some(bytes);
+        self
+    }
+
+    /// Sorts rows listed out of order or repeated by row number, see [`Options::reorder_rows`].
+    pub fn reorder_rows(&mut self, yes: bool) -> &mut Self {
+        self.options.reorder_rows = yes;
+        self
+    }
+
+    /// Fails on data the conversion would otherwise lose with a warning, see [`Options::strict`].
+    pub fn strict(&mut self, yes: bool) -> &mut Self {
+        self.options.strict = yes;
+        self
+    }
+
+    /// Fails on parts nesting elements deeper than `depth`, see [`Options::max_xml_depth`].
+    pub fn max_xml_depth(&mut self, depth: usize) -> &mut Self {
+        self.options.max_xml_depth = depth;
+        self
+    }
+
+    /// Accepts up to `entities` entity declarations in a DOCTYPE, see [`Options::max_entity_expansion`].
+    pub fn max_entity_expansion(&mut self, entities: usize) -> &mut Self {
+        self.options.max_entity_expansion = entities;
+        self
+    }
+
+    /// Fails on workbooks inflating to more than `bytes`, `None` for no limit, see
+    /// [`Options::max_uncompressed_size`].
+    pub fn max_uncompressed_size(&mut self, bytes: Option<u64>) -> &mut Self {
+        self.options.max_uncompressed_size = bytes;
+        self
+    }
+
+    /// Sets what happens to cell text that is not valid UTF-8, see [`Options::invalid_utf8`].
+    pub fn invalid_utf8(&mut self, mode: InvalidUtf8) -> &mut Self {
+        self.options.invalid_utf8 = mode;
+        self
+    }
+
+    /// Removes control characters from cell values, see [`Options::strip_control_chars`].
+    pub fn strip_control_chars(&mut self, yes: bool) -> &mut Self {
+        self.options.strip_control_chars = yes;
+        self
+    }
+
+    /// Stops the conversion with [`Xlsx2CsvError::Cancelled`] once `flag` is set.
+    pub fn cancel_flag(&mut self, flag: Arc<AtomicBool>) -> &mut Self {
+        self.options.cancel = Some(flag);
+        self
+    }
+
+    /// Calls `callback` every 1000 rows of a sheet and when the sheet is done.
+    pub fn progress(&mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> &mut Self {
+        self.options.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Counts the sheets, rows and cells written into `totals`.
+    pub fn totals(&mut self, totals: Arc<Totals>) -> &mut Self {
+        self.options.totals = Some(totals);
+        self
+    }
+
+    /// Receives the warnings about data converted only in part instead of
+    /// printing them to stderr.
+    pub fn warning(&mut self, callback: impl Fn(&Warning) + Send + Sync + 'static) -> &mut Self {
+        self.options.warning = Some(Arc::new(callback));
+        self
+    }
+}
+
+/// Settings read from the options object of the language bindings, named as
+/// the builder methods (JavaScript callers may use camelCase). Missing fields
+/// keep the defaults.
+#[cfg(any(feature = "wasm", feature = "ffi"))]
+#[derive(Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OptionsSpec {
+    sheet: Option<String>,
+    #[serde(alias = "sheetIndex")]
+    sheet_index: Option<usize>,
+    #[serde(alias = "allSheets")]
+    all_sheets: bool,
+    delimiter: Option<String>,
+    #[serde(alias = "sheetDelimiter")]
+    sheet_delimiter: Option<String>,
+    format: Option<String>,
+    #[serde(alias = "newlineHandling")]
+    newline_handling: Option<String>,
+    #[serde(alias = "ignoreEmpty")]
+    ignore_empty: bool,
+    #[serde(alias = "skipEmptyColumns")]
+    skip_empty_columns: bool,
+    formulas: bool,
+    #[serde(alias = "inferTypes")]
+    infer_types: bool,
+    #[serde(alias = "dateFormat")]
+    date_format: Option<String>,
+    range: Option<String>,
+    #[serde(rename = "where")]
+    where_expr: Option<String>,
+}
+
+#[cfg(any(feature = "wasm", feature = "ffi"))]
+impl OptionsSpec {
+    pub(crate) fn builder(self) -> Result<ConverterBuilder> {
+        use clap::ValueEnum;
+
+        fn value_enum<T: ValueEnum>(value: &str, option: &str) -> Result<T> {
+            T::from_str(value, true).map_err(|_| Xlsx2CsvError::InvalidOption(format!("Invalid {}: {}", option, value)))
+        }
+
+        let mut builder = ConverterBuilder::new();
+        builder
+            .all_sheets(self.all_sheets)
+            .ignore_empty(self.ignore_empty)
+            .skip_empty_columns(self.skip_empty_columns)
+            .formulas(self.formulas)
+            .infer_types(self.infer_types);
+        if let Some(delimiter) = self.sheet_delimiter {
+            builder.sheet_delimiter(Some(delimiter).filter(|d| !d.is_empty()));
+        }
+        if let Some(name) = self.sheet {
+            builder.sheet(name);
+        }
+        if let Some(index) = self.sheet_index {
+            builder.sheet_index(index);
+        }
+        if let Some(delimiter) = self.delimiter {
+            match delimiter.as_bytes() {
+                [byte] => builder.delimiter(*byte),
+                _ => return Err(Xlsx2CsvError::InvalidOption(format!("Invalid delimiter: {}", delimiter))),
+            };
+        }
+        if let Some(format) = self.format {
+            builder.format(value_enum::<OutputFormat>(&format, "format")?);
+        }
+        if let Some(handling) = self.newline_handling {
+            builder.newline_handling(value_enum::<NewlineHandling>(&handling, "newline_handling")?);
+        }
+        if let Some(pattern) = self.date_format {
+            builder.date_format(pattern);
+        }
+        if let Some(range) = self.range {
+            builder.range(range);
+        }
+        if let Some(expr) = self.where_expr {
+            builder.where_expr(expr);
+        }
+        Ok(builder)
+    }
+}
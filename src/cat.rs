@@ -0,0 +1,112 @@
+//! Concatenation of one sheet of many workbooks for the `cat` subcommand.
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::{Cell, Result, Workbook, Xlsx2CsvError};
+
+/// Writes the rows of `sheet` (a name or a 1-based number, the first sheet
+/// without it) of every workbook in `paths` as one csv. The header row is
+/// written once and must be the same in every workbook; with `add_filename`
+/// a `filename` column in front holds the path each row comes from. Returns
+/// the number of data rows written.
+pub fn print_cat<P: AsRef<Path>, W: Write>(paths: &[P], sheet: Option<&str>, add_filename: bool, out: W) -> Result<u64> {
+    // Every header is read before any row is written, so a mismatch leaves no
+    // partial output behind
+    let mut header: Option<Vec<String>> = None;
+    for path in paths {
+        let path = path.as_ref();
+        let Some(first) = first_row(path, sheet)? else {
+            continue;
+        };
+        match &header {
+            None => header = Some(first),
+            Some(expected) if *expected != first => {
+                return Err(Xlsx2CsvError::HeaderMismatch {
+                    file: path.display().to_string(),
+                    expected: expected.join(","),
+                    found: first.join(","),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    let mut rows_written = 0;
+    if let Some(mut header) = header {
+        if add_filename {
+            header.insert(0, "filename".to_string());
+        }
+        writer.write_record(&header)?;
+    }
+    for path in paths {
+        let path = path.as_ref();
+        let mut workbook = Workbook::open(path)?;
+        let name = workbook.resolve_sheet(sheet)?;
+        let filename = path.display().to_string();
+        // The header row was checked above
+        for row in workbook.sheet(&name)?.rows().skip(1) {
+            let mut record = record(row?);
+            if add_filename {
+                record.insert(0, filename.clone());
+            }
+            writer.write_record(&record)?;
+            rows_written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(rows_written)
+}
+
+/// The header row of `sheet` in the workbook at `path`, if the sheet has rows.
+fn first_row(path: &Path, sheet: Option<&str>) -> Result<Option<Vec<String>>> {
+    let mut workbook = Workbook::open(path)?;
+    let name = workbook.resolve_sheet(sheet)?;
+    let first = workbook.sheet(&name)?.rows().next().transpose()?;
+    Ok(first.map(record))
+}
+
+/// The text of each cell of `row`, without the empty cells it ends with.
+fn record(row: Vec<Cell>) -> Vec<String> {
+    let mut record: Vec<String> = row.iter().map(Cell::to_string).collect();
+    while record.last().is_some_and(String::is_empty) {
+        record.pop();
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::print_cat;
+    use crate::{fixtures, Xlsx2CsvError};
+    use std::path::{Path, PathBuf};
+
+    /// Writes a one-sheet workbook to `dir`.
+    fn workbook(dir: &Path, name: &str, rows: &[&[&str]]) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, fixtures::workbook(rows)).unwrap();
+        path
+    }
+
+    #[test]
+    fn rows_follow_one_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = workbook(dir.path(), "a.xlsx", &[&["id", "name"], &["1", "x"]]);
+        let b = workbook(dir.path(), "b.xlsx", &[&["id", "name"], &["2", "y"]]);
+        let mut out = Vec::new();
+        assert_eq!(print_cat(&[a, b], None, false, &mut out).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "id,name\n1,x\n2,y\n");
+    }
+
+    #[test]
+    fn header_mismatch_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = workbook(dir.path(), "a.xlsx", &[&["id", "name"], &["1", "x"]]);
+        let b = workbook(dir.path(), "b.xlsx", &[&["id", "title"], &["2", "y"]]);
+        let mut out = Vec::new();
+        let err = print_cat(&[a, b], None, false, &mut out).unwrap_err();
+        assert!(matches!(err, Xlsx2CsvError::HeaderMismatch { .. }));
+        assert!(out.is_empty());
+    }
+}
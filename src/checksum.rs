@@ -0,0 +1,80 @@
+//! Output digests for `--checksum`.
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::Result;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Passes writes through to `inner` while feeding them to a digest that
+/// outlives the writer, so it can be read once the output is complete.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Rc<RefCell<Sha256>>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> (Self, Rc<RefCell<Sha256>>) {
+        let hasher = Rc::new(RefCell::new(Sha256::new()));
+        let writer = HashingWriter {
+            inner,
+            hasher: Rc::clone(&hasher),
+        };
+        (writer, hasher)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.borrow_mut().update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn hex_digest(hasher: &RefCell<Sha256>) -> String {
+    to_hex(&hasher.borrow().clone().finalize())
+}
+
+pub fn file_digest(path: &str) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reports the digest of one output in `sha256sum` format: next to a file
+/// output as `<path>.sha256`, or on stderr for STDOUT (`-`) and uploads.
+pub fn report(algorithm: ChecksumAlgorithm, path: &str, digest: &str) -> Result<()> {
+    if path == "-" || path.starts_with("s3://") {
+        eprintln!("{}  {}", digest, path);
+        return Ok(());
+    }
+    let name = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+    fs::write(format!("{}.{}", path, algorithm.extension()), format!("{}  {}\n", digest, name))?;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Choosing the columns of a sheet `--only-types` keeps from a sample of its
+//! data rows.
+
+use clap::ValueEnum;
+use std::io::Read;
+
+use crate::events::{SheetEvent, SheetEventReader};
+use crate::sheet::{column_index, CellRange, CellType};
+use crate::styles::NumberFormat;
+use crate::Result;
+
+/// Data rows sampled from the start of each sheet.
+const SAMPLE_ROWS: usize = 1000;
+
+/// What the cells of a column hold, as stored in the workbook.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ColumnKind {
+    /// numbers without a date format
+    Number,
+    /// numbers with a date or time format
+    Date,
+    Bool,
+    /// shared, inline and formula strings, including numbers stored as text
+    String,
+}
+
+/// Samples the first data rows of the worksheet XML in `part`, after its
+/// header row and within `range`, and returns by 0-based column whether more
+/// than half of the column's non-empty cells hold one of `kinds`. Error
+/// cells and cells from column `max_columns` on are not counted.
+pub fn sample_columns(
+    part: impl Read,
+    kinds: &[ColumnKind],
+    number_formats: &[NumberFormat],
+    range: Option<&CellRange>,
+    max_columns: usize,
+) -> Result<Vec<bool>> {
+    // Cells of one of the kinds and non-empty cells, by column
+    let mut counts: Vec<(usize, usize)> = Vec::new();
+    let mut header = true;
+    let mut rows = 0;
+    let mut row_number = 0;
+    let mut row_cells = 0;
+    let mut next_col = 0;
+    for event in SheetEventReader::new(Box::new(part)) {
+        match event? {
+            SheetEvent::RowStart(number) => {
+                row_number = number as usize;
+                row_cells = 0;
+                next_col = 0;
+                if range.is_some_and(|range| row_number > range.rows.1) || rows == SAMPLE_ROWS {
+                    break;
+                }
+            }
+            SheetEvent::Cell { reference, cell_type, style, raw } => {
+                let col = reference.as_deref().and_then(column_index).unwrap_or(next_col);
+                next_col = col.saturating_add(1);
+                if header || raw.is_empty() || range.is_some_and(|range| row_number < range.rows.0) {
+                    row_cells += 1;
+                    continue;
+                }
+                // The conversion fails on or clips these cells
+                if col >= max_columns {
+                    continue;
+                }
+                let kind = match cell_type {
+                    CellType::Error => continue,
+                    CellType::SharedString | CellType::InlineStr | CellType::PlainStr => ColumnKind::String,
+                    CellType::Bool => ColumnKind::Bool,
+                    CellType::Number => match style.and_then(|style| number_formats.get(style)) {
+                        Some(NumberFormat::Date) => ColumnKind::Date,
+                        _ => ColumnKind::Number,
+                    },
+                };
+                if counts.len() <= col {
+                    counts.resize(col + 1, (0, 0));
+                }
+                counts[col].0 += usize::from(kinds.contains(&kind));
+                counts[col].1 += 1;
+                row_cells += 1;
+            }
+            SheetEvent::RowEnd => {
+                if range.is_some_and(|range| row_number < range.rows.0) || row_cells == 0 {
+                    continue;
+                }
+                // The first row with cells is the header row
+                if header {
+                    header = false;
+                } else {
+                    rows += 1;
+                }
+            }
+        }
+    }
+    Ok(counts.into_iter().map(|(matching, values)| matching * 2 > values).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_columns, ColumnKind};
+    use crate::fixtures;
+
+    #[test]
+    fn columns_holding_mostly_the_kinds_are_kept() {
+        let sheet = fixtures::sheet_xml(&[&["id", "name", "mixed"], &["1", "a", "1"], &["2", "b", "x"], &["3", "", "y"]]);
+        let kept = sample_columns(sheet.as_bytes(), &[ColumnKind::Number], &[], None, 16384).unwrap();
+        assert_eq!(kept, [true, false, false]);
+    }
+
+    #[test]
+    fn cells_past_the_column_limit_are_not_counted() {
+        let sheet = r#"<worksheet><sheetData>
+<row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row>
+<row r="2"><c r="A2"><v>1</v></c><c r="ZZZZZZZZZZZZZZZZ2"><v>9</v></c><c><v>10</v></c></row>
+</sheetData></worksheet>"#;
+        let kept = sample_columns(sheet.as_bytes(), &[ColumnKind::Number], &[], None, 10).unwrap();
+        assert_eq!(kept, [true]);
+    }
+}
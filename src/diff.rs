@@ -0,0 +1,153 @@
+//! Cell by cell comparison of two workbooks for the `diff` subcommand.
+
+use clap::ValueEnum;
+use std::io::{Read, Seek, Write};
+
+use crate::sheet::column_name;
+use crate::{Cell, Result, Rows, Workbook, Xlsx2CsvError};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum DiffFormat {
+    /// `sheet,ref,old,new` lines after a header
+    Csv,
+    /// an array of objects with sheet, ref, old and new
+    Json,
+}
+
+/// Writes the changed cells as they are found.
+struct ChangeWriter<W: Write> {
+    sink: Sink<W>,
+    count: usize,
+}
+
+enum Sink<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json(W),
+}
+
+impl<W: Write> ChangeWriter<W> {
+    fn new(format: DiffFormat, mut out: W) -> Result<Self> {
+        let sink = match format {
+            DiffFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(out);
+                csv.write_record(["sheet", "ref", "old", "new"])?;
+                Sink::Csv(Box::new(csv))
+            }
+            DiffFormat::Json => {
+                out.write_all(b"[")?;
+                Sink::Json(out)
+            }
+        };
+        Ok(ChangeWriter { sink, count: 0 })
+    }
+
+    fn write(&mut self, sheet: &str, cell: &str, old: &str, new: &str) -> Result<()> {
+        match &mut self.sink {
+            Sink::Csv(csv) => csv.write_record([sheet, cell, old, new])?,
+            Sink::Json(out) => {
+                let change = serde_json::json!({ "sheet": sheet, "ref": cell, "old": old, "new": new });
+                let separator = if self.count == 0 { "\n  " } else { ",\n  " };
+                write!(out, "{}{}", separator, change)?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<usize> {
+        match self.sink {
+            Sink::Csv(mut csv) => csv.flush()?,
+            Sink::Json(mut out) => {
+                out.write_all(if self.count == 0 { b"]\n" } else { b"\n]\n" })?;
+                out.flush()?;
+            }
+        }
+        Ok(self.count)
+    }
+}
+
+/// Compares the cell values of `sheet`, or of every sheet of either workbook
+/// without it, and writes the cells that differ. A sheet missing from one
+/// workbook compares as empty. Returns the number of changed cells.
+pub fn print_diff<A: Read + Seek, B: Read + Seek, W: Write>(
+    old: &mut Workbook<A>,
+    new: &mut Workbook<B>,
+    sheet: Option<&str>,
+    format: DiffFormat,
+    out: W,
+) -> Result<usize> {
+    let old_names: Vec<String> = old.sheet_names().map(str::to_string).collect();
+    let new_names: Vec<String> = new.sheet_names().map(str::to_string).collect();
+    let names: Vec<String> = match sheet {
+        Some(name) if old_names.iter().chain(&new_names).any(|known| known == name) => vec![name.to_string()],
+        Some(name) => {
+            return Err(Xlsx2CsvError::SheetNotFound {
+                requested: name.to_string(),
+                available: old_names,
+            })
+        }
+        None => {
+            let added = new_names.iter().filter(|name| !old_names.contains(name)).cloned();
+            old_names.iter().cloned().chain(added).collect()
+        }
+    };
+
+    let mut changes = ChangeWriter::new(format, out)?;
+    for name in &names {
+        let old_rows = if old_names.contains(name) { Some(old.sheet(name)?.rows()) } else { None };
+        let new_rows = if new_names.contains(name) { Some(new.sheet(name)?.rows()) } else { None };
+        diff_sheet(name, old_rows, new_rows, &mut changes)?;
+    }
+    changes.finish()
+}
+
+/// Walks the rows of both sheets in step by row number, so that only one
+/// row of each is held at a time.
+fn diff_sheet<W: Write>(
+    name: &str,
+    mut old: Option<Rows>,
+    mut new: Option<Rows>,
+    changes: &mut ChangeWriter<W>,
+) -> Result<()> {
+    let mut old_row = next_row(&mut old)?;
+    let mut new_row = next_row(&mut new)?;
+    loop {
+        let row_number = match (&old_row, &new_row) {
+            (None, None) => return Ok(()),
+            (Some((old_number, _)), Some((new_number, _))) => (*old_number).min(*new_number),
+            (Some((number, _)), None) | (None, Some((number, _))) => *number,
+        };
+        let old_cells = match &old_row {
+            Some((number, cells)) if *number == row_number => cells.as_slice(),
+            _ => &[],
+        };
+        let new_cells = match &new_row {
+            Some((number, cells)) if *number == row_number => cells.as_slice(),
+            _ => &[],
+        };
+        for col in 0..old_cells.len().max(new_cells.len()) {
+            let old_text = old_cells.get(col).map(Cell::to_string).unwrap_or_default();
+            let new_text = new_cells.get(col).map(Cell::to_string).unwrap_or_default();
+            if old_text != new_text {
+                changes.write(name, &format!("{}{}", column_name(col), row_number), &old_text, &new_text)?;
+            }
+        }
+        if old_row.as_ref().is_some_and(|(number, _)| *number == row_number) {
+            old_row = next_row(&mut old)?;
+        }
+        if new_row.as_ref().is_some_and(|(number, _)| *number == row_number) {
+            new_row = next_row(&mut new)?;
+        }
+    }
+}
+
+/// The next row of `rows` with its 1-based row number.
+fn next_row(rows: &mut Option<Rows>) -> Result<Option<(usize, Vec<Cell>)>> {
+    let Some(rows) = rows else {
+        return Ok(None);
+    };
+    match rows.next() {
+        Some(row) => Ok(Some((rows.row_number(), row?))),
+        None => Ok(None),
+    }
+}
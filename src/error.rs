@@ -0,0 +1,109 @@
+//! The error type of the library.
+
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that can make a conversion fail.
+#[derive(Debug, Error)]
+pub enum Xlsx2CsvError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The input is not a readable zip archive, or lacks a required part
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    /// A workbook part is not well-formed XML
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// A sheet pattern or `--replace` rule is not a valid regex
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+
+    /// The workbook file cannot be opened or read
+    #[error("Cannot read '{}': {source}", path.display())]
+    InputFile { path: PathBuf, source: io::Error },
+
+    #[error("No sheets found in workbook")]
+    NoSheets,
+    #[error("Cannot find sheet named '{requested}' (available: {})", available.join(", "))]
+    SheetNotFound { requested: String, available: Vec<String> },
+    #[error("Sheet ID {index} out of range (1-{count})")]
+    SheetIndexOutOfRange { index: usize, count: usize },
+    #[error("No sheets found matching criteria")]
+    NoMatchingSheets,
+    /// Two of the sheets to convert have the same name, ignoring case as Excel
+    /// does, or point to the same worksheet part
+    #[error("Sheets '{first}' and '{second}' {conflict}")]
+    ConflictingSheets { first: String, second: String, conflict: String },
+    /// A workbook given to `cat` has another header row than the first one
+    #[error("Header of '{file}' does not match: expected '{expected}', found '{found}'")]
+    HeaderMismatch { file: String, expected: String, found: String },
+    /// A column named by an option does not occur in the header row
+    #[error("Unknown column in {option}: '{name}'")]
+    UnknownColumn { option: String, name: String },
+    #[error("Cell {cell} exceeds the limit of {limit} columns (see --max-columns)")]
+    ColumnLimit { cell: String, limit: usize },
+    /// A cell refers to a string past the end of the shared string table,
+    /// only an error with [`Options::strict`](crate::Options::strict)
+    #[error("Cell {cell} refers to shared string {index}, past the end of the table")]
+    SharedStringOutOfRange { cell: String, index: usize },
+    /// A cell of an unknown type, or whose value does not fit its type; only
+    /// checked with [`Options::strict`](crate::Options::strict)
+    #[error("Cell {cell}: {reason}")]
+    InvalidCell { cell: String, reason: String },
+    /// Cell or shared string text that is not valid UTF-8, with
+    /// [`InvalidUtf8::Error`](crate::InvalidUtf8::Error)
+    #[error("{0} is not valid UTF-8 (see --invalid-utf8)")]
+    InvalidUtf8(String),
+    /// A part nests elements deeper than [`Options::max_xml_depth`](crate::Options::max_xml_depth)
+    /// or declares entities, see [`Options::max_entity_expansion`](crate::Options::max_entity_expansion)
+    #[error("XML limit exceeded: {0}")]
+    XmlLimit(String),
+    /// The parts of the archive inflate to more than
+    /// [`Options::max_uncompressed_size`](crate::Options::max_uncompressed_size)
+    #[error("Workbook inflates to {size} bytes, more than the limit of {limit} (see --max-uncompressed-size)")]
+    UncompressedSizeLimit { size: u64, limit: u64 },
+    /// A relationship id missing from the `.rels` part of `part`; only an
+    /// error with [`Options::strict`](crate::Options::strict)
+    #[error("Relationship '{id}' of {part} is not defined")]
+    UnresolvedRelationship { part: String, id: String },
+    /// A `--where` expression that does not parse
+    #[error("{0}")]
+    Expression(String),
+    /// `validate` found problems in the workbook, printed before
+    #[error("Validation found {0} problem(s)")]
+    Invalid(usize),
+    /// A sheet yielded no rows besides its header, with
+    /// [`Options::fail_if_empty`](crate::Options::fail_if_empty)
+    #[error("No data rows (--fail-if-empty)")]
+    NoDataRows,
+    /// The converted data does not meet an expectation such as `--expect-rows-min`
+    #[error("{0}")]
+    Expectation(String),
+    /// A `query` statement that does not parse or does not fit its sheet
+    #[error("{0}")]
+    Query(String),
+    /// An option value that does not parse or a combination of options that does not work together
+    #[error("{0}")]
+    InvalidOption(String),
+    /// An output file of the command line tool exists and `--force` was not given
+    #[error("Output file '{}' already exists, use --force to overwrite it", .0.display())]
+    OutputExists(PathBuf),
+    /// The [`Options::cancel`](crate::Options::cancel) flag was raised
+    #[error("Conversion cancelled")]
+    Cancelled,
+    /// Any error while converting a sheet, with the sheet's name
+    #[error("Failed to read sheet '{name}': {source}")]
+    Sheet { name: String, source: Box<Xlsx2CsvError> },
+    /// An error returned by user code, ex. a [`Visitor`](crate::Visitor) callback
+    #[error(transparent)]
+    Custom(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<quick_xml::events::attributes::AttrError> for Xlsx2CsvError {
+    fn from(err: quick_xml::events::attributes::AttrError) -> Self {
+        Xlsx2CsvError::Xml(err.into())
+    }
+}
@@ -0,0 +1,167 @@
+//! Low-level reading of a worksheet as raw parse events, see [`SheetEventReader`].
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{BufReader, Read};
+
+use crate::archive::eq_local;
+use crate::richtext::{Phonetic, RichText, StringBuilder};
+use crate::sheet::{row_number, CellType};
+use crate::Result;
+
+/// An event of [`SheetEventReader`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SheetEvent {
+    /// A `<row>` begins, with its 1-based row number
+    RowStart(u32),
+    /// A cell of the current row, as stored in the XML
+    Cell {
+        /// the `r` attribute, ex. `C5`, absent in some generated files
+        reference: Option<String>,
+        cell_type: CellType,
+        /// index into the cell formats, see `xl/styles.xml`
+        style: Option<usize>,
+        /// the text of `<v>` (a shared string index for [`CellType::SharedString`],
+        /// a serial number for dates), or the text of `<is>` for inline strings
+        raw: String,
+    },
+    /// The current `<row>` ends
+    RowEnd,
+}
+
+/// Iterator over the rows and cells of a sheet without any interpretation:
+/// shared strings are not resolved, numbers not parsed and empty cells not
+/// filled in, for consumers that build their own representation such as a
+/// sparse matrix. Created by [`Sheet::events`](crate::Sheet::events).
+///
+/// ```no_run
+/// use xlsx2csv::{SheetEvent, Workbook};
+///
+/// let mut workbook = Workbook::open("input.xlsx")?;
+/// let mut cells = 0;
+/// for event in workbook.sheet("Data")?.events() {
+///     if let SheetEvent::Cell { .. } = event? {
+///         cells += 1;
+///     }
+/// }
+/// println!("{} cells", cells);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct SheetEventReader<'a> {
+    reader: Reader<BufReader<Box<dyn Read + 'a>>>,
+    buf: Vec<u8>,
+    row_number: u32,
+    /// the `RowEnd` of a self-closing `<row/>`
+    pending: Option<SheetEvent>,
+    done: bool,
+}
+
+impl<'a> SheetEventReader<'a> {
+    pub(crate) fn new(file: Box<dyn Read + 'a>) -> Self {
+        let mut reader = Reader::from_reader(BufReader::new(file));
+        reader.trim_text(false);
+        SheetEventReader {
+            reader,
+            buf: Vec::new(),
+            row_number: 0,
+            pending: None,
+            done: false,
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<SheetEvent>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+        let mut reference = None;
+        let mut cell_type = CellType::Number;
+        let mut style = None;
+        let mut raw = String::new();
+        let mut inline = StringBuilder::new(RichText::Plain, Phonetic::Strip);
+        let mut in_value = false;
+        let mut in_inline = false;
+        let mut in_phonetic = false;
+
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
+                    self.row_number = row_number(&e)?.map_or(self.row_number + 1, |n| n as u32);
+                    return Ok(Some(SheetEvent::RowStart(self.row_number)));
+                }
+                Event::Empty(e) if eq_local(e.name().as_ref(), b"row") => {
+                    self.row_number = row_number(&e)?.map_or(self.row_number + 1, |n| n as u32);
+                    self.pending = Some(SheetEvent::RowEnd);
+                    return Ok(Some(SheetEvent::RowStart(self.row_number)));
+                }
+                Event::End(e) if eq_local(e.name().as_ref(), b"row") => return Ok(Some(SheetEvent::RowEnd)),
+                Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
+                    (reference, cell_type, style) = cell_attributes(&e)?;
+                }
+                Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                    let (reference, cell_type, style) = cell_attributes(&e)?;
+                    return Ok(Some(SheetEvent::Cell {
+                        reference,
+                        cell_type,
+                        style,
+                        raw: String::new(),
+                    }));
+                }
+                Event::Start(e) if eq_local(e.name().as_ref(), b"v") => in_value = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"v") => in_value = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"is") => in_inline = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"is") => in_inline = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = false,
+                Event::Text(t) if in_inline && !in_phonetic => inline.push_str(&t.unescape()?),
+                Event::Text(t) if in_value => raw.push_str(&t.unescape()?),
+                Event::CData(t) if in_value => raw.push_str(&String::from_utf8_lossy(t.as_ref())),
+                Event::End(e) if eq_local(e.name().as_ref(), b"c") => {
+                    if cell_type == CellType::InlineStr {
+                        raw = inline.finish();
+                    }
+                    return Ok(Some(SheetEvent::Cell {
+                        reference,
+                        cell_type,
+                        style,
+                        raw,
+                    }));
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads the reference, type and style index of a `<c>` element.
+fn cell_attributes(e: &BytesStart) -> Result<(Option<String>, CellType, Option<usize>)> {
+    let mut reference = None;
+    let mut cell_type = CellType::Number;
+    let mut style = None;
+    for attr in e.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"r" => reference = Some(attr.unescape_value()?.into_owned()),
+            b"t" => cell_type = CellType::from_attr(&attr.unescape_value()?),
+            b"s" => style = attr.unescape_value()?.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Ok((reference, cell_type, style))
+}
+
+impl Iterator for SheetEventReader<'_> {
+    type Item = Result<SheetEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_event();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
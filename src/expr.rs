@@ -0,0 +1,451 @@
+//! A tiny expression language used by `--where` to filter rows.
+//!
+//! ```text
+//! expr    := or
+//! or      := and ( "||" and )*
+//! and     := unary ( "&&" unary )*
+//! unary   := "!" unary | cmp
+//! cmp     := primary ( ("==" | "!=" | "<" | "<=" | ">" | ">=") primary )?
+//! primary := string | number | "true" | "false"
+//!          | ("col" | "num") "(" (string | number) ")" | "(" expr ")"
+//! ```
+//!
+//! `col` yields the cell text and `num` the cell parsed as a number. Columns
+//! are referenced by header name or by 1-based position.
+
+use crate::{Result, Xlsx2CsvError};
+
+#[derive(Clone, Debug)]
+enum Column {
+    Name(String),
+    Position(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Col(Column),
+    NumCol(Column),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Cmp(CmpOp, Box<Node>, Box<Node>),
+}
+
+enum Value<'a> {
+    Str(&'a str),
+    Num(f64),
+    Bool(bool),
+}
+
+/// A parsed `--where` expression.
+#[derive(Clone, Debug)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Self> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(Xlsx2CsvError::Expression(format!("Unexpected token in expression: {:?}", parser.tokens[parser.pos])));
+        }
+        Ok(Expr { root })
+    }
+
+    /// Resolves column names against `header`, producing an expression that
+    /// only refers to column positions.
+    pub fn bind(&self, header: &[String]) -> Result<Self> {
+        Ok(Expr {
+            root: bind_node(&self.root, header)?,
+        })
+    }
+
+    /// Evaluates a bound expression against `row`.
+    pub fn matches(&self, row: &[String]) -> bool {
+        truthy(&eval(&self.root, row))
+    }
+}
+
+fn bind_node(node: &Node, header: &[String]) -> Result<Node> {
+    let column = |col: &Column| -> Result<Column> {
+        match col {
+            Column::Name(name) => header
+                .iter()
+                .position(|h| h == name)
+                .map(Column::Position)
+                .ok_or_else(|| Xlsx2CsvError::UnknownColumn {
+                    option: "expression".to_string(),
+                    name: name.clone(),
+                }),
+            Column::Position(idx) => Ok(Column::Position(*idx)),
+        }
+    };
+    Ok(match node {
+        Node::Col(col) => Node::Col(column(col)?),
+        Node::NumCol(col) => Node::NumCol(column(col)?),
+        Node::Not(inner) => Node::Not(Box::new(bind_node(inner, header)?)),
+        Node::And(l, r) => Node::And(Box::new(bind_node(l, header)?), Box::new(bind_node(r, header)?)),
+        Node::Or(l, r) => Node::Or(Box::new(bind_node(l, header)?), Box::new(bind_node(r, header)?)),
+        Node::Cmp(op, l, r) => Node::Cmp(*op, Box::new(bind_node(l, header)?), Box::new(bind_node(r, header)?)),
+        other => other.clone(),
+    })
+}
+
+fn cell<'a>(row: &'a [String], col: &Column) -> &'a str {
+    match col {
+        Column::Position(idx) => row.get(*idx).map(String::as_str).unwrap_or(""),
+        Column::Name(_) => "",
+    }
+}
+
+fn eval<'a>(node: &'a Node, row: &'a [String]) -> Value<'a> {
+    match node {
+        Node::Str(s) => Value::Str(s),
+        Node::Num(n) => Value::Num(*n),
+        Node::Bool(b) => Value::Bool(*b),
+        Node::Col(col) => Value::Str(cell(row, col)),
+        Node::NumCol(col) => Value::Num(cell(row, col).trim().parse().unwrap_or(f64::NAN)),
+        Node::Not(inner) => Value::Bool(!truthy(&eval(inner, row))),
+        Node::And(l, r) => Value::Bool(truthy(&eval(l, row)) && truthy(&eval(r, row))),
+        Node::Or(l, r) => Value::Bool(truthy(&eval(l, row)) || truthy(&eval(r, row))),
+        Node::Cmp(op, l, r) => Value::Bool(compare(*op, &eval(l, row), &eval(r, row))),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Str(s) => !s.is_empty(),
+        Value::Num(n) => *n != 0.0 && !n.is_nan(),
+        Value::Bool(b) => *b,
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Str(s) => s.trim().parse().ok(),
+        Value::Num(n) => Some(*n),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+    }
+}
+
+fn compare(op: CmpOp, left: &Value, right: &Value) -> bool {
+    use std::cmp::Ordering;
+
+    let ordering = match (left, right) {
+        (Value::Str(l), Value::Str(r)) => Some(l.cmp(r)),
+        (Value::Bool(l), Value::Bool(r)) => Some(l.cmp(r)),
+        _ => match (as_number(left), as_number(right)) {
+            (Some(l), Some(r)) => l.partial_cmp(&r),
+            _ => None,
+        },
+    };
+    match ordering {
+        Some(ord) => match op {
+            CmpOp::Eq => ord == Ordering::Equal,
+            CmpOp::Ne => ord != Ordering::Equal,
+            CmpOp::Lt => ord == Ordering::Less,
+            CmpOp::Le => ord != Ordering::Greater,
+            CmpOp::Gt => ord == Ordering::Greater,
+            CmpOp::Ge => ord != Ordering::Less,
+        },
+        None => op == CmpOp::Ne,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Str(String),
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    const OPS: [&str; 9] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!"];
+
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RParen);
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(Xlsx2CsvError::Expression("Unterminated string in expression".to_string())),
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, other)) => value.push(other),
+                    None => return Err(Xlsx2CsvError::Expression("Unterminated string in expression".to_string())),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if c.is_ascii_digit() || c == '.' || c == '-' {
+            let mut end = start + c.len_utf8();
+            chars.next();
+            while let Some(&(i, d)) = chars.peek() {
+                if d.is_ascii_digit() || d == '.' || d == 'e' || d == 'E' {
+                    end = i + d.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &src[start..end];
+            let num = text
+                .parse()
+                .map_err(|_| Xlsx2CsvError::Expression(format!("Invalid number in expression: {}", text)))?;
+            tokens.push(Token::Num(num));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&(_, d)) = chars.peek() {
+                if d.is_alphanumeric() || d == '_' {
+                    ident.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(ident));
+        } else {
+            let rest = &src[start..];
+            let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) else {
+                return Err(Xlsx2CsvError::Expression(format!("Unexpected character in expression: '{}'", c)));
+            };
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(Xlsx2CsvError::Expression(format!("Expected {:?} in expression, found {:?}", expected, other))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        let mut node = self.and()?;
+        while self.eat_op("||") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        while self.eat_op("&&") {
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.eat_op("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Node> {
+        let left = self.primary()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CmpOp::Eq,
+            Some(Token::Op("!=")) => CmpOp::Ne,
+            Some(Token::Op("<")) => CmpOp::Lt,
+            Some(Token::Op("<=")) => CmpOp::Le,
+            Some(Token::Op(">")) => CmpOp::Gt,
+            Some(Token::Op(">=")) => CmpOp::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.primary()?;
+        Ok(Node::Cmp(op, Box::new(left), Box::new(right)))
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Node::Str(s)),
+            Some(Token::Num(n)) => Ok(Node::Num(n)),
+            Some(Token::LParen) => {
+                let node = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Node::Bool(true)),
+                "false" => Ok(Node::Bool(false)),
+                "col" | "num" => {
+                    self.expect(Token::LParen)?;
+                    let column = match self.next() {
+                        Some(Token::Str(name)) => Column::Name(name),
+                        Some(Token::Num(n)) if n >= 1.0 && n.fract() == 0.0 => Column::Position(n as usize - 1),
+                        other => return Err(Xlsx2CsvError::Expression(format!("Invalid column reference in expression: {:?}", other))),
+                    };
+                    self.expect(Token::RParen)?;
+                    Ok(if ident == "col" {
+                        Node::Col(column)
+                    } else {
+                        Node::NumCol(column)
+                    })
+                }
+                _ => Err(Xlsx2CsvError::Expression(format!("Unknown function in expression: {}", ident))),
+            },
+            other => Err(Xlsx2CsvError::Expression(format!("Unexpected token in expression: {:?}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<String> {
+        ["name", "qty", "price"].map(String::from).to_vec()
+    }
+
+    fn matches(src: &str, row: &[&str]) -> bool {
+        let row: Vec<String> = row.iter().map(|cell| cell.to_string()).collect();
+        Expr::parse(src).unwrap().bind(&header()).unwrap().matches(&row)
+    }
+
+    fn parse_error(src: &str) -> String {
+        match Expr::parse(src) {
+            Err(Xlsx2CsvError::Expression(message)) => message,
+            other => panic!("expected an expression error for {:?}, got {:?}", src, other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches("true || false && false", &[]));
+        assert!(!matches("(true || false) && false", &[]));
+        assert!(matches("false && true || true", &[]));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_or() {
+        assert!(matches("!false && true", &[]));
+        assert!(!matches("!true || false", &[]));
+        assert!(matches("!(col('name') == 'a') || false", &["b"]));
+        assert!(matches("!!true", &[]));
+    }
+
+    #[test]
+    fn comparisons_bind_tighter_than_logic() {
+        let row = ["widget", "12", "2.50"];
+        assert!(matches("num('qty') > 10 && num('price') < 3", &row));
+        assert!(!matches("num('qty') > 20 || col('name') == 'gadget'", &row));
+    }
+
+    #[test]
+    fn strings_compare_as_text() {
+        assert!(matches("col('qty') < '9'", &["", "10"]));
+        assert!(matches("col('name') >= 'apple'", &["banana"]));
+        assert!(!matches("col('name') == 'Banana'", &["banana"]));
+    }
+
+    #[test]
+    fn strings_compare_with_numbers_as_numbers() {
+        assert!(matches("col('qty') == 10", &["", "10.0"]));
+        assert!(matches("col('qty') > 9", &["", "10"]));
+        assert!(matches("num('qty') >= 1e1", &["", " 10 "]));
+        assert!(matches("num(3) < -1", &["", "", "-2.5"]));
+    }
+
+    #[test]
+    fn non_numeric_cells_only_differ_from_numbers() {
+        assert!(!matches("col('name') == 1", &["x"]));
+        assert!(!matches("col('name') < 1", &["x"]));
+        assert!(matches("col('name') != 1", &["x"]));
+        assert!(!matches("num('qty') > 0", &["", ""]));
+    }
+
+    #[test]
+    fn columns_by_position_and_missing_cells() {
+        assert!(matches("col(2) == '5'", &["a", "5"]));
+        assert!(matches("col(3) == ''", &["a"]));
+        assert!(!matches("col(3)", &["a"]));
+        assert!(matches("col(1)", &["a"]));
+    }
+
+    #[test]
+    fn unknown_columns_fail_to_bind() {
+        let expr = Expr::parse("col('missing') == 1").unwrap();
+        match expr.bind(&header()) {
+            Err(Xlsx2CsvError::UnknownColumn { name, .. }) => assert_eq!(name, "missing"),
+            other => panic!("expected an unknown column, got {:?}", other.map(|_| ())),
+        }
+        // Positions past the header are empty cells, not errors
+        assert!(Expr::parse("num(9) == 1").unwrap().bind(&header()).is_ok());
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        assert!(parse_error("col('name) == 'a'").contains("Unterminated string"));
+        assert!(parse_error("(true && false").contains("Expected RParen"));
+        assert!(parse_error("upper('name')").contains("Unknown function"));
+        assert!(parse_error("true false").contains("Unexpected token"));
+        assert!(parse_error("col(0)").contains("Invalid column reference"));
+        assert!(parse_error("col(1.5)").contains("Invalid column reference"));
+        assert!(parse_error("num('qty') > 1 # 2").contains("Unexpected character"));
+        assert!(parse_error("1.2.3 == 1").contains("Invalid number"));
+        assert!(parse_error("col('name') ==").contains("Unexpected token"));
+        assert!(parse_error("").contains("Unexpected token"));
+    }
+}
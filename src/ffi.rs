@@ -0,0 +1,89 @@
+//! C API, behind the `ffi` feature. The header `include/xlsx2csv.h` is
+//! regenerated by the build with cbindgen.
+//!
+//! ```c
+//! #include "xlsx2csv.h"
+//!
+//! if (xlsx2csv_convert("input.xlsx", "{\"sheet\": \"Data\", \"delimiter\": \";\"}", "out.csv") != XLSX2CSV_OK) {
+//!     fprintf(stderr, "%s\n", xlsx2csv_last_error());
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+use crate::builder::OptionsSpec;
+use crate::Result;
+
+/// The conversion succeeded.
+pub const XLSX2CSV_OK: c_int = 0;
+/// A path or the options are missing, not UTF-8 or not valid JSON.
+pub const XLSX2CSV_INVALID_ARGUMENT: c_int = 1;
+/// The conversion failed, see `xlsx2csv_last_error`.
+pub const XLSX2CSV_FAILED: c_int = 2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Converts the workbook at `input_path` into `output_path` with the options
+/// given as a JSON object (ex. `{"sheet": "Data", "format": "jsonl"}`), which
+/// may be NULL for the defaults. Returns `XLSX2CSV_OK` or an error code, with
+/// the message available from `xlsx2csv_last_error`.
+///
+/// # Safety
+///
+/// The arguments must be NULL or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn xlsx2csv_convert(
+    input_path: *const c_char,
+    options_json: *const c_char,
+    output_path: *const c_char,
+) -> c_int {
+    let (input, output) = match (c_str(input_path), c_str(output_path)) {
+        (Some(Ok(input)), Some(Ok(output))) => (input, output),
+        _ => return fail(XLSX2CSV_INVALID_ARGUMENT, "input and output paths must be UTF-8 strings"),
+    };
+    let options = match c_str(options_json) {
+        None => OptionsSpec::default(),
+        Some(Ok(json)) => match serde_json::from_str(json) {
+            Ok(options) => options,
+            Err(err) => return fail(XLSX2CSV_INVALID_ARGUMENT, &format!("Invalid options: {}", err)),
+        },
+        Some(Err(_)) => return fail(XLSX2CSV_INVALID_ARGUMENT, "options must be a UTF-8 string"),
+    };
+    match convert_file(input, options, output) {
+        Ok(()) => {
+            LAST_ERROR.with(|last| last.borrow_mut().take());
+            XLSX2CSV_OK
+        }
+        Err(err) => fail(XLSX2CSV_FAILED, &err.to_string()),
+    }
+}
+
+/// The message of the last failed call on this thread, or NULL. The string
+/// stays valid until the next `xlsx2csv_convert` call on the same thread.
+#[no_mangle]
+pub extern "C" fn xlsx2csv_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |msg| msg.as_ptr()))
+}
+
+unsafe fn c_str<'a>(ptr: *const c_char) -> Option<std::result::Result<&'a str, std::str::Utf8Error>> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str())
+}
+
+fn fail(code: c_int, message: &str) -> c_int {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
+
+fn convert_file(input: &str, options: OptionsSpec, output: &str) -> Result<()> {
+    let options = options.builder()?.build()?;
+    let mut out = BufWriter::new(File::create(output)?);
+    crate::convert(BufReader::new(File::open(input)?), &options, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Small in-memory workbooks for the unit tests.
+
+use std::io::{Cursor, Write};
+
+/// The worksheet XML of `rows`, starting at A1. Cells that parse as numbers
+/// are written as numbers, other text as inline strings, and empty cells are
+/// left out.
+pub fn sheet_xml(rows: &[&[&str]]) -> String {
+    let mut sheet = String::from(r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#);
+    for (r, row) in rows.iter().enumerate() {
+        sheet.push_str(&format!(r#"<row r="{}">"#, r + 1));
+        for (c, value) in row.iter().enumerate() {
+            let reference = format!("{}{}", crate::sheet::column_name(c), r + 1);
+            if value.is_empty() {
+                continue;
+            } else if value.parse::<f64>().is_ok() {
+                sheet.push_str(&format!(r#"<c r="{}"><v>{}</v></c>"#, reference, value));
+            } else {
+                sheet.push_str(&format!(r#"<c r="{}" t="inlineStr"><is><t>{}</t></is></c>"#, reference, value));
+            }
+        }
+        sheet.push_str("</row>");
+    }
+    sheet.push_str("</sheetData></worksheet>");
+    sheet
+}
+
+/// An xlsx file holding one sheet named `Sales` with the given worksheet XML.
+pub fn xlsx(sheet: &str) -> Vec<u8> {
+    let parts = [
+        (
+            "[Content_Types].xml",
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="xml" ContentType="application/xml"/></Types>"#,
+        ),
+        (
+            "xl/workbook.xml",
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Sales" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+        ),
+        (
+            "xl/_rels/workbook.xml.rels",
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#,
+        ),
+        ("xl/worksheets/sheet1.xml", sheet),
+    ];
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (part, content) in parts {
+        zip.start_file(part, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// An xlsx file holding `rows` in one sheet named `Sales`.
+pub fn workbook(rows: &[&[&str]]) -> Vec<u8> {
+    xlsx(&sheet_xml(rows))
+}
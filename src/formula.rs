@@ -0,0 +1,147 @@
+//! Helpers for emitting formula text with `--formulas`.
+
+/// Shifts the relative A1 references in `formula` by the given number of rows
+/// and columns, the way Excel fills a shared formula from its master cell.
+/// Absolute parts (`$A`, `$1`), string literals and quoted sheet names are
+/// left untouched.
+pub fn shift_references(formula: &str, row_delta: isize, col_delta: isize) -> String {
+    let bytes = formula.as_bytes();
+    let mut out = String::with_capacity(formula.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'"' || c == b'\'' {
+            // Copy literals verbatim, doubled quotes are escapes
+            let start = i;
+            i += 1;
+            while i < bytes.len() {
+                if bytes[i] == c {
+                    if bytes.get(i + 1) == Some(&c) {
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                i += 1;
+            }
+            out.push_str(&formula[start..i]);
+        } else if c.is_ascii_alphabetic() || c == b'$' || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'$' | b'_' | b'.')) {
+                i += 1;
+            }
+            let token = &formula[start..i];
+            let is_call = bytes.get(i) == Some(&b'(');
+            let is_sheet = bytes.get(i) == Some(&b'!');
+            match (is_call || is_sheet, parse_reference(token)) {
+                (false, Some(reference)) => out.push_str(&reference.shift(row_delta, col_delta)),
+                _ => out.push_str(token),
+            }
+        } else {
+            let ch = formula[i..].chars().next().unwrap_or_default();
+            out.push(ch);
+            i += ch.len_utf8().max(1);
+        }
+    }
+
+    out
+}
+
+struct Reference {
+    col_absolute: bool,
+    col: usize,
+    row_absolute: bool,
+    row: usize,
+}
+
+impl Reference {
+    fn shift(&self, row_delta: isize, col_delta: isize) -> String {
+        let col = if self.col_absolute {
+            self.col
+        } else {
+            self.col.saturating_add_signed(col_delta)
+        };
+        let row = if self.row_absolute {
+            self.row
+        } else {
+            self.row.saturating_add_signed(row_delta).max(1)
+        };
+        format!(
+            "{}{}{}{}",
+            if self.col_absolute { "$" } else { "" },
+            crate::sheet::column_name(col),
+            if self.row_absolute { "$" } else { "" },
+            row
+        )
+    }
+}
+
+/// Parses a single-cell reference such as `B7`, `$B7` or `B$7`.
+fn parse_reference(token: &str) -> Option<Reference> {
+    let (col_absolute, rest) = match token.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let letters = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+    if letters == 0 || letters > 3 {
+        return None;
+    }
+    let (col_part, rest) = rest.split_at(letters);
+    let (row_absolute, digits) = match rest.strip_prefix('$') {
+        Some(digits) => (true, digits),
+        None => (false, rest),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(Reference {
+        col_absolute,
+        col: crate::sheet::column_index(col_part)?,
+        row_absolute,
+        row: digits.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shift_references;
+
+    #[test]
+    fn relative_references_follow_the_offset() {
+        assert_eq!(shift_references("A1+B2", 2, 1), "B3+C4");
+        assert_eq!(shift_references("SUM(A1,Z9)", 0, 1), "SUM(B1,AA9)");
+        assert_eq!(shift_references("A1*2", 0, 0), "A1*2");
+    }
+
+    #[test]
+    fn anchored_parts_stay_in_place() {
+        assert_eq!(shift_references("$A$1+$A1+A$1+A1", 3, 2), "$A$1+$A4+C$1+C4");
+    }
+
+    #[test]
+    fn both_ends_of_a_range_move() {
+        assert_eq!(shift_references("SUM(A1:B10)", 1, 0), "SUM(A2:B11)");
+        assert_eq!(shift_references("SUM($A$1:B10)", 1, 1), "SUM($A$1:C11)");
+    }
+
+    #[test]
+    fn sheet_qualified_references_keep_their_sheet() {
+        assert_eq!(shift_references("Sheet2!A1+1", 1, 0), "Sheet2!A2+1");
+        assert_eq!(shift_references("'My Sheet'!B2*'Q1 A1'!C3", 1, 1), "'My Sheet'!C3*'Q1 A1'!D4");
+    }
+
+    #[test]
+    fn strings_and_functions_are_copied_verbatim() {
+        assert_eq!(shift_references("IF(A1=\"B2\",\"say \"\"C3\"\"\",A1)", 1, 0), "IF(A2=\"B2\",\"say \"\"C3\"\"\",A2)");
+        assert_eq!(shift_references("LOG10(A1)", 1, 0), "LOG10(A2)");
+        assert_eq!(shift_references("ABCD1+TRUE", 1, 1), "ABCD1+TRUE");
+    }
+
+    #[test]
+    fn negative_offsets_stop_at_the_first_row_and_column() {
+        assert_eq!(shift_references("C5-B4", -2, -1), "B3-A2");
+        assert_eq!(shift_references("A2+B1", -3, -2), "A1+A1");
+    }
+}
@@ -0,0 +1,179 @@
+//! Minimal xlsx writing for the `from-csv` subcommand.
+
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{Read, Seek, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::sheet::column_name;
+use crate::{Result, Xlsx2CsvError};
+
+/// Longest sheet name Excel accepts.
+const MAX_SHEET_NAME: usize = 31;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/sharedStrings.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/></Relationships>"#;
+
+/// Writes the csv read from `csv` as the only sheet, named `sheet_name`, of a
+/// new workbook. Values that read as plain decimal numbers become number
+/// cells, everything else goes to the shared string table.
+pub fn write_xlsx<R: Read, W: Write + Seek>(csv: R, delimiter: u8, sheet_name: &str, out: W) -> Result<()> {
+    check_sheet_name(sheet_name)?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(csv);
+    let mut strings = SharedStrings::default();
+    let mut sheet_data = String::new();
+    let mut rows = 0;
+    let mut columns = 0;
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        rows += 1;
+        columns = columns.max(record.len());
+        let _ = write!(sheet_data, "<row r=\"{}\">", rows);
+        for (col, value) in record.iter().enumerate() {
+            if value.is_empty() {
+                continue;
+            }
+            let cell = format!("{}{}", column_name(col), rows);
+            if is_number(value) {
+                let _ = write!(sheet_data, "<c r=\"{}\"><v>{}</v></c>", cell, value);
+            } else {
+                let _ = write!(sheet_data, "<c r=\"{}\" t=\"s\"><v>{}</v></c>", cell, strings.index(value));
+            }
+        }
+        sheet_data.push_str("</row>");
+    }
+    let dimension = if rows == 0 {
+        "A1".to_string()
+    } else {
+        format!("A1:{}{}", column_name(columns.saturating_sub(1)), rows)
+    };
+
+    let workbook = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" "#,
+            r#"xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+            r#"<sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#
+        ),
+        escape(sheet_name)
+    );
+    let worksheet = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#,
+            r#"<dimension ref="{}"/><sheetData>{}</sheetData></worksheet>"#
+        ),
+        dimension, sheet_data
+    );
+
+    let mut zip = ZipWriter::new(out);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let parts = [
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("xl/workbook.xml", workbook.as_str()),
+        ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+        ("xl/worksheets/sheet1.xml", worksheet.as_str()),
+        ("xl/sharedStrings.xml", &strings.to_xml()),
+    ];
+    for (name, content) in parts {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Fails on names Excel refuses: empty, longer than 31 characters or holding
+/// one of `[]:*?/\`.
+fn check_sheet_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.chars().count() > MAX_SHEET_NAME || name.contains(['[', ']', ':', '*', '?', '/', '\\']) {
+        return Err(Xlsx2CsvError::InvalidOption(format!(
+            "Invalid sheet name '{}': it must have 1 to {} characters and none of []:*?/\\",
+            name, MAX_SHEET_NAME
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `value` is written as a number cell. Leading zeros and signs other
+/// than `-` keep a value a string, so codes like `007` survive the round trip.
+fn is_number(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let integer = digits.split(['.', 'e', 'E']).next().unwrap_or_default();
+    let leading_zero = integer.len() > 1 && integer.starts_with('0');
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && !leading_zero
+        && value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// The shared string table, each distinct value stored once.
+#[derive(Default)]
+struct SharedStrings {
+    indexes: HashMap<String, usize>,
+    values: Vec<String>,
+    /// References to the table, repeated values included
+    count: usize,
+}
+
+impl SharedStrings {
+    fn index(&mut self, value: &str) -> usize {
+        self.count += 1;
+        if let Some(&index) = self.indexes.get(value) {
+            return index;
+        }
+        let index = self.values.len();
+        self.indexes.insert(value.to_string(), index);
+        self.values.push(value.to_string());
+        index
+    }
+
+    fn to_xml(&self) -> String {
+        let mut xml = format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+                "\n",
+                r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="{}" uniqueCount="{}">"#
+            ),
+            self.count,
+            self.values.len()
+        );
+        for value in &self.values {
+            let space = if value.starts_with(char::is_whitespace) || value.ends_with(char::is_whitespace) {
+                r#" xml:space="preserve""#
+            } else {
+                ""
+            };
+            let _ = write!(xml, "<si><t{}>{}</t></si>", space, escape(&xml_chars(value)));
+        }
+        xml.push_str("</sst>");
+        xml
+    }
+}
+
+/// Writes the characters XML 1.0 cannot hold as Excel's `_xHHHH_` escapes.
+fn xml_chars(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '\t' | '\n' | '\r' => c.to_string(),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => format!("_x{:04X}_", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
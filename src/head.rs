@@ -0,0 +1,55 @@
+//! The first or last rows of a sheet for the `head` and `tail` subcommands.
+
+use std::collections::VecDeque;
+use std::io::{Read, Seek, Write};
+
+use crate::{Cell, Result, Workbook};
+
+/// Writes the first `count` rows of `sheet` (a name or a 1-based number, the
+/// first sheet without it) as csv, reading no further into the sheet.
+pub fn print_head<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    sheet: Option<&str>,
+    count: usize,
+    out: W,
+) -> Result<()> {
+    let name = workbook.resolve_sheet(sheet)?;
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    for row in workbook.sheet(&name)?.rows().take(count) {
+        writer.write_record(record(&row?))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the last `count` rows of `sheet` as csv. The sheet is read to the
+/// end, holding only the last `count` rows in a ring buffer.
+pub fn print_tail<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    sheet: Option<&str>,
+    count: usize,
+    out: W,
+) -> Result<()> {
+    let name = workbook.resolve_sheet(sheet)?;
+    let mut last = VecDeque::with_capacity(count);
+    for row in workbook.sheet(&name)?.rows() {
+        let row = row?;
+        if count == 0 {
+            continue;
+        }
+        if last.len() == count {
+            last.pop_front();
+        }
+        last.push_back(row);
+    }
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    for row in &last {
+        writer.write_record(record(row))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn record(row: &[Cell]) -> Vec<String> {
+    row.iter().map(Cell::to_string).collect()
+}
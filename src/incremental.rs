@@ -0,0 +1,115 @@
+//! The state file of `--if-changed`, recording the inputs already converted
+//! so unchanged ones can be skipped, and the `--checkpoint` file recording how
+//! far a conversion got.
+
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{ResumePoint, Result, Xlsx2CsvError};
+
+/// Digests of converted inputs, with the arguments they were converted
+/// with, keyed by input path.
+pub struct ChangeState {
+    path: PathBuf,
+    inputs: Map<String, Value>,
+}
+
+impl ChangeState {
+    /// Reads the state file at `path`, empty when it does not exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let inputs = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(Value::Object(inputs)) => inputs,
+                _ => return Err(Xlsx2CsvError::InvalidOption(format!("'{}' is not an --if-changed state file", path.display()))),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Map::new(),
+            Err(source) => return Err(Xlsx2CsvError::InputFile { path, source }),
+        };
+        Ok(ChangeState { path, inputs })
+    }
+
+    /// Whether `input` was last converted from content with this `digest`
+    /// and with the same `arguments`.
+    pub fn is_unchanged(&self, input: &str, digest: &str, arguments: &[String]) -> bool {
+        self.inputs
+            .get(input)
+            .is_some_and(|entry| entry["sha256"] == digest && entry["arguments"] == json!(arguments))
+    }
+
+    pub fn record(&mut self, input: &str, digest: &str, arguments: &[String]) {
+        self.inputs.insert(input.to_string(), json!({ "sha256": digest, "arguments": arguments }));
+    }
+
+    /// Writes the state file under a temporary name first, so an interrupted
+    /// run leaves the previous state intact.
+    pub fn save(&self) -> Result<()> {
+        let staging = staging_path(&self.path);
+        fs::write(&staging, serde_json::to_string_pretty(&self.inputs).map_err(io::Error::from)? + "\n")?;
+        fs::rename(&staging, &self.path)?;
+        Ok(())
+    }
+}
+
+/// How far the conversion of a workbook got: the last row written and the
+/// length of the output up to it.
+pub struct CheckpointFile {
+    /// SHA-256 of the workbook, which must not change before resuming
+    pub sha256: String,
+    pub sheet: String,
+    pub row: usize,
+    pub complete: bool,
+    pub output_bytes: u64,
+}
+
+impl CheckpointFile {
+    /// Reads the checkpoint file at `path`, `None` when it does not exist.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(Xlsx2CsvError::InputFile { path: path.to_path_buf(), source }),
+        };
+        let invalid = || Xlsx2CsvError::InvalidOption(format!("'{}' is not a --checkpoint file", path.display()));
+        let value: Value = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        Ok(Some(CheckpointFile {
+            sha256: value["sha256"].as_str().ok_or_else(invalid)?.to_string(),
+            sheet: value["sheet"].as_str().ok_or_else(invalid)?.to_string(),
+            row: value["row"].as_u64().ok_or_else(invalid)? as usize,
+            complete: value["complete"].as_bool().ok_or_else(invalid)?,
+            output_bytes: value["output_bytes"].as_u64().ok_or_else(invalid)?,
+        }))
+    }
+
+    /// Writes the checkpoint under a temporary name first, so it is never
+    /// left half written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let value = json!({
+            "sha256": self.sha256,
+            "sheet": self.sheet,
+            "row": self.row,
+            "complete": self.complete,
+            "output_bytes": self.output_bytes,
+        });
+        let staging = staging_path(path);
+        fs::write(&staging, serde_json::to_string_pretty(&value).map_err(io::Error::from)? + "\n")?;
+        fs::rename(&staging, path)?;
+        Ok(())
+    }
+
+    /// Where the conversion continues.
+    pub fn resume_point(&self) -> ResumePoint {
+        ResumePoint {
+            sheet: self.sheet.clone(),
+            row: (!self.complete).then_some(self.row),
+        }
+    }
+}
+
+fn staging_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
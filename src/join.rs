@@ -0,0 +1,99 @@
+//! Joining two sheets of a workbook on a key column for the `join` subcommand.
+
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use crate::{Cell, Result, Workbook, Xlsx2CsvError};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum JoinKind {
+    /// only left rows with a matching right row
+    Inner,
+    /// every left row, with empty right columns when nothing matches
+    Left,
+}
+
+/// Writes the rows of the `left` sheet joined with the rows of the `right`
+/// sheet whose key column holds the same text. `on` names the key column,
+/// or the left and right key columns as `left_name=right_name`. The right
+/// sheet is held in memory while the left one is streamed; its key column
+/// is not repeated in the output, and its other columns are prefixed with
+/// the sheet name where they clash with a left column.
+pub fn print_join<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    left: &str,
+    right: &str,
+    on: &str,
+    kind: JoinKind,
+    out: W,
+) -> Result<()> {
+    let (left_key, right_key) = on.split_once('=').unwrap_or((on, on));
+    let left = workbook.resolve_sheet(Some(left))?;
+    let right = workbook.resolve_sheet(Some(right))?;
+
+    let mut right_rows = workbook.sheet(&right)?.rows();
+    let right_header = match right_rows.next() {
+        Some(row) => record(&row?),
+        None => Vec::new(),
+    };
+    let right_col = key_column(&right_header, right_key)?;
+    let mut matches: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+    for row in right_rows {
+        let mut row = record(&row?);
+        row.resize(right_header.len().max(right_col + 1), String::new());
+        let key = row.remove(right_col);
+        matches.entry(key).or_default().push(row);
+    }
+    let right_width = right_header.len().max(right_col + 1) - 1;
+
+    let mut left_rows = workbook.sheet(&left)?.rows();
+    let left_header = match left_rows.next() {
+        Some(row) => record(&row?),
+        None => Vec::new(),
+    };
+    let left_col = key_column(&left_header, left_key)?;
+
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    let mut header = left_header.clone();
+    for (col, name) in right_header.iter().enumerate() {
+        if col == right_col {
+            continue;
+        }
+        if left_header.contains(name) {
+            header.push(format!("{}.{}", right, name));
+        } else {
+            header.push(name.clone());
+        }
+    }
+    writer.write_record(&header)?;
+
+    let empty = vec![String::new(); right_width];
+    for row in left_rows {
+        let mut row = record(&row?);
+        row.resize(row.len().max(left_header.len()), String::new());
+        let key = row.get(left_col).cloned().unwrap_or_default();
+        match matches.get(&key) {
+            Some(right_rows) => {
+                for right_row in right_rows {
+                    writer.write_record(row.iter().chain(right_row))?;
+                }
+            }
+            None if kind == JoinKind::Left => writer.write_record(row.iter().chain(&empty))?,
+            None => {}
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn record(row: &[Cell]) -> Vec<String> {
+    row.iter().map(Cell::to_string).collect()
+}
+
+fn key_column(header: &[String], name: &str) -> Result<usize> {
+    header.iter().position(|h| h == name).ok_or_else(|| Xlsx2CsvError::UnknownColumn {
+        option: "--on".to_string(),
+        name: name.to_string(),
+    })
+}
@@ -0,0 +1,108 @@
+//! Row output as JSON objects for `--format json` and `--format jsonl`.
+
+use std::io::Write;
+
+use crate::schema::json_string;
+use crate::sheet::CellType;
+use crate::Result;
+
+/// Writes each data row as an object keyed by its sheet's header row. With
+/// `array` the objects form one JSON array across all sheets, otherwise they
+/// are written one per line.
+pub struct JsonRows<W: Write> {
+    out: W,
+    array: bool,
+    infer_types: bool,
+    keys: Vec<String>,
+    rows: usize,
+}
+
+impl<W: Write> JsonRows<W> {
+    pub fn new(out: W, array: bool, infer_types: bool) -> Self {
+        JsonRows {
+            out,
+            array,
+            infer_types,
+            keys: Vec::new(),
+            rows: 0,
+        }
+    }
+
+    /// Starts a sheet, taking the object keys from its header row.
+    pub fn set_header(&mut self, header: &[String]) {
+        self.keys = header
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                if name.is_empty() {
+                    format!("column_{}", idx + 1)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+    }
+
+    /// Writes a data row. `types` holds the cell type of each value and is
+    /// only consulted with `--infer-types`.
+    pub fn write_row(&mut self, row: &[String], types: &[CellType]) -> Result<()> {
+        let mut object = String::from("{");
+        for (idx, value) in row.iter().enumerate() {
+            if idx > 0 {
+                object.push(',');
+            }
+            let key = match self.keys.get(idx) {
+                Some(key) => json_string(key),
+                None => json_string(&format!("column_{}", idx + 1)),
+            };
+            object.push_str(&key);
+            object.push(':');
+            let cell_type = types.get(idx).copied().unwrap_or_default();
+            object.push_str(&self.value(value, cell_type));
+        }
+        object.push('}');
+
+        if self.array {
+            write!(self.out, "{}{}", if self.rows == 0 { "[\n" } else { ",\n" }, object)?;
+        } else {
+            writeln!(self.out, "{}", object)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Closes the array, flushes the output and returns it.
+    pub fn finish(mut self) -> Result<W> {
+        if self.array {
+            if self.rows == 0 {
+                writeln!(self.out, "[]")?;
+            } else {
+                writeln!(self.out, "\n]")?;
+            }
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn value(&self, value: &str, cell_type: CellType) -> String {
+        if !self.infer_types {
+            return json_string(value);
+        }
+        if value.is_empty() {
+            return "null".to_string();
+        }
+        match cell_type {
+            CellType::Number => match value.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => n.to_string(),
+                _ => json_string(value),
+            },
+            CellType::Bool if value == "true" || value == "false" => value.to_string(),
+            _ => json_string(value),
+        }
+    }
+}
@@ -0,0 +1,874 @@
+//! Streaming xlsx to csv conversion.
+//!
+//! The `xlsx2csv` binary is a thin command line wrapper around [`convert`],
+//! which reads a workbook from any seekable reader and writes the selected
+//! sheets to any writer:
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io;
+//!
+//! let options = xlsx2csv::Options {
+//!     all_sheets: true,
+//!     ..Default::default()
+//! };
+//! xlsx2csv::convert(File::open("input.xlsx")?, &options, io::stdout())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Workbook`] reads sheets row by row instead, for processing data in Rust
+//! without going through csv, deserializes them into serde structs, or
+//! passes them to the callbacks of a [`Visitor`].
+//!
+//! With the `tokio` feature, `convert_async` converts from `AsyncRead + AsyncSeek`
+//! sources to `AsyncWrite` sinks without blocking the runtime, and the `wasm`
+//! feature exports a JavaScript `convert(Uint8Array, options)` for browsers.
+
+mod annotations;
+mod archive;
+mod audit;
+#[cfg(feature = "tokio")]
+mod async_convert;
+mod autofilter;
+pub mod bench;
+mod builder;
+pub mod cat;
+pub mod diff;
+mod error;
+mod events;
+pub mod checksum;
+mod column_kinds;
+mod expr;
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod formula;
+pub mod from_csv;
+pub mod head;
+pub mod join;
+pub mod incremental;
+mod json;
+mod limits;
+pub mod media;
+pub mod meta;
+mod parallel;
+mod phonetic;
+#[cfg(feature = "http")]
+pub mod post;
+pub mod preview;
+mod progress;
+pub mod query;
+#[cfg(feature = "python")]
+mod python;
+mod reorder;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sample;
+mod richtext;
+mod rows;
+mod schema;
+mod shared_strings;
+mod sheet;
+mod spill;
+pub mod stats;
+mod styles;
+mod text;
+mod transform;
+pub mod validate;
+mod visit;
+mod warning;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod workbook;
+mod writer;
+
+use csv::WriterBuilder;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+use zip::read::ZipArchive;
+
+use crate::annotations::{load_comments, load_hyperlinks, load_validations, CellAnnotations};
+use crate::archive::{check_uncompressed_size, load_relationships, open_part, CachedPart, PartCache};
+use crate::autofilter::AutoFilter;
+use crate::json::JsonRows;
+use crate::schema::SheetSchema;
+use crate::shared_strings::{needs_shared_strings, spawn_shared_strings, SharedStrings};
+use crate::sheet::{convert_sheet, SheetContext};
+use crate::styles::{CellStyle, NumberFormat};
+use crate::transform::Transforms;
+use crate::workbook::{check_conflicting_sheets, filter_sheets, load_external_links, load_sheets};
+use crate::warning::warn;
+use crate::writer::RowWriter;
+
+#[cfg(feature = "tokio")]
+pub use crate::async_convert::convert_async;
+pub use crate::builder::ConverterBuilder;
+pub use crate::column_kinds::ColumnKind;
+pub use crate::error::Xlsx2CsvError;
+pub use crate::events::{SheetEvent, SheetEventReader};
+pub use crate::progress::{Checkpoint, CheckpointCallback, Progress, ProgressCallback, ResumePoint, Totals};
+pub use crate::richtext::{Phonetic, RichText};
+pub use crate::rows::{Cell, DeserializeRows, Rows};
+pub use crate::schema::SqlDialect;
+pub use crate::shared_strings::StringArena;
+pub use crate::sheet::{CellRange, CellType, HyperlinkMode, InvalidUtf8, MaxColumnsAction, NewlineHandling, TypeErrors};
+pub use crate::transform::{CellContext, CellTransformer};
+pub use crate::visit::{CellRef, Visitor};
+pub use crate::warning::{Warning, WarningCallback};
+pub use crate::workbook::{Sheet, SheetInfo, SheetVisibility, Workbook};
+pub use crate::writer::{is_broken_pipe, OutputFormat};
+pub use csv::QuoteStyle;
+
+/// Default of [`Options::max_uncompressed_size`].
+pub const DEFAULT_MAX_UNCOMPRESSED_SIZE: u64 = 8 << 30;
+
+/// Result of the library's fallible operations.
+pub type Result<T, E = Xlsx2CsvError> = std::result::Result<T, E>;
+
+/// Settings for [`convert`]. The defaults match those of the command line,
+/// see [`ConverterBuilder`] for setting them one by one.
+#[derive(Clone)]
+pub struct Options {
+    /// convert only the sheet with this name
+    pub sheet_name: Option<String>,
+    /// convert only the sheet at this 1-based position
+    pub sheet_index: Option<usize>,
+    /// convert every sheet instead of only the first
+    pub all_sheets: bool,
+    /// with `all_sheets`, only convert sheets whose name matches one of these regexes
+    pub include_sheet_patterns: Vec<String>,
+    /// with `all_sheets`, skip sheets whose name matches one of these regexes
+    pub exclude_sheet_patterns: Vec<String>,
+
+    /// field delimiter of the csv output
+    pub delimiter: u8,
+    /// record terminator of the csv output
+    pub line_terminator: u8,
+    pub quote_style: QuoteStyle,
+    /// record written between sheets in csv format, `None` for no separator
+    pub sheet_delimiter: Option<String>,
+    pub format: OutputFormat,
+    pub newline_handling: NewlineHandling,
+    /// skip rows without any value
+    pub ignore_empty: bool,
+    /// drop trailing empty cells of each row
+    pub skip_empty_columns: bool,
+    /// stop a sheet after this many consecutive empty rows following data
+    pub stop_at_blank_row: Option<usize>,
+    /// only keep the first data row of each sheet and every Nth after it,
+    /// counting the rows left by the filters
+    pub every: Option<usize>,
+    /// guard against stray or corrupted cell references
+    pub max_columns: usize,
+    pub max_columns_action: MaxColumnsAction,
+
+    /// only convert the cells inside this range of each sheet
+    pub range: Option<CellRange>,
+
+    /// emit formula text instead of the cached value for formula cells
+    pub formulas: bool,
+    /// add the number format code, fill color and bold flag of each cell to
+    /// `--format long`
+    pub style_columns: bool,
+    pub rich_text: RichText,
+    pub phonetic: Phonetic,
+    /// write typed JSON values in json/jsonl format
+    pub infer_types: bool,
+    /// strftime style pattern for date cells, ex. `%Y/%m/%d`; dates are left
+    /// as serial numbers without it, except with `infer_types`
+    pub date_format: Option<String>,
+    pub hyperlinks: Option<HyperlinkMode>,
+    /// append cell comments to the commented cell's value
+    pub append_comments: bool,
+    /// only keep rows matching the sheet's stored autofilter criteria
+    pub respect_autofilter: bool,
+    /// warn about external workbook links on stderr
+    pub report_external_links: bool,
+
+    /// header renames as (old, new) pairs, applied to the written header only:
+    /// the column options name the columns of the sheet's header row
+    pub rename: Vec<(String, String)>,
+    pub snake_case_headers: bool,
+    /// row filter expression, see `--where`
+    pub where_expr: Option<String>,
+    /// `Cols=step|step` cell cleanups, see `--map-column`
+    pub map_columns: Vec<String>,
+    /// transformers run on the data cells of the given header columns (all
+    /// columns when empty), after `map_columns` and before `replace`
+    pub transformers: Vec<(Vec<String>, CellTransformer)>,
+    /// csv file of `column,from,to` rows replacing whole cell values of the
+    /// named header columns, after `transformers` and before `replace`
+    pub map_values: Option<PathBuf>,
+    /// `[Cols::]regex=>replacement` rules, see `--replace`
+    pub replace: Vec<String>,
+    pub dedup: bool,
+    /// `Column=delimiter` rules repeating a data row once per piece of the
+    /// column's cell, after the filters and `dedup`
+    pub explode: Vec<String>,
+    /// header columns compared when deduplicating, implies `dedup`
+    pub dedup_key: Vec<String>,
+    /// pad whole numbers formatted with zeros like `00000` to the format's
+    /// width, write them and numbers formatted as text as strings with
+    /// `infer_types`, and infer values with leading zeros as strings in the
+    /// schema
+    pub preserve_leading_zeros: bool,
+    /// `Column=type` rules coercing the data cells of the named columns, see `--types`
+    pub types: Vec<String>,
+    pub type_errors: TypeErrors,
+    /// only keep the columns of each sheet whose sampled data cells are
+    /// mostly of these kinds
+    pub only_types: Vec<ColumnKind>,
+    pub add_row_number: bool,
+    pub add_source_ref: bool,
+    /// fail a sheet without any data row besides its header row
+    pub fail_if_empty: bool,
+    /// fail a sheet with fewer data rows, not counting the header row
+    pub expect_rows_min: Option<u64>,
+    /// fail a sheet with more data rows, not counting the header row
+    pub expect_rows_max: Option<u64>,
+    /// fail a sheet whose header row has another number of columns
+    pub expect_columns: Option<usize>,
+    /// fail a sheet with another header row, compared after renames
+    pub expect_header: Option<Vec<String>>,
+
+    /// csv file receiving hyperlinks with [`HyperlinkMode::SeparateFile`]
+    pub hyperlinks_file: Option<PathBuf>,
+    /// csv file receiving the cell comments of the converted sheets
+    pub comments: Option<PathBuf>,
+    /// csv file receiving the data validation rules of the converted sheets
+    pub validations: Option<PathBuf>,
+    /// JSON file receiving the inferred column schema of the converted sheets
+    pub schema: Option<PathBuf>,
+    /// JSON file receiving the comments, formulas, hyperlinks, merged regions,
+    /// hidden rows and missing shared strings of the converted sheets, with
+    /// what the conversion did with them
+    pub audit: Option<PathBuf>,
+    /// write `CREATE TABLE` statements instead of the rows
+    pub ddl: Option<SqlDialect>,
+
+    /// checked before every row; setting it from another thread stops the
+    /// conversion with [`Xlsx2CsvError::Cancelled`]
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// receives the position within each sheet, ex. to drive a progress bar
+    pub progress: Option<ProgressCallback>,
+    /// receives the warnings about data converted only in part, which are
+    /// printed to stderr without it
+    pub warning: Option<WarningCallback>,
+    /// counts the sheets, rows and cells written
+    pub totals: Option<Arc<Totals>>,
+    /// receives the last row written, with the output flushed, to resume an
+    /// interrupted conversion from; converts the sheets one at a time
+    pub checkpoint: Option<CheckpointCallback>,
+    /// continue an interrupted conversion whose output is written up to this
+    /// point: earlier sheets and rows, and the header of the sheet, are not
+    /// written again
+    pub resume: Option<ResumePoint>,
+    /// flush the output after this many rows, so a reader of a pipe gets
+    /// them without waiting for the buffers to fill; converts the sheets one
+    /// at a time
+    pub flush_every: Option<u64>,
+    /// sheets converted at once, each on its own thread; 0 for one per
+    /// available core. Output stays in sheet order.
+    pub threads: usize,
+    /// split each sheet into chunks of rows converted on `threads` threads,
+    /// for workbooks with one huge sheet; only for options that do not
+    /// carry state from row to row
+    pub parallel_rows: bool,
+    /// bytes of memory to stay within: shared string tables over half of it
+    /// and parallel output beyond the other half go to temporary files
+    pub max_memory: Option<u64>,
+    /// sort rows that the XML lists out of order or more than once by row
+    /// number before converting them, which holds each sheet in memory
+    pub reorder_rows: bool,
+    /// fail instead of doing what is possible when the workbook loses data or
+    /// breaks the format: cells referring to missing shared strings, cells of
+    /// unknown types or with values that do not fit their type, and
+    /// relationship ids of sheets and hyperlinks that are not defined
+    pub strict: bool,
+    /// element nesting allowed in worksheets and the shared string table, as
+    /// a guard against hostile files
+    pub max_xml_depth: usize,
+    /// entity declarations allowed in a DOCTYPE, which OOXML parts never
+    /// have; declared entities are not expanded either way
+    pub max_entity_expansion: usize,
+    /// bytes the parts of the workbook may inflate to in total, 8 GiB by
+    /// default, as a guard against zip bombs; `None` for no limit. Parts
+    /// inflating past the size their zip entry declares always fail.
+    pub max_uncompressed_size: Option<u64>,
+    /// what happens to cell text that is not valid UTF-8
+    pub invalid_utf8: InvalidUtf8,
+    /// remove control characters other than tabs and line breaks from cell values
+    pub strip_control_chars: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sheet_name: None,
+            sheet_index: None,
+            all_sheets: false,
+            include_sheet_patterns: Vec::new(),
+            exclude_sheet_patterns: Vec::new(),
+            delimiter: b',',
+            line_terminator: b'\n',
+            quote_style: QuoteStyle::Necessary,
+            sheet_delimiter: Some("--------".to_string()),
+            format: OutputFormat::Csv,
+            newline_handling: NewlineHandling::Keep,
+            ignore_empty: false,
+            skip_empty_columns: false,
+            stop_at_blank_row: None,
+            every: None,
+            max_columns: sheet::MAX_COLUMNS,
+            max_columns_action: MaxColumnsAction::Error,
+            range: None,
+            formulas: false,
+            style_columns: false,
+            rich_text: RichText::Plain,
+            phonetic: Phonetic::Strip,
+            infer_types: false,
+            date_format: None,
+            hyperlinks: None,
+            append_comments: false,
+            respect_autofilter: false,
+            report_external_links: false,
+            rename: Vec::new(),
+            snake_case_headers: false,
+            where_expr: None,
+            map_columns: Vec::new(),
+            transformers: Vec::new(),
+            map_values: None,
+            replace: Vec::new(),
+            dedup: false,
+            explode: Vec::new(),
+            dedup_key: Vec::new(),
+            preserve_leading_zeros: false,
+            types: Vec::new(),
+            type_errors: TypeErrors::Warn,
+            only_types: Vec::new(),
+            add_row_number: false,
+            add_source_ref: false,
+            fail_if_empty: false,
+            expect_rows_min: None,
+            expect_rows_max: None,
+            expect_columns: None,
+            expect_header: None,
+            hyperlinks_file: None,
+            comments: None,
+            validations: None,
+            schema: None,
+            audit: None,
+            ddl: None,
+            cancel: None,
+            progress: None,
+            warning: None,
+            totals: None,
+            checkpoint: None,
+            resume: None,
+            flush_every: None,
+            threads: 0,
+            parallel_rows: false,
+            max_memory: None,
+            reorder_rows: false,
+            strict: false,
+            max_xml_depth: limits::DEFAULT_MAX_DEPTH,
+            max_entity_expansion: 0,
+            max_uncompressed_size: Some(DEFAULT_MAX_UNCOMPRESSED_SIZE),
+            invalid_utf8: InvalidUtf8::Error,
+            strip_control_chars: false,
+        }
+    }
+}
+
+/// Converts the sheets of the xlsx workbook read from `reader` that are
+/// selected by `options`, writing them to `writer` one after another.
+///
+/// A consumer closing the output early surfaces as an error for which
+/// [`is_broken_pipe`] returns true.
+#[instrument(skip_all)]
+pub fn convert<R: Read + Seek, W: Write>(reader: R, options: &Options, writer: W) -> Result<()> {
+    let transforms = Transforms::from_options(options)?;
+
+    if options.infer_types && !matches!(options.format, OutputFormat::Json | OutputFormat::Jsonl) {
+        return Err(Xlsx2CsvError::InvalidOption("--infer-types requires --format json or jsonl".to_string()));
+    }
+    if options.phonetic == Phonetic::Only && options.format == OutputFormat::Long {
+        return Err(Xlsx2CsvError::InvalidOption("--phonetic only adds columns, use --phonetic append with --format long".to_string()));
+    }
+    if options.style_columns && options.format != OutputFormat::Long {
+        return Err(Xlsx2CsvError::InvalidOption("--style-columns requires --format long".to_string()));
+    }
+    let resumable = options.checkpoint.is_some() || options.resume.is_some();
+    if resumable && (options.ddl.is_some() || !matches!(options.format, OutputFormat::Csv | OutputFormat::Jsonl)) {
+        return Err(Xlsx2CsvError::InvalidOption("Checkpoints require --format csv or jsonl".to_string()));
+    }
+    if let Some(option) = options.sequential_rows_option().filter(|_| options.parallel_rows) {
+        return Err(Xlsx2CsvError::InvalidOption(format!("--parallel-rows cannot be combined with {}", option)));
+    }
+
+    let mut archive = ZipArchive::new(reader)?;
+    debug!(entries = archive.len(), "opened archive");
+    if let Some(limit) = options.max_uncompressed_size {
+        check_uncompressed_size(&mut archive, limit)?;
+    }
+
+    let rels = load_relationships(&mut archive)?;
+    let sheets = load_sheets(&mut archive, &rels, options.strict)?;
+    debug!(count = sheets.len(), "found sheets");
+    // The other parts are only read once the sheets to convert are known
+    let targets = filter_sheets(
+        sheets,
+        &options.sheet_name,
+        options.sheet_index,
+        options.all_sheets,
+        &options.include_sheet_patterns,
+        &options.exclude_sheet_patterns,
+    )?;
+
+    if targets.is_empty() {
+        return Err(Xlsx2CsvError::NoMatchingSheets);
+    }
+    check_conflicting_sheets(&targets)?;
+
+    let external_links = if options.report_external_links {
+        let links = load_external_links(&mut archive)?;
+        for (idx, target) in links.iter().enumerate() {
+            let message = format!("workbook links to external workbook [{}] '{}'", idx + 1, target);
+            warn(options, Warning { message, sheet: None, cell: None });
+        }
+        links
+    } else {
+        Vec::new()
+    };
+    let mut shared_strings = if needs_shared_strings(&mut archive, &targets)? {
+        spawn_shared_strings(&mut archive, options.rich_text, options.phonetic, options.max_memory, options.into(), options.invalid_utf8)?
+    } else {
+        StringArena::default().into()
+    };
+
+    // Dates are only told apart from numbers by their cell style
+    let dates = options.infer_types || options.date_format.is_some();
+    let infer_schema = options.schema.is_some() || options.ddl.is_some();
+    let mut number_formats = if dates || infer_schema || options.preserve_leading_zeros || !options.only_types.is_empty() {
+        styles::load_number_formats(&mut archive)?
+    } else {
+        Vec::new()
+    };
+    // --only-types, --schema and --ddl tell date columns apart even when dates are written as numbers
+    let column_formats = if options.only_types.is_empty() && !infer_schema { Vec::new() } else { number_formats.clone() };
+    if !dates {
+        number_formats.iter_mut().filter(|format| **format == NumberFormat::Date).for_each(|format| *format = NumberFormat::General);
+    }
+    let cell_styles = if options.style_columns {
+        styles::load_cell_styles(&mut archive)?
+    } else {
+        Vec::new()
+    };
+    let date1904 = if dates || !options.types.is_empty() {
+        meta::load_workbook_info(&mut archive)?.date1904
+    } else {
+        false
+    };
+
+    info!(sheets = ?targets.iter().map(|sheet| &sheet.name).collect::<Vec<_>>(), "converting");
+    let audited = options.audit.is_some().then(|| targets.clone());
+
+    let mut side_outputs = SideOutputs::create(options)?;
+    let conversion = Conversion {
+        options,
+        transforms: &transforms,
+        external_links: &external_links,
+        number_formats: &number_formats,
+        column_formats: &column_formats,
+        cell_styles: &cell_styles,
+        date1904,
+    };
+
+    // Earlier sheets were written by the interrupted conversion
+    let (first_sheet, resume_row) = match &options.resume {
+        Some(ResumePoint { sheet, row }) => match targets.iter().position(|target| target.name == *sheet) {
+            Some(idx) => (idx + usize::from(row.is_none()), *row),
+            None => return Err(Xlsx2CsvError::InvalidOption(format!("Cannot resume at sheet '{}', it is not converted", sheet))),
+        },
+        None => (0, None),
+    };
+
+    // A json array spans the sheets, so its rows are written by one writer;
+    // checkpoints and flushes follow the sheets in order
+    let threads = if resumable || options.flush_every.is_some() { 1 } else { options.worker_threads(targets.len()) };
+    let (schemas, mut out) = if threads > 1 && options.format != OutputFormat::Json {
+        parallel::convert_sheets(&mut archive, targets, threads, &conversion, &mut shared_strings, &mut side_outputs, writer)?
+    } else {
+        let mut writer = row_writer(options, writer);
+        if options.resume.is_none() {
+            write_long_header(&mut writer, options)?;
+        }
+        let mut schemas = Vec::new();
+        for (idx, SheetInfo { name: sheet_name, path, .. }) in targets.into_iter().enumerate().skip(first_sheet) {
+            let (annotations, autofilter) = side_outputs.load_sheet(&mut archive, options, &sheet_name, &path)?;
+            // The delimiter before a partly written sheet is already written
+            let resume_after = resume_row.filter(|_| idx == first_sheet);
+            if idx > 0 && resume_after.is_none() {
+                write_sheet_delimiter(&mut writer, options)?;
+            }
+            let kept_columns = conversion.kept_columns(&mut archive, &path, &mut side_outputs)?;
+            let phonetic_columns = conversion.phonetic_columns(&mut archive, &path, &mut shared_strings, &mut side_outputs)?;
+            let (part, size) = side_outputs.sheet_part(&mut archive, &path)?;
+            let mut sheet = conversion.sheet(&sheet_name, &path, size, &annotations, autofilter.as_ref());
+            sheet.resume_after = resume_after;
+            sheet.kept_columns = kept_columns.as_deref();
+            sheet.phonetic_columns = phonetic_columns.as_deref();
+            let mut schema = conversion.schema(&sheet_name);
+            let result = convert_sheet(part, &sheet, &mut shared_strings, &mut writer, options, &transforms, schema.as_mut());
+            if let Err(err) = result {
+                return Err(sheet_error(sheet_name, err));
+            }
+            schemas.extend(schema);
+        }
+        (schemas, writer.finish()?)
+    };
+
+    if let Some(path) = &options.schema {
+        let mut out = BufWriter::new(File::create(path)?);
+        schema::write_schema(&schemas, &mut out)?;
+        out.flush()?;
+    }
+
+    side_outputs.flush()?;
+
+    if let (Some(path), Some(sheets)) = (&options.audit, audited) {
+        audit::write_audit(&mut archive, &sheets, options, path)?;
+    }
+
+    if let Some(dialect) = options.ddl {
+        schema::write_ddl(&schemas, dialect, &mut out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// What the sheets of one conversion share.
+pub(crate) struct Conversion<'a> {
+    pub options: &'a Options,
+    pub transforms: &'a Transforms,
+    pub external_links: &'a [String],
+    pub number_formats: &'a [NumberFormat],
+    /// The number formats with their dates, only loaded for --only-types, --schema and --ddl
+    pub column_formats: &'a [NumberFormat],
+    pub cell_styles: &'a [CellStyle],
+    pub date1904: bool,
+}
+
+impl<'a> Conversion<'a> {
+    pub fn sheet(
+        &self,
+        name: &'a str,
+        path: &'a str,
+        size: u64,
+        annotations: &'a CellAnnotations,
+        autofilter: Option<&'a AutoFilter>,
+    ) -> SheetContext<'a> {
+        SheetContext {
+            name,
+            path,
+            size,
+            after_header: false,
+            row_offset: 0,
+            resume_after: None,
+            kept_columns: None,
+            phonetic_columns: None,
+            annotations,
+            autofilter,
+            external_links: self.external_links,
+            number_formats: self.number_formats,
+            column_formats: self.column_formats,
+            cell_styles: self.cell_styles,
+            date1904: self.date1904,
+        }
+    }
+
+    /// The columns `--only-types` keeps of the sheet at `path`, from a
+    /// sample of its first rows.
+    pub fn kept_columns<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        path: &str,
+        side_outputs: &mut SideOutputs,
+    ) -> Result<Option<Vec<bool>>> {
+        if self.options.only_types.is_empty() {
+            return Ok(None);
+        }
+        let options = self.options;
+        let part = side_outputs.parts.open(archive, path)?;
+        let kept = column_kinds::sample_columns(part, &options.only_types, self.column_formats, options.range.as_ref(), options.max_columns)?;
+        Ok(Some(kept))
+    }
+
+    /// The columns of the sheet at `path` followed by a reading column for
+    /// `--phonetic only`, those holding a string with a reading.
+    pub fn phonetic_columns<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        path: &str,
+        shared_strings: &mut SharedStrings,
+        side_outputs: &mut SideOutputs,
+    ) -> Result<Option<Vec<bool>>> {
+        if self.options.phonetic != Phonetic::Only {
+            return Ok(None);
+        }
+        let part = side_outputs.parts.open(archive, path)?;
+        Ok(Some(phonetic::reading_columns(part, shared_strings, self.options.max_columns)?))
+    }
+
+    /// The schema to infer for a sheet, only with `--schema` or `--ddl`.
+    pub fn schema(&self, sheet_name: &str) -> Option<SheetSchema> {
+        (self.options.schema.is_some() || self.options.ddl.is_some()).then(|| SheetSchema::new(sheet_name, self.options.preserve_leading_zeros))
+    }
+}
+
+impl Options {
+    /// Threads converting sheets at once, at most one per sheet unless the
+    /// sheets are split into row chunks.
+    fn worker_threads(&self, sheets: usize) -> usize {
+        let threads = match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        if self.parallel_rows {
+            threads
+        } else {
+            threads.min(sheets)
+        }
+    }
+
+    /// The first option that needs the rows of a sheet converted in order,
+    /// which rules out `parallel_rows`.
+    fn sequential_rows_option(&self) -> Option<&'static str> {
+        let needs_order = [
+            (matches!(self.format, OutputFormat::Json | OutputFormat::Jsonl), "--format json/jsonl"),
+            (self.where_expr.is_some(), "--where"),
+            (!self.map_columns.is_empty(), "--map-column"),
+            (!self.transformers.is_empty(), "cell transformers"),
+            (self.map_values.is_some(), "--map-values"),
+            (!self.replace.is_empty(), "--replace"),
+            (self.dedup || !self.dedup_key.is_empty(), "--dedup"),
+            (!self.types.is_empty(), "--types"),
+            (self.stop_at_blank_row.is_some(), "--stop-at-blank-row"),
+            (self.every.is_some(), "--every"),
+            (self.formulas, "--formulas"),
+            (self.style_columns, "--style-columns"),
+            (self.schema.is_some(), "--schema"),
+            (self.ddl.is_some(), "--ddl"),
+            (self.reorder_rows, "--reorder-rows"),
+            (self.expect_rows_min.is_some() || self.expect_rows_max.is_some(), "--expect-rows-min/max"),
+            (self.fail_if_empty, "--fail-if-empty"),
+            (self.flush_every.is_some(), "--flush-every"),
+        ];
+        needs_order.into_iter().find(|(used, _)| *used).map(|(_, option)| option)
+    }
+}
+
+/// Where the rows go in the selected output format. `--ddl` only needs the
+/// sheets scanned, the statements replace the rows.
+pub(crate) fn row_writer<W: Write>(options: &Options, writer: W) -> RowWriter<W> {
+    match options.format {
+        _ if options.ddl.is_some() => RowWriter::Discard(writer),
+        OutputFormat::Json | OutputFormat::Jsonl => RowWriter::Json(JsonRows::new(
+            writer,
+            options.format == OutputFormat::Json,
+            options.infer_types,
+        )),
+        OutputFormat::Csv | OutputFormat::Long => RowWriter::Csv(Box::new(
+            WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .delimiter(options.delimiter)
+                .quote_style(options.quote_style)
+                .terminator(csv::Terminator::Any(options.line_terminator))
+                .from_writer(writer),
+        )),
+    }
+}
+
+/// Writes the column names of `--format long`, once for all sheets.
+pub(crate) fn write_long_header<W: Write>(writer: &mut RowWriter<W>, options: &Options) -> Result<()> {
+    if options.format == OutputFormat::Long {
+        if let RowWriter::Csv(w) = writer {
+            let mut header = vec!["sheet", "ref", "row", "col", "type", "value"];
+            if options.formulas {
+                header.push("formula");
+            }
+            if options.style_columns {
+                header.extend(["number_format", "fill", "bold"]);
+            }
+            w.write_record(&header)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the record separating two sheets, long output is one continuous table.
+pub(crate) fn write_sheet_delimiter<W: Write>(writer: &mut RowWriter<W>, options: &Options) -> Result<()> {
+    if options.format == OutputFormat::Csv {
+        if let (Some(delim), RowWriter::Csv(w)) = (&options.sheet_delimiter, writer) {
+            w.write_record([delim])?;
+        }
+    }
+    Ok(())
+}
+
+/// Names the sheet whose conversion failed, except for errors that end the
+/// whole conversion.
+pub(crate) fn sheet_error(name: String, err: Xlsx2CsvError) -> Xlsx2CsvError {
+    if is_broken_pipe(&err) || matches!(err, Xlsx2CsvError::Cancelled) {
+        return err;
+    }
+    debug!(sheet = %name, error = %err, "sheet conversion failed");
+    Xlsx2CsvError::Sheet {
+        name,
+        source: Box::new(err),
+    }
+}
+
+/// The csv files receiving the comments, hyperlinks and validation rules of
+/// the converted sheets, and the sheets inflated for them, the `--only-types`
+/// sample or the `--phonetic only` scan until their conversion reads them again.
+pub(crate) struct SideOutputs {
+    comments: Option<csv::Writer<File>>,
+    hyperlinks: Option<csv::Writer<File>>,
+    validations: Option<csv::Writer<File>>,
+    parts: PartCache,
+}
+
+impl SideOutputs {
+    fn create(options: &Options) -> Result<Self> {
+        let comments = match &options.comments {
+            Some(path) => {
+                let mut w = csv::Writer::from_path(path)?;
+                w.write_record(["sheet", "cell", "author", "text"])?;
+                Some(w)
+            }
+            None => None,
+        };
+
+        let hyperlinks = match (options.hyperlinks, &options.hyperlinks_file) {
+            (Some(HyperlinkMode::SeparateFile), Some(path)) => {
+                let mut w = csv::Writer::from_path(path)?;
+                w.write_record(["sheet", "cell", "url", "display"])?;
+                Some(w)
+            }
+            (Some(HyperlinkMode::SeparateFile), None) => {
+                return Err(Xlsx2CsvError::InvalidOption(
+                    "--hyperlinks=separate-file requires --hyperlinks-file".to_string(),
+                ));
+            }
+            _ => None,
+        };
+
+        let validations = match &options.validations {
+            Some(path) => {
+                let mut w = csv::Writer::from_path(path)?;
+                w.write_record(["sheet", "range", "type", "operator", "formula1", "formula2", "allow_blank"])?;
+                Some(w)
+            }
+            None => None,
+        };
+
+        Ok(SideOutputs {
+            comments,
+            hyperlinks,
+            validations,
+            parts: PartCache::new(options.max_memory),
+        })
+    }
+
+    /// Writes the side outputs of a sheet and loads the annotations and
+    /// autofilter its conversion needs.
+    pub fn load_sheet<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        options: &Options,
+        sheet_name: &str,
+        path: &str,
+    ) -> Result<(CellAnnotations, Option<AutoFilter>)> {
+        if let Some(w) = self.validations.as_mut() {
+            for rule in load_validations(self.parts.open(archive, path)?)? {
+                w.write_record([
+                    sheet_name,
+                    &rule.sqref,
+                    &rule.kind,
+                    &rule.operator,
+                    &rule.formula1,
+                    &rule.formula2,
+                    if rule.allow_blank { "true" } else { "false" },
+                ])?;
+            }
+        }
+        let mut annotations = CellAnnotations::default();
+        if let Some(mode) = options.hyperlinks {
+            let sheet = self.parts.open(archive, path)?;
+            let links = load_hyperlinks(archive, path, sheet, options.strict)?;
+            if let Some(w) = self.hyperlinks.as_mut() {
+                for link in &links {
+                    w.write_record([sheet_name, &link.cell_ref, &link.url, &link.display])?;
+                }
+            }
+            annotations.add_hyperlinks(&links, mode);
+        }
+        if self.comments.is_some() || options.append_comments {
+            let comments = load_comments(archive, path)?;
+            if let Some(w) = self.comments.as_mut() {
+                for comment in &comments {
+                    w.write_record([sheet_name, &comment.cell_ref, &comment.author, &comment.text])?;
+                }
+            }
+            if options.append_comments {
+                annotations.add_comments(&comments);
+            }
+        }
+
+        let autofilter = if options.respect_autofilter {
+            autofilter::load_autofilter(self.parts.open(archive, path)?)?
+        } else {
+            None
+        };
+        Ok((annotations, autofilter))
+    }
+
+    /// The XML of the sheet at `path` for its conversion and its uncompressed
+    /// size, inflated again unless an earlier pass over it cached it.
+    pub fn sheet_part<'a, R: Read + Seek>(
+        &mut self,
+        archive: &'a mut ZipArchive<R>,
+        path: &str,
+    ) -> Result<(Box<dyn Read + 'a>, u64)> {
+        match self.cached_part(path) {
+            Some(part) => {
+                let size = part.size()?;
+                Ok((part.into_reader()?, size))
+            }
+            None => {
+                let part = open_part(archive, path)?;
+                let size = part.size();
+                Ok((Box::new(part), size))
+            }
+        }
+    }
+
+    /// The XML of the sheet at `path` if an earlier pass over it inflated it.
+    pub fn cached_part(&mut self, path: &str) -> Option<CachedPart> {
+        self.parts.take(path)
+    }
+
+    fn flush(self) -> Result<()> {
+        for mut w in [self.comments, self.hyperlinks, self.validations].into_iter().flatten() {
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
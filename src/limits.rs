@@ -0,0 +1,81 @@
+//! Guards against hostile XML in the parts with the workbook's content, see
+//! [`Options::max_xml_depth`](crate::Options::max_xml_depth) and
+//! [`Options::max_entity_expansion`](crate::Options::max_entity_expansion).
+//!
+//! quick-xml only expands the predefined entities and character references,
+//! so entities declared in a DOCTYPE (billion laughs) are never expanded and
+//! references to them fail to unescape. OOXML parts have no DOCTYPE at all,
+//! so one declaring entities is rejected before any of its text is read.
+
+use quick_xml::events::Event;
+
+use crate::{Options, Result, Xlsx2CsvError};
+
+/// Element nesting allowed by default; worksheets nest about 6 deep.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub struct XmlLimits {
+    pub max_depth: usize,
+    /// Entity declarations allowed in a DOCTYPE
+    pub max_entities: usize,
+}
+
+impl Default for XmlLimits {
+    fn default() -> Self {
+        XmlLimits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_entities: 0,
+        }
+    }
+}
+
+impl From<&Options> for XmlLimits {
+    fn from(options: &Options) -> Self {
+        XmlLimits {
+            max_depth: options.max_xml_depth,
+            max_entities: options.max_entity_expansion,
+        }
+    }
+}
+
+/// Checks the events of one part against [`XmlLimits`].
+pub struct XmlGuard {
+    limits: XmlLimits,
+    depth: usize,
+}
+
+impl XmlGuard {
+    pub fn new(limits: XmlLimits) -> Self {
+        XmlGuard { limits, depth: 0 }
+    }
+
+    /// Fails on an element nested deeper than allowed or a DOCTYPE declaring
+    /// more entities than allowed.
+    pub fn check(&mut self, event: &Event) -> Result<()> {
+        match event {
+            Event::Start(_) => {
+                self.depth += 1;
+                if self.depth > self.limits.max_depth {
+                    return Err(Xlsx2CsvError::XmlLimit(format!(
+                        "elements nested more than {} deep (see --max-xml-depth)",
+                        self.limits.max_depth
+                    )));
+                }
+            }
+            // Row chunks of --parallel-rows start inside the elements they end
+            Event::End(_) => self.depth = self.depth.saturating_sub(1),
+            Event::DocType(doctype) => {
+                let entities = doctype.windows(8).filter(|window| window == b"<!ENTITY").count();
+                if entities > self.limits.max_entities {
+                    return Err(Xlsx2CsvError::XmlLimit(format!(
+                        "DOCTYPE declares {} entities, more than {} (see --max-entity-expansion)",
+                        entities, self.limits.max_entities
+                    )));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,97 @@
-use clap::Parser;
-use csv::{QuoteStyle, WriterBuilder};
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use regex::Regex;
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use clap::{ArgAction, ColorChoice, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use csv::QuoteStyle;
+use std::env;
+use std::fs::{self, File};
+use indicatif::{ProgressBar, ProgressStyle};
+use memmap2::Mmap;
+use std::io::{self, BufReader, BufWriter, Cursor, IsTerminal, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 use zip::read::ZipArchive;
 
-type BoxResult<T> = Result<T, Box<dyn Error>>;
+use xlsx2csv::checksum::{self, ChecksumAlgorithm, HashingWriter};
+use xlsx2csv::diff::DiffFormat;
+use xlsx2csv::incremental::{ChangeState, CheckpointFile};
+use xlsx2csv::join::JoinKind;
+#[cfg(feature = "http")]
+use xlsx2csv::post::HttpPost;
+use xlsx2csv::query::Aggregate;
+#[cfg(feature = "s3")]
+use xlsx2csv::s3::S3Upload;
+use xlsx2csv::{
+    bench, cat, diff, from_csv, head, is_broken_pipe, join, meta, media, preview, query, sample, stats, validate, ConverterBuilder, HyperlinkMode, InvalidUtf8, MaxColumnsAction,
+    Checkpoint, ColumnKind, NewlineHandling, Options, OutputFormat, Phonetic, Progress, Result, RichText, SqlDialect, Totals, TypeErrors, Warning, Workbook,
+    Xlsx2CsvError,
+};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Exit status of failures that fit none of the classes below.
+const EXIT_FAILURE: i32 = 1;
+/// Exit status of invalid arguments, as clap uses for its own errors.
+const EXIT_USAGE: i32 = 2;
+/// Exit status when the workbook cannot be opened or is not a zip archive.
+const EXIT_INPUT: i32 = 3;
+/// Exit status when the requested sheets are not in the workbook.
+const EXIT_SHEET_NOT_FOUND: i32 = 4;
+/// Exit status of malformed workbook content.
+const EXIT_PARSE: i32 = 5;
+/// Exit status when the output cannot be written.
+const EXIT_OUTPUT: i32 = 6;
+/// Exit status when the converted data does not meet an `--expect-*` option.
+const EXIT_EXPECTATION: i32 = 7;
+/// Exit status when a sheet yields no data rows with `--fail-if-empty`.
+const EXIT_EMPTY: i32 = 8;
+/// Exit status after Ctrl-C, as shells report for SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Input size from which the CLI shows a progress bar.
+const PROGRESS_THRESHOLD: u64 = 8 * 1024 * 1024;
+
 #[derive(Parser)]
 #[command(name = "xlsx2csv")]
 #[command(about = "xlsx to csv converter", version = VERSION)]
+#[command(long_about = "xlsx to csv converter\n\nOptions marked [env: XLSX2CSV_...] can also be set through \
+that environment variable; a flag on the command line takes precedence.")]
+#[command(after_help = "Exit status: 0 success, 1 other failure, 2 invalid arguments, 3 workbook not found or \
+unreadable, 4 sheet not found, 5 malformed workbook content, 6 output error, 7 unmet --expect-* option, \
+8 no data rows with --fail-if-empty, 130 interrupted")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand the arguments are those of `convert`
+    #[command(flatten)]
+    args: Args,
+
+    /// how errors and warnings are written to stderr: 'text', or 'json' for one object per line
+    /// with level, code, message, file, sheet and cell
+    #[arg(long = "error-format", value_enum, default_value = "text", global = true)]
+    error_format: ErrorFormat,
+}
+
+/// How errors and warnings are written to stderr.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+/// The arguments of a conversion.
+#[derive(clap::Args)]
 struct Args {
     /// xlsx file path, use '-' to read from STDIN
-    xlsxfile: String,
+    #[arg(required = true)]
+    xlsxfile: Option<String>,
 
-    /// output csv file path
+    /// output csv file path, or s3://bucket/key to upload it (with the s3 feature)
     outfile: Option<String>,
 
     /// export all sheets
@@ -32,27 +103,37 @@ struct Args {
     outputencoding: String,
 
     /// delimiter - columns delimiter in csv, 'tab' or 'x09' for a tab (default: comma ',')
-    #[arg(short = 'd', long, default_value = ",")]
+    #[arg(short = 'd', long, default_value = ",", env = "XLSX2CSV_DELIMITER")]
     delimiter: String,
 
-    /// include hyperlinks
-    #[arg(long)]
-    hyperlinks: bool,
+    /// include hyperlinks: 'url' replaces the cell text with the link, 'append' adds it after the text,
+    /// 'separate-file' writes them to --hyperlinks-file; the mode is given as --hyperlinks=append
+    /// (default: url)
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "url")]
+    hyperlinks: Option<HyperlinkMode>,
+
+    /// csv file receiving hyperlinks (sheet,cell,url,display) with --hyperlinks=separate-file
+    #[arg(long = "hyperlinks-file")]
+    hyperlinks_file: Option<String>,
 
-    /// Escape \r\n\t characters
-    #[arg(short = 'e', long)]
+    /// Escape \r\n\t characters, same as --newline-handling escape
+    #[arg(short = 'e', long, conflicts_with_all = ["no_line_breaks", "newline_handling"])]
     escape: bool,
 
-    /// Replace \r\n\t with space
-    #[arg(long = "no-line-breaks")]
+    /// Replace \r\n\t with space, same as --newline-handling space
+    #[arg(long = "no-line-breaks", conflicts_with = "newline_handling")]
     no_line_breaks: bool,
 
+    /// embedded line breaks in cells: keep them (quoted), escape them as \n, or replace them with a space
+    #[arg(long = "newline-handling", value_enum, env = "XLSX2CSV_NEWLINE_HANDLING")]
+    newline_handling: Option<NewlineHandling>,
+
     /// exclude sheets named matching given pattern, only effects when -a option is enabled
     #[arg(short = 'E', long = "exclude_sheet_pattern")]
     exclude_sheet_pattern: Vec<String>,
 
     /// override date/time format (ex. %Y/%m/%d)
-    #[arg(short = 'f', long)]
+    #[arg(short = 'f', long, env = "XLSX2CSV_DATE_FORMAT")]
     dateformat: Option<String>,
 
     /// override time format (ex. %H/%M/%S)
@@ -75,12 +156,22 @@ struct Args {
     #[arg(long)]
     exclude_hidden_sheets: bool,
 
+    /// list the sheets (index, name, visibility, relationship id, part path and
+    /// dimension) as csv instead of converting
+    #[arg(long = "list-sheets")]
+    list_sheets: bool,
+
+    /// pick the sheets to convert from a numbered list with their row counts, when the workbook
+    /// has several and none is selected; only prompts when STDIN and STDERR are a terminal
+    #[arg(long)]
+    interactive: bool,
+
     /// Ignores format for specific data types
     #[arg(long = "ignore-formats")]
     ignore_formats: Vec<String>,
 
     /// line terminator - lines terminator in csv, '\n' '\r\n' or '\r' (default: \n)
-    #[arg(short = 'l', long, default_value = "\n")]
+    #[arg(short = 'l', long, default_value = "\n", env = "XLSX2CSV_LINE_TERMINATOR")]
     lineterminator: String,
 
     /// merge cells
@@ -100,11 +191,11 @@ struct Args {
     skipemptycolumns: bool,
 
     /// sheet delimiter used to separate sheets, pass '' if you do not need delimiter, or 'x07' or '\f' for form feed (default: '--------')
-    #[arg(short = 'p', long, default_value = "--------")]
+    #[arg(short = 'p', long, default_value = "--------", env = "XLSX2CSV_SHEET_DELIMITER")]
     sheetdelimiter: String,
 
     /// quoting - fields quoting in csv, 'none' 'minimal' 'nonnumeric' or 'all' (default: minimal)
-    #[arg(short = 'q', long, default_value = "minimal")]
+    #[arg(short = 'q', long, default_value = "minimal", env = "XLSX2CSV_QUOTING")]
     quoting: String,
 
     /// sheet number to convert
@@ -114,543 +205,1593 @@ struct Args {
     /// include hidden rows
     #[arg(long)]
     include_hidden_rows: bool,
-}
 
-#[derive(Clone, Copy)]
-enum CellType {
-    SharedString,
-    InlineStr,
-    Bool,
-    Number,
-    Error,
-    PlainStr,
-}
+    /// rename header columns, comma separated old=new pairs (ex. 'Old Name=new_name,Amt=amount');
+    /// options naming columns (--where, --map-column, --map-values, --replace, --dedup-key, --types,
+    /// --explode) use the names in the sheet, --expect-header the renamed ones
+    #[arg(long)]
+    rename: Vec<String>,
 
-fn main() -> BoxResult<()> {
-    let args = Args::parse();
+    /// csv file with old,new header name pairs, applied like --rename
+    #[arg(long = "rename-file")]
+    rename_file: Option<String>,
 
-    // Handle version flag (already handled by clap)
+    /// sanitize header names to lowercase snake_case and deduplicate repeated names; like
+    /// --rename, this only changes the written header, not the names other options use
+    #[arg(long = "snake-case-headers")]
+    snake_case_headers: bool,
 
-    // Validate encoding
-    if args.outputencoding != "utf-8" {
-        eprintln!("Warning: Only UTF-8 encoding is supported in this Rust implementation");
-    }
+    /// only keep data rows matching expression (ex. 'col("Status") == "ACTIVE" && num("Amount") > 0')
+    #[arg(long = "where")]
+    where_expr: Option<String>,
 
-    // Parse delimiter
-    let delimiter = parse_delimiter(&args.delimiter)?;
+    /// clean up the cells of the named columns with steps run left to right, ex. 'Amount=trim|upper'
+    /// or 'Name,City=collapse'; steps: trim, ltrim, rtrim, upper, lower, collapse, digits (repeatable)
+    #[arg(long = "map-column", value_name = "COLS=STEPS")]
+    map_column: Vec<String>,
 
-    // Parse line terminator
-    let line_terminator = parse_escape_sequence(&args.lineterminator)?;
+    /// recode whole cell values with the 'column,from,to' rows of this csv file, ex. country names to
+    /// ISO codes; values without a row are kept
+    #[arg(long = "map-values", value_name = "FILE")]
+    map_values: Option<String>,
 
-    // Parse sheet delimiter
-    let sheet_delimiter = if args.sheetdelimiter.is_empty() {
-        None
-    } else {
-        Some(parse_escape_sequence(&args.sheetdelimiter)?)
-    };
+    /// replace cell text matching a regex, 'regex=>replacement' optionally scoped as 'Col1,Col2::regex=>replacement' (repeatable)
+    #[arg(long)]
+    replace: Vec<String>,
 
-    // Parse quoting style
-    let quote_style = parse_quote_style(&args.quoting)?;
+    /// stop converting a sheet at the first fully empty row, or after N consecutive empty rows
+    /// with --stop-at-blank-row=N
+    #[arg(long = "stop-at-blank-row", value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "1")]
+    stop_at_blank_row: Option<usize>,
 
-    // Determine if we're reading from stdin
-    if args.xlsxfile == "-" {
-        return Err("Reading from STDIN is not yet supported in this implementation".into());
-    }
+    /// only keep the first data row of each sheet and every Nth after it, a quick thumbnail of a
+    /// large sheet; counts the rows left by --where, --dedup and the other filters
+    #[arg(long, value_name = "N")]
+    every: Option<usize>,
+
+    /// drop rows that exactly duplicate an earlier row of the same sheet
+    #[arg(long)]
+    dedup: bool,
+
+    /// only compare these comma separated header columns when deduplicating, implies --dedup
+    #[arg(long = "dedup-key", value_delimiter = ',')]
+    dedup_key: Vec<String>,
+
+    /// write a data row once per piece of a delimited cell, ex. 'Tags=;' turns 'a; b' into two
+    /// rows; pieces are trimmed and empty ones dropped, repeat for more columns
+    #[arg(long, value_name = "COL=DELIM")]
+    explode: Vec<String>,
+
+    /// pad whole numbers formatted like '00000' in the workbook to the width of their format, write
+    /// them and numbers formatted as text as strings with --infer-types, and infer values with
+    /// leading zeros such as 00420 as strings in --schema and --ddl
+    #[arg(long = "preserve-leading-zeros")]
+    preserve_leading_zeros: bool,
+
+    /// coerce the data cells of the named columns, ex. 'Amount=float,OrderDate=date,ZIP=string';
+    /// types: string, int, float, bool, date (date serials become ISO dates or follow --dateformat)
+    #[arg(long, value_delimiter = ',', value_name = "COL=TYPE")]
+    types: Vec<String>,
+
+    /// what to do with cells that do not fit the type --types gives their column: 'warn' (keep
+    /// them with a warning) or 'fail'
+    #[arg(long = "type-errors", value_enum, default_value = "warn")]
+    type_errors: TypeErrors,
+
+    /// only keep the columns whose content is mostly of these kinds, ex. 'number,date', judged from
+    /// the cells of the first 1000 data rows of each sheet as the workbook stores them
+    #[arg(long = "only-types", value_enum, value_delimiter = ',', value_name = "KINDS")]
+    only_types: Vec<ColumnKind>,
+
+    /// prepend a row_number column holding each row's original 1-based sheet row
+    #[arg(long = "add-row-number")]
+    add_row_number: bool,
+
+    /// prepend a source_ref column holding each row's origin as a cell reference (ex. 'Data'!A12)
+    #[arg(long = "add-source-ref")]
+    add_source_ref: bool,
+
+    /// fail with exit status 8 when a sheet yields no data rows besides its header
+    #[arg(long = "fail-if-empty")]
+    fail_if_empty: bool,
+
+    /// fail with exit status 7 when a sheet has fewer data rows than this, not counting the header
+    #[arg(long = "expect-rows-min", value_name = "ROWS")]
+    expect_rows_min: Option<u64>,
+
+    /// fail with exit status 7 when a sheet has more data rows than this, not counting the header
+    #[arg(long = "expect-rows-max", value_name = "ROWS")]
+    expect_rows_max: Option<u64>,
+
+    /// fail with exit status 7 when the header row of a sheet does not have this many columns
+    #[arg(long = "expect-columns", value_name = "N")]
+    expect_columns: Option<usize>,
+
+    /// fail with exit status 7 when the header row of a sheet, after --rename and
+    /// --snake-case-headers, differs from the comma separated header in this file
+    #[arg(long = "expect-header", value_name = "FILE")]
+    expect_header: Option<String>,
+
+    /// only convert the cells inside this range of each sheet (ex. 'B2:D20')
+    #[arg(long)]
+    range: Option<String>,
+
+    /// maximum number of columns per row, guards against stray or corrupted cell references
+    #[arg(long = "max-columns", default_value_t = 16384)]
+    max_columns: usize,
+
+    /// what to do with cells beyond --max-columns: 'error' or 'clip' (drop them with a warning)
+    #[arg(long = "max-columns-action", value_enum, default_value = "error")]
+    max_columns_action: MaxColumnsAction,
+
+    /// write cell comments/notes of the converted sheets to this csv file (sheet,cell,author,text)
+    #[arg(long)]
+    comments: Option<String>,
+
+    /// append cell comments/notes to the commented cell's value as ' [author: text]'
+    #[arg(long = "append-comments")]
+    append_comments: bool,
+
+    /// emit formula text (ex. '=SUM(A1:A3)') instead of the cached value for formula cells
+    #[arg(long)]
+    formulas: bool,
+
+    /// add number_format, fill and bold columns to --format long, read from each cell's style
+    /// (fills as #RRGGBB, or theme:N for theme colors)
+    #[arg(long = "style-columns")]
+    style_columns: bool,
+
+    /// output layout: 'csv' mirrors the sheet grid, 'long' writes one record per non-empty cell
+    /// (sheet,ref,row,col,type,value, plus formula with --formulas), 'json' writes an array and
+    /// 'jsonl' one line per row of objects keyed by the header row
+    #[arg(long, value_enum, default_value = "csv", env = "XLSX2CSV_FORMAT")]
+    format: OutputFormat,
+
+    /// write the data validation rules of the converted sheets to this csv file
+    /// (sheet,range,type,operator,formula1,formula2,allow_blank)
+    #[arg(long)]
+    validations: Option<String>,
+
+    /// only export rows inside the sheet's autofilter range that match its stored filter criteria
+    #[arg(long = "respect-autofilter")]
+    respect_autofilter: bool,
+
+    /// how formatted runs in strings are rendered: 'plain' text, '**markdown**' or '<b>html</b>'
+    #[arg(long = "rich-text", value_enum, default_value = "plain")]
+    rich_text: RichText,
+
+    /// phonetic (furigana) runs: 'strip' them, 'append' them in parentheses, or write 'only' the
+    /// reading to a '<header>_phonetic' column after each column holding one
+    #[arg(long, value_enum, default_value = "strip")]
+    phonetic: Phonetic,
 
-    let file = File::open(&args.xlsxfile)?;
-    let mut archive = ZipArchive::new(file)?;
+    /// warn about external workbook links and the cells whose formulas reference them
+    #[arg(long = "report-external-links")]
+    report_external_links: bool,
 
-    let rels = load_relationships(&mut archive)?;
-    let sheets = load_sheets(&mut archive, &rels)?;
-    let shared_strings = load_shared_strings(&mut archive)?;
+    /// infer column types, nullability, max length and example values of the converted
+    /// sheets and write them to this JSON file
+    #[arg(long)]
+    schema: Option<String>,
+
+    /// write a JSON report of the comments, formulas, hyperlinks, merged regions, hidden rows and
+    /// missing shared strings of the converted sheets and what the conversion did with them
+    #[arg(long, value_name = "FILE")]
+    audit: Option<String>,
+
+    /// print a CREATE TABLE statement per sheet for this SQL dialect, with types inferred as
+    /// for --schema, instead of the converted data
+    #[arg(long, value_enum)]
+    ddl: Option<SqlDialect>,
+
+    /// with --format json/jsonl, write numbers and booleans as JSON values, dates as ISO
+    /// strings and empty cells as null instead of quoting everything
+    #[arg(long = "infer-types")]
+    infer_types: bool,
+
+    /// write a digest of each produced file next to it as <file>.sha256 (sha256sum format),
+    /// or print it to stderr when writing to STDOUT
+    #[arg(long, value_enum)]
+    checksum: Option<ChecksumAlgorithm>,
 
-    // Filter sheets based on arguments
-    let targets = filter_sheets(
-        sheets,
-        &args.sheetname,
-        args.sheet,
-        args.all,
-        &args.include_sheet_pattern,
-        &args.exclude_sheet_pattern,
-    )?;
+    /// overwrite output files that already exist instead of failing
+    #[arg(long)]
+    force: bool,
 
-    if targets.is_empty() {
-        return Err("No sheets found matching criteria".into());
+    /// print nothing to stderr but errors: no warnings, no progress bar and no log messages below
+    /// error level (-q is --quoting)
+    #[arg(long)]
+    quiet: bool,
+
+    /// do not print warnings about data converted only in part
+    #[arg(long = "no-warnings")]
+    no_warnings: bool,
+
+    /// never show the progress bar, which is otherwise drawn on a terminal for
+    /// workbooks over 8 MiB
+    #[arg(long = "no-progress")]
+    no_progress: bool,
+
+    /// record the last row written in this JSON file every 100000 rows and after each sheet, so an
+    /// interrupted conversion can continue with --resume; needs an output file and csv or jsonl
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<String>,
+
+    /// continue the interrupted conversion recorded in the --checkpoint file, appending to its
+    /// partial output, instead of starting over; starts over when there is no checkpoint
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// send the output as the body of a POST request to this URL instead of writing it, streamed
+    /// while it is converted; fails unless the response status is a success (with the http feature)
+    #[arg(long, value_name = "URL", conflicts_with = "outfile")]
+    post: Option<String>,
+
+    /// extra header of the --post request, as 'Name: value'; can be repeated
+    #[arg(long = "post-header", value_name = "HEADER", requires = "post")]
+    post_headers: Vec<String>,
+
+    /// Content-Type of the --post request (default: text/csv, application/json for --format json,
+    /// application/x-ndjson for jsonl)
+    #[arg(long = "post-content-type", value_name = "TYPE", requires = "post")]
+    post_content_type: Option<String>,
+
+    /// flush the output every N rows instead of when its buffers fill, so a reader of a pipe or
+    /// FIFO gets rows with bounded latency; converts the sheets one at a time
+    #[arg(long = "flush-every", value_name = "N")]
+    flush_every: Option<u64>,
+
+    /// skip the conversion when the workbook's content and the arguments are the same as this JSON
+    /// state file recorded for it, and record them after each successful conversion
+    #[arg(long = "if-changed", value_name = "STATE")]
+    if_changed: Option<String>,
+
+    /// print a summary to stderr once the conversion is done: sheets converted, rows and cells
+    /// written, empty cells, duration, throughput and peak memory
+    #[arg(long)]
+    stats: bool,
+
+    /// memory-map the workbook instead of reading it through system calls, faster for large
+    /// files on local disks; the file must not change while it is converted
+    #[arg(long)]
+    mmap: bool,
+
+    /// number of sheets converted at once (default: one per CPU core); the output keeps the sheet order
+    #[arg(short = 'j', long, value_name = "N", env = "XLSX2CSV_THREADS")]
+    threads: Option<usize>,
+
+    /// also split sheets into chunks of rows converted on --threads threads, for workbooks with
+    /// one huge sheet; not available with options that carry state across rows (ex. --where, --dedup)
+    #[arg(long = "parallel-rows")]
+    parallel_rows: bool,
+
+    /// stay within about this much memory (ex. 512M, 2G) by keeping large shared string tables
+    /// and output buffered by parallel conversions in temporary files
+    #[arg(long = "max-memory", value_name = "SIZE", value_parser = parse_size, env = "XLSX2CSV_MAX_MEMORY")]
+    max_memory: Option<u64>,
+
+    /// sort rows that the file lists out of order by row number and merge repeated rows, for
+    /// files from generators that do so; holds each sheet in memory
+    #[arg(long = "reorder-rows")]
+    reorder_rows: bool,
+
+    /// fail instead of converting what is possible: on cells referring to missing shared
+    /// strings, cells of unknown types or with values that do not fit their type, and undefined
+    /// relationship ids of sheets and hyperlinks
+    #[arg(long)]
+    strict: bool,
+
+    /// what to do with cell text that is not valid UTF-8: 'error', 'replace' each invalid sequence
+    /// with U+FFFD, or 'skip' the invalid bytes
+    #[arg(long = "invalid-utf8", value_enum, default_value = "error")]
+    invalid_utf8: InvalidUtf8,
+
+    /// remove control characters other than tabs and line breaks from cell values
+    #[arg(long = "strip-control-chars")]
+    strip_control_chars: bool,
+
+    /// fail on worksheets or shared strings nesting XML elements deeper than this, a guard for
+    /// untrusted files
+    #[arg(long = "max-xml-depth", value_name = "N", default_value_t = 256)]
+    max_xml_depth: usize,
+
+    /// accept up to this many entity declarations in an XML DOCTYPE (default: 0, xlsx parts have
+    /// none); declared entities are never expanded
+    #[arg(long = "max-entity-expansion", value_name = "N", default_value_t = 0)]
+    max_entity_expansion: usize,
+
+    /// fail on workbooks whose parts inflate to more than this in total (ex. 500M, 20G, default:
+    /// 8G), a guard against zip bombs; 0 for no limit
+    #[arg(long = "max-uncompressed-size", value_name = "SIZE", value_parser = parse_size)]
+    max_uncompressed_size: Option<u64>,
+
+    /// log what the conversion does to stderr, -vv for more detail; RUST_LOG
+    /// (ex. RUST_LOG=xlsx2csv=trace) takes precedence
+    #[arg(short = 'v', long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+impl Args {
+    fn show_warnings(&self) -> bool {
+        !self.quiet && !self.no_warnings
     }
 
-    // Setup output writer
-    let writer: Box<dyn Write> = match &args.outfile {
-        Some(path) if path != "-" => Box::new(BufWriter::new(File::create(path)?)),
-        _ => Box::new(io::stdout()),
-    };
+    fn newline_handling(&self) -> NewlineHandling {
+        match self.newline_handling {
+            Some(handling) => handling,
+            None if self.no_line_breaks => NewlineHandling::Space,
+            None if self.escape => NewlineHandling::Escape,
+            None => NewlineHandling::Keep,
+        }
+    }
 
-    let mut wtr = WriterBuilder::new()
-        .has_headers(false)
-        .flexible(true)
-        .delimiter(delimiter)
-        .quote_style(quote_style)
-        .terminator(csv::Terminator::Any(line_terminator.as_bytes()[0]))
-        .from_writer(writer);
-
-    let mut first_sheet = true;
-    for (sheet_name, path) in targets {
-        // Write sheet delimiter if not first sheet
-        if !first_sheet {
-            if let Some(ref delim) = sheet_delimiter {
-                if let Err(err) = wtr.write_record(&[delim]) {
-                    let boxed: Box<dyn Error> = Box::new(err);
-                    if is_broken_pipe(&*boxed) {
-                        return Ok(());
+    /// Maps the conversion flags onto library [`Options`].
+    fn options(&self) -> Result<Options> {
+        let mut rename = Vec::new();
+        if let Some(path) = &self.rename_file {
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(path)
+                .map_err(|err| Xlsx2CsvError::InvalidOption(format!("Cannot read '{}': {}", path, err)))?;
+            for record in rdr.records() {
+                let record = record.map_err(|err| Xlsx2CsvError::InvalidOption(format!("Cannot read '{}': {}", path, err)))?;
+                match (record.get(0), record.get(1)) {
+                    (Some(old), Some(new)) => rename.push((old.to_string(), new.to_string())),
+                    _ => {
+                        return Err(Xlsx2CsvError::InvalidOption(format!(
+                            "Invalid rename entry in '{}': {:?}",
+                            path, record
+                        )))
                     }
                 }
             }
         }
-        first_sheet = false;
+        for spec in &self.rename {
+            rename.extend(parse_rename_map(spec)?);
+        }
 
-        if let Err(err) = convert_sheet(
-            &mut archive,
-            &path,
-            &shared_strings,
-            &mut wtr,
-            &args,
-        ) {
-            if is_broken_pipe(&*err) {
-                return Ok(());
+        let expected_header = match &self.expect_header {
+            Some(path) => {
+                let mut rdr = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .from_path(path)
+                    .map_err(|err| Xlsx2CsvError::InvalidOption(format!("Cannot read '{}': {}", path, err)))?;
+                match rdr.records().next() {
+                    Some(record) => {
+                        let record = record.map_err(|err| Xlsx2CsvError::InvalidOption(format!("Cannot read '{}': {}", path, err)))?;
+                        Some(record.iter().map(str::to_string).collect())
+                    }
+                    None => return Err(Xlsx2CsvError::InvalidOption(format!("No header in '{}'", path))),
+                }
             }
-            return Err(format!("Failed to read sheet '{sheet_name}': {err}").into());
+            None => None,
+        };
+
+        let mut builder = ConverterBuilder::new();
+        builder
+            .all_sheets(self.all)
+            .delimiter(parse_delimiter(&self.delimiter)?)
+            .line_terminator(parse_escape_sequence(&self.lineterminator)?.as_bytes().first().copied().unwrap_or(b'\n'))
+            .quote_style(parse_quote_style(&self.quoting)?)
+            .format(self.format)
+            .newline_handling(self.newline_handling())
+            .ignore_empty(self.ignoreempty)
+            .skip_empty_columns(self.skipemptycolumns)
+            .max_columns(self.max_columns, self.max_columns_action)
+            .formulas(self.formulas)
+            .style_columns(self.style_columns)
+            .rich_text(self.rich_text)
+            .phonetic(self.phonetic)
+            .infer_types(self.infer_types)
+            .append_comments(self.append_comments)
+            .respect_autofilter(self.respect_autofilter)
+            .report_external_links(self.report_external_links)
+            .snake_case_headers(self.snake_case_headers)
+            .dedup(self.dedup)
+            .dedup_key(self.dedup_key.clone())
+            .preserve_leading_zeros(self.preserve_leading_zeros)
+            .type_errors(self.type_errors)
+            .only_types(self.only_types.clone())
+            .add_row_number(self.add_row_number)
+            .add_source_ref(self.add_source_ref)
+            .fail_if_empty(self.fail_if_empty)
+            .expect_rows(self.expect_rows_min, self.expect_rows_max)
+            .threads(self.threads.unwrap_or(0))
+            .parallel_rows(self.parallel_rows)
+            .flush_every(self.flush_every)
+            .reorder_rows(self.reorder_rows)
+            .strict(self.strict)
+            .max_xml_depth(self.max_xml_depth)
+            .max_entity_expansion(self.max_entity_expansion)
+            .invalid_utf8(self.invalid_utf8)
+            .strip_control_chars(self.strip_control_chars);
+        if let Some(bytes) = self.max_uncompressed_size {
+            builder.max_uncompressed_size(Some(bytes).filter(|&bytes| bytes > 0));
+        }
+        if self.sheetdelimiter.is_empty() {
+            builder.sheet_delimiter(None);
+        } else {
+            builder.sheet_delimiter(Some(parse_escape_sequence(&self.sheetdelimiter)?));
+        }
+        if let Some(name) = &self.sheetname {
+            builder.sheet(name);
+        }
+        if let Some(index) = self.sheet {
+            builder.sheet_index(index);
+        }
+        for pattern in &self.include_sheet_pattern {
+            builder.include_sheet_pattern(pattern);
+        }
+        for pattern in &self.exclude_sheet_pattern {
+            builder.exclude_sheet_pattern(pattern);
+        }
+        if let Some(rows) = self.stop_at_blank_row {
+            builder.stop_at_blank_row(rows);
+        }
+        if let Some(rows) = self.every {
+            builder.every(rows);
+        }
+        if let Some(range) = &self.range {
+            builder.range(range);
+        }
+        if let Some(bytes) = self.max_memory {
+            builder.max_memory(bytes);
+        }
+        if let Some(pattern) = &self.dateformat {
+            builder.date_format(pattern);
+        }
+        if let Some(mode) = self.hyperlinks {
+            builder.hyperlinks(mode);
+        }
+        for (old, new) in rename {
+            builder.rename(old, new);
+        }
+        if let Some(expr) = &self.where_expr {
+            builder.where_expr(expr);
+        }
+        for spec in &self.map_column {
+            builder.map_column(spec);
+        }
+        if let Some(path) = &self.map_values {
+            builder.map_values(path);
+        }
+        for rule in &self.types {
+            builder.column_type(rule);
         }
+        for rule in &self.replace {
+            builder.replace(rule);
+        }
+        for rule in &self.explode {
+            builder.explode(rule);
+        }
+        if let Some(path) = &self.hyperlinks_file {
+            builder.hyperlinks_file(path);
+        }
+        if let Some(path) = &self.comments {
+            builder.comments(path);
+        }
+        if let Some(path) = &self.validations {
+            builder.validations(path);
+        }
+        if let Some(path) = &self.schema {
+            builder.schema(path);
+        }
+        if let Some(path) = &self.audit {
+            builder.audit(path);
+        }
+        if let Some(dialect) = self.ddl {
+            builder.ddl(dialect);
+        }
+        if let Some(columns) = self.expect_columns {
+            builder.expect_columns(columns);
+        }
+        if let Some(header) = expected_header {
+            builder.expect_header(header);
+        }
+        builder.build()
     }
+}
 
-    if let Err(err) = wtr.flush() {
-        if is_broken_pipe(&err) {
-            return Ok(());
+#[derive(Subcommand)]
+enum Command {
+    /// convert sheets to csv or another format, the default when no subcommand is given
+    Convert(Box<Args>),
+    /// list the sheets (index, name, visibility, relationship id, part path and dimension) as csv
+    Sheets {
+        /// xlsx file path
+        xlsxfile: String,
+    },
+    /// show the first rows of a sheet as an aligned table, the first row as its header
+    Preview {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet name or 1-based number (default: the first sheet)
+        sheet: Option<String>,
+
+        /// number of rows shown, the header row included
+        #[arg(short = 'n', long, default_value_t = 20)]
+        rows: usize,
+
+        /// bold header and red error cells: 'auto' when writing to a terminal, 'always' or 'never'
+        #[arg(long, value_enum, default_value = "auto")]
+        color: ColorChoice,
+    },
+    /// check zip integrity, required parts, relationships, shared string indexes and sheet
+    /// dimensions, then read every sheet with the checks of --strict, without writing any output;
+    /// every structural problem is listed
+    Validate {
+        /// xlsx file path
+        xlsxfile: String,
+    },
+    /// print document properties, sheet count, defined names and the date system
+    Meta {
+        /// xlsx file path
+        xlsxfile: String,
+    },
+    /// list defined names as csv with their scope and refers-to ranges
+    Names {
+        /// xlsx file path
+        xlsxfile: String,
+    },
+    /// list images and embedded files as csv, optionally extracting them
+    Media {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// directory to extract the media files into, under their path in the workbook's xl/ folder
+        /// (ex. media/image1.png)
+        #[arg(long = "extract-to")]
+        extract_to: Option<PathBuf>,
+
+        /// overwrite files that already exist in the --extract-to directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// report per-sheet row, column and cell counts, cell types and data density as csv
+    Stats {
+        /// xlsx file path
+        xlsxfile: String,
+    },
+    /// convert every sheet to nowhere and report zip inflation and xml parsing times, throughput
+    /// and peak memory
+    Bench {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// number of sheets converted at once (default: one per CPU core)
+        #[arg(short = 'j', long, value_name = "N")]
+        threads: Option<usize>,
+    },
+    /// compare the cell values of two workbooks and print the cells that differ (sheet, ref, old
+    /// and new value); a sheet missing from one workbook compares as empty
+    Diff {
+        /// xlsx file with the old values
+        old: String,
+
+        /// xlsx file with the new values
+        new: String,
+
+        /// compare only this sheet instead of every sheet of either workbook
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// output format of the changed cells
+        #[arg(long, value_enum, default_value = "csv")]
+        format: DiffFormat,
+    },
+    /// concatenate one sheet of many workbooks into one csv, writing the header row once; the
+    /// header must be the same in every workbook
+    Cat {
+        /// xlsx file paths
+        #[arg(required = true)]
+        xlsxfiles: Vec<String>,
+
+        /// sheet to read from each workbook, the first sheet by default
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// csv file to write instead of STDOUT
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// add a 'filename' column in front holding the workbook each row comes from
+        #[arg(long = "add-filename")]
+        add_filename: bool,
+
+        /// overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// print the first rows of a sheet as csv without reading the rest of it
+    Head {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet name or 1-based number, the first sheet by default
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// number of rows
+        #[arg(short = 'n', long, default_value_t = 10)]
+        rows: usize,
+    },
+    /// print the last rows of a sheet as csv, keeping only those rows in memory
+    Tail {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet name or 1-based number, the first sheet by default
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// number of rows
+        #[arg(short = 'n', long, default_value_t = 10)]
+        rows: usize,
+    },
+    /// print one csv row per group of a sheet with the given aggregates, ex. "--group-by Region
+    /// --sum Amount --count '*'"; the aggregates follow the group columns, counts first, then sums,
+    /// averages, minimums and maximums
+    Agg {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet name or 1-based number, the first sheet by default
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// columns whose values form the groups, comma separated (default: one group of every row)
+        #[arg(long, value_delimiter = ',')]
+        group_by: Vec<String>,
+
+        /// count the non-empty cells of a column, or the rows with '*'
+        #[arg(long, value_name = "COLUMN")]
+        count: Vec<String>,
+
+        /// sum the numbers of a column
+        #[arg(long, value_name = "COLUMN")]
+        sum: Vec<String>,
+
+        /// average the numbers of a column
+        #[arg(long, value_name = "COLUMN")]
+        avg: Vec<String>,
+
+        /// smallest value of a column
+        #[arg(long, value_name = "COLUMN")]
+        min: Vec<String>,
+
+        /// largest value of a column
+        #[arg(long, value_name = "COLUMN")]
+        max: Vec<String>,
+    },
+    /// run a SQL query over the rows of a sheet and print the result as csv, ex.
+    /// "SELECT region, SUM(amount) FROM 'Sales' GROUP BY region ORDER BY 2 DESC"; supports WHERE,
+    /// GROUP BY, ORDER BY, LIMIT and COUNT, SUM, AVG, MIN and MAX, the first row naming the columns
+    Query {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// the SELECT statement
+        sql: String,
+    },
+    /// print the header row and a random sample of the other rows of a sheet as csv, reading the
+    /// sheet once and keeping only the sample in memory
+    Sample {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet name or 1-based number, the first sheet by default
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// number of rows to sample
+        #[arg(short = 'n', long, default_value_t = 100)]
+        rows: usize,
+
+        /// seed of the random choice, the same seed picks the same rows (default: random)
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// join the rows of two sheets of a workbook on a key column and print them as csv
+    Join {
+        /// xlsx file path
+        xlsxfile: String,
+
+        /// sheet whose rows are all streamed, by name or 1-based number
+        #[arg(long)]
+        left: String,
+
+        /// sheet looked up by key, held in memory
+        #[arg(long)]
+        right: String,
+
+        /// key column in both sheets, or 'left_name=right_name' when they differ
+        #[arg(long)]
+        on: String,
+
+        /// 'inner' keeps only left rows with a match, 'left' keeps every left row
+        #[arg(long, value_enum, default_value = "inner")]
+        how: JoinKind,
+    },
+    /// write a csv file as the only sheet of a new xlsx workbook, numbers as number cells and
+    /// everything else as strings
+    #[command(name = "from-csv")]
+    FromCsv {
+        /// csv file path, use '-' to read from STDIN
+        csvfile: String,
+
+        /// xlsx file to write
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// name of the sheet
+        #[arg(long, default_value = "Sheet1")]
+        sheet: String,
+
+        /// columns delimiter of the csv, 'tab' or 'x09' for a tab
+        #[arg(short = 'd', long, default_value = ",")]
+        delimiter: String,
+
+        /// overwrite the xlsx file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// print a completion script for the shell, ex. 'xlsx2csv completions bash > /etc/bash_completion.d/xlsx2csv'
+    Completions {
+        /// bash, zsh, fish, powershell or elvish
+        shell: Shell,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let args = match &cli.command {
+        Some(Command::Convert(args)) => args,
+        _ => &cli.args,
+    };
+    init_logging(args.verbose, args.quiet);
+
+    // The output is UTF-8, which programs reading it through a pipe in the
+    // same console decode with the console code page
+    #[cfg(windows)]
+    let code_page = windows_console::use_utf8();
+    let result = run(&cli);
+    #[cfg(windows)]
+    windows_console::restore(code_page);
+
+    if let Err(err) = result {
+        match cli.error_format {
+            ErrorFormat::Text => eprintln!("Error: {}", err),
+            ErrorFormat::Json => {
+                let (sheet, cell) = error_location(&err);
+                print_json_diagnostic(&serde_json::json!({
+                    "level": "error",
+                    "code": exit_code(&err),
+                    "message": err.to_string(),
+                    "file": input_file(&cli),
+                    "sheet": sheet,
+                    "cell": cell,
+                }));
+            }
         }
-        return Err(err.into());
+        process::exit(exit_code(&err));
     }
-    Ok(())
 }
 
-fn parse_delimiter(s: &str) -> BoxResult<u8> {
-    match s {
-        "tab" | "\\t" | "x09" => Ok(b'\t'),
-        s if s.len() == 1 => Ok(s.as_bytes()[0]),
-        _ => Err(format!("Invalid delimiter: {}", s).into()),
+/// The console code page. Writing to the console itself needs no conversion,
+/// the standard library writes UTF-16 there. Paths need nothing either: the
+/// standard library passes them to Windows as UTF-16 and adds the `\\?\`
+/// prefix to those longer than `MAX_PATH`.
+#[cfg(windows)]
+mod windows_console {
+    const CP_UTF8: u32 = 65001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+    }
+
+    /// Switches the console to UTF-8, returning the code page to restore;
+    /// `None` without a console or when it already uses UTF-8.
+    pub fn use_utf8() -> Option<u32> {
+        // SAFETY: these calls only read and set the code page of the attached console
+        let previous = unsafe { GetConsoleOutputCP() };
+        (previous != 0 && previous != CP_UTF8 && unsafe { SetConsoleOutputCP(CP_UTF8) } != 0).then_some(previous)
+    }
+
+    pub fn restore(code_page: Option<u32>) {
+        if let Some(code_page) = code_page {
+            // SAFETY: see use_utf8
+            unsafe { SetConsoleOutputCP(code_page) };
+        }
     }
 }
 
-fn parse_escape_sequence(s: &str) -> BoxResult<String> {
-    Ok(s.replace("\\n", "\n")
-        .replace("\\r", "\r")
-        .replace("\\t", "\t")
-        .replace("\\f", "\x0C")
-        .replace("x07", "\x07")
-        .replace("x09", "\t"))
+/// The xlsx file the command reads.
+fn input_file(cli: &Cli) -> Option<&str> {
+    match &cli.command {
+        None => cli.args.xlsxfile.as_deref(),
+        Some(Command::Convert(args)) => args.xlsxfile.as_deref(),
+        Some(
+            Command::Sheets { xlsxfile }
+            | Command::Preview { xlsxfile, .. }
+            | Command::Validate { xlsxfile }
+            | Command::Meta { xlsxfile }
+            | Command::Names { xlsxfile }
+            | Command::Media { xlsxfile, .. }
+            | Command::Stats { xlsxfile }
+            | Command::Bench { xlsxfile, .. }
+            | Command::Head { xlsxfile, .. }
+            | Command::Tail { xlsxfile, .. }
+            | Command::Query { xlsxfile, .. }
+            | Command::Agg { xlsxfile, .. }
+            | Command::Sample { xlsxfile, .. }
+            | Command::Join { xlsxfile, .. },
+        ) => Some(xlsxfile),
+        Some(Command::Diff { old, .. }) => Some(old),
+        Some(Command::Cat { xlsxfiles, .. }) => xlsxfiles.first().map(String::as_str),
+        Some(Command::FromCsv { csvfile, .. }) => Some(csvfile),
+        Some(Command::Completions { .. }) => None,
+    }
 }
 
-fn parse_quote_style(s: &str) -> BoxResult<QuoteStyle> {
-    match s {
-        "none" => Ok(QuoteStyle::Never),
-        "minimal" => Ok(QuoteStyle::Necessary),
-        "nonnumeric" => Ok(QuoteStyle::NonNumeric),
-        "all" => Ok(QuoteStyle::Always),
-        _ => Err(format!("Invalid quoting style: {}", s).into()),
+/// The sheet and the cell a failure happened at, when known.
+fn error_location(err: &Xlsx2CsvError) -> (Option<&str>, Option<&str>) {
+    match err {
+        Xlsx2CsvError::Sheet { name, source } => (Some(name), error_location(source).1),
+        Xlsx2CsvError::InvalidCell { cell, .. }
+        | Xlsx2CsvError::SharedStringOutOfRange { cell, .. }
+        | Xlsx2CsvError::ColumnLimit { cell, .. } => (None, Some(cell)),
+        _ => (None, None),
     }
 }
 
-fn filter_sheets(
-    sheets: Vec<(String, String)>,
-    sheetname: &Option<String>,
-    sheet_id: Option<usize>,
-    all: bool,
-    include_patterns: &[String],
-    exclude_patterns: &[String],
-) -> BoxResult<Vec<(String, String)>> {
-    // If specific sheet name is requested
-    if let Some(name) = sheetname {
-        let targets: Vec<(String, String)> = sheets
-            .into_iter()
-            .filter(|(sheet, _)| sheet == name)
-            .collect();
-        if targets.is_empty() {
-            return Err(format!("Cannot find sheet named '{}'", name).into());
-        }
-        return Ok(targets);
+/// Prints the `--stats` summary of a conversion that read `input_bytes` of
+/// workbook in `elapsed`.
+fn print_run_stats(totals: &Totals, input_bytes: u64, elapsed: Duration) {
+    let rows = totals.rows.load(Ordering::Relaxed);
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+    eprintln!("sheets converted: {}", totals.sheets.load(Ordering::Relaxed));
+    eprintln!("rows written: {}", rows);
+    eprintln!("cells written: {}", totals.cells.load(Ordering::Relaxed));
+    eprintln!("empty cells: {}", totals.empty_cells.load(Ordering::Relaxed));
+    eprintln!("duration: {:.3} s", elapsed.as_secs_f64());
+    eprintln!("throughput: {:.0} rows/s, {:.1} MB/s of xlsx", rows as f64 / seconds, input_bytes as f64 / 1e6 / seconds);
+    match bench::peak_rss() {
+        Some(bytes) => eprintln!("peak memory: {:.1} MB", bytes as f64 / 1e6),
+        None => eprintln!("peak memory: unavailable on this platform"),
+    }
+}
+
+/// Prints a warning in `format`, `file` being the xlsx file it is about.
+fn print_warning(format: ErrorFormat, file: &str, warning: &Warning) {
+    match format {
+        ErrorFormat::Text => eprintln!("Warning: {}", warning),
+        ErrorFormat::Json => print_json_diagnostic(&serde_json::json!({
+            "level": "warning",
+            "message": warning.message,
+            "file": file,
+            "sheet": warning.sheet,
+            "cell": warning.cell,
+        })),
     }
+}
+
+/// Writes a diagnostic of `--error-format json` as one line to stderr.
+fn print_json_diagnostic(diagnostic: &serde_json::Value) {
+    eprintln!("{}", diagnostic);
+}
 
-    // If specific sheet ID is requested
-    if let Some(id) = sheet_id {
-        if id == 0 || id > sheets.len() {
-            return Err(format!("Sheet ID {} out of range (1-{})", id, sheets.len()).into());
+/// The exit status of a failure, by its cause.
+fn exit_code(err: &Xlsx2CsvError) -> i32 {
+    match err {
+        Xlsx2CsvError::Sheet { source, .. } => exit_code(source),
+        Xlsx2CsvError::InvalidOption(_)
+        | Xlsx2CsvError::Expression(_)
+        | Xlsx2CsvError::Query(_)
+        | Xlsx2CsvError::Regex(_)
+        | Xlsx2CsvError::UnknownColumn { .. } => EXIT_USAGE,
+        Xlsx2CsvError::InputFile { .. } | Xlsx2CsvError::Zip(_) => EXIT_INPUT,
+        Xlsx2CsvError::NoSheets
+        | Xlsx2CsvError::SheetNotFound { .. }
+        | Xlsx2CsvError::SheetIndexOutOfRange { .. }
+        | Xlsx2CsvError::NoMatchingSheets => EXIT_SHEET_NOT_FOUND,
+        Xlsx2CsvError::Xml(_)
+        | Xlsx2CsvError::ColumnLimit { .. }
+        | Xlsx2CsvError::SharedStringOutOfRange { .. }
+        | Xlsx2CsvError::InvalidCell { .. }
+        | Xlsx2CsvError::InvalidUtf8(_)
+        | Xlsx2CsvError::XmlLimit(_)
+        | Xlsx2CsvError::UncompressedSizeLimit { .. }
+        | Xlsx2CsvError::UnresolvedRelationship { .. }
+        | Xlsx2CsvError::ConflictingSheets { .. }
+        | Xlsx2CsvError::Invalid(_) => EXIT_PARSE,
+        // Parts that do not inflate are read through io errors
+        Xlsx2CsvError::Io(err) if matches!(err.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) => {
+            EXIT_PARSE
         }
-        return Ok(vec![sheets[id - 1].clone()]);
+        Xlsx2CsvError::Io(_) | Xlsx2CsvError::Csv(_) | Xlsx2CsvError::OutputExists(_) => EXIT_OUTPUT,
+        Xlsx2CsvError::Expectation(_) => EXIT_EXPECTATION,
+        Xlsx2CsvError::NoDataRows => EXIT_EMPTY,
+        Xlsx2CsvError::Cancelled => EXIT_INTERRUPTED,
+        Xlsx2CsvError::HeaderMismatch { .. } | Xlsx2CsvError::Custom(_) => EXIT_FAILURE,
     }
+}
 
-    // If all sheets or pattern matching
-    let mut targets = if all {
-        sheets
-    } else {
-        // Default to first sheet if not using -a
-        sheets.into_iter().take(1).collect()
-    };
+/// Sends `tracing` events to stderr, filtered by RUST_LOG or else by the
+/// number of -v flags.
+fn init_logging(verbose: u8, quiet: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(match verbose {
+            0 if quiet => "error",
+            0 => "warn",
+            1 => "xlsx2csv=debug",
+            _ => "xlsx2csv=trace",
+        })
+    });
+    let span_events = if verbose > 0 { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
 
-    // Apply include patterns if specified
-    if !include_patterns.is_empty() {
-        let patterns: Vec<Regex> = include_patterns
-            .iter()
-            .map(|p| Regex::new(p))
-            .collect::<Result<Vec<_>, _>>()?;
+fn run(cli: &Cli) -> Result<()> {
+    let args = match &cli.command {
+        None => &cli.args,
+        Some(Command::Convert(args)) => args,
+        Some(command) => return run_command(command, cli.error_format),
+    };
+    if args.list_sheets {
+        let xlsxfile = args.xlsxfile.as_deref().unwrap_or_default();
+        let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+        return meta::print_sheets(&mut archive, io::stdout().lock());
+    }
+    convert(args, cli.error_format)
+}
 
-        targets = targets
+/// Runs the subcommands other than `convert`.
+fn run_command(command: &Command, format: ErrorFormat) -> Result<()> {
+    match command {
+        Command::Convert(args) => convert(args, format),
+        Command::Sheets { xlsxfile } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            meta::print_sheets(&mut archive, io::stdout().lock())
+        }
+        Command::Preview { xlsxfile, sheet, rows, color } => {
+            let color = match color {
+                ColorChoice::Auto => io::stdout().is_terminal(),
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+            };
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            match preview::print_preview(&mut workbook, sheet.as_deref(), *rows, color, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Validate { xlsxfile } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            let problems = validate::validate(&mut archive)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("{}: {}", xlsxfile, problem);
+                }
+                return Err(Xlsx2CsvError::Invalid(problems.len()));
+            }
+            // Whatever the structural checks let through must also convert
+            let options = Options {
+                all_sheets: true,
+                strict: true,
+                ..Options::default()
+            };
+            xlsx2csv::convert(open_input(xlsxfile)?, &options, io::sink())?;
+            println!("{}: ok", xlsxfile);
+            Ok(())
+        }
+        Command::Meta { xlsxfile } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            let mut out = io::stdout().lock();
+            meta::print_meta(&mut archive, &mut out)
+        }
+        Command::Names { xlsxfile } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            meta::print_names(&mut archive, io::stdout().lock())
+        }
+        Command::Media { xlsxfile, extract_to, force } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            media::media(&mut archive, extract_to.as_deref(), *force, io::stdout().lock())
+        }
+        Command::Stats { xlsxfile } => {
+            let mut archive = ZipArchive::new(open_input(xlsxfile)?)?;
+            stats::print_stats(&mut archive, io::stdout().lock())
+        }
+        Command::Bench { xlsxfile, threads } => {
+            let data = fs::read(xlsxfile).map_err(|source| Xlsx2CsvError::InputFile { path: xlsxfile.into(), source })?;
+            let options = Options {
+                threads: threads.unwrap_or(0),
+                ..Options::default()
+            };
+            bench::print_bench(&data, &options, &mut io::stdout().lock())
+        }
+        Command::Diff { old, new, sheet, format } => {
+            let mut old = Workbook::new(BufReader::new(open_input(old)?))?;
+            let mut new = Workbook::new(BufReader::new(open_input(new)?))?;
+            match diff::print_diff(&mut old, &mut new, sheet.as_deref(), *format, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result.map(drop),
+            }
+        }
+        Command::FromCsv { csvfile, output, sheet, delimiter, force } => {
+            let delimiter = parse_delimiter(delimiter)?;
+            let input: Box<dyn io::Read> = if csvfile == "-" {
+                Box::new(io::stdin().lock())
+            } else {
+                Box::new(open_input(csvfile)?)
+            };
+            write_staged(output, *force, |out| from_csv::write_xlsx(BufReader::new(input), delimiter, sheet, out))
+        }
+        Command::Head { xlsxfile, sheet, rows } | Command::Tail { xlsxfile, sheet, rows } => {
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            let out = io::stdout().lock();
+            let result = if matches!(command, Command::Head { .. }) {
+                head::print_head(&mut workbook, sheet.as_deref(), *rows, out)
+            } else {
+                head::print_tail(&mut workbook, sheet.as_deref(), *rows, out)
+            };
+            match result {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Sample { xlsxfile, sheet, rows, seed } => {
+            let seed = seed.unwrap_or_else(|| {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+                now.as_nanos() as u64 ^ u64::from(process::id())
+            });
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            match sample::print_sample(&mut workbook, sheet.as_deref(), *rows, seed, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Join { xlsxfile, left, right, on, how } => {
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            match join::print_join(&mut workbook, left, right, on, *how, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Agg { xlsxfile, sheet, group_by, count, sum, avg, min, max } => {
+            let aggregates: Vec<(Aggregate, Option<String>)> = [
+                (Aggregate::Count, count),
+                (Aggregate::Sum, sum),
+                (Aggregate::Avg, avg),
+                (Aggregate::Min, min),
+                (Aggregate::Max, max),
+            ]
             .into_iter()
-            .filter(|(name, _)| patterns.iter().any(|p| p.is_match(name)))
+            .flat_map(|(aggregate, columns)| {
+                columns.iter().map(move |column| (aggregate, Some(column.clone()).filter(|column| column != "*")))
+            })
             .collect();
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            match query::print_aggregate(&mut workbook, sheet.as_deref(), group_by, &aggregates, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Query { xlsxfile, sql } => {
+            let mut workbook = Workbook::new(BufReader::new(open_input(xlsxfile)?))?;
+            match query::run_query(&mut workbook, sql, io::stdout().lock()) {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Cat { xlsxfiles, sheet, output, add_filename, force } => {
+            let result = match output {
+                Some(path) => write_staged(path, *force, |out| {
+                    cat::print_cat(xlsxfiles, sheet.as_deref(), *add_filename, out).map(drop)
+                }),
+                None => cat::print_cat(xlsxfiles, sheet.as_deref(), *add_filename, io::stdout().lock()).map(drop),
+            };
+            match result {
+                Err(err) if is_broken_pipe(&err) => Ok(()),
+                result => result,
+            }
+        }
+        Command::Completions { shell } => {
+            // clap_complete panics on write errors, so the script is written here
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Cli::command(), "xlsx2csv", &mut script);
+            match io::stdout().write_all(&script) {
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                result => Ok(result?),
+            }
+        }
     }
+}
 
-    // Apply exclude patterns if specified
-    if !exclude_patterns.is_empty() {
-        let patterns: Vec<Regex> = exclude_patterns
-            .iter()
-            .map(|p| Regex::new(p))
-            .collect::<Result<Vec<_>, _>>()?;
+fn convert(args: &Args, format: ErrorFormat) -> Result<()> {
+    let Some(xlsxfile) = args.xlsxfile.as_deref() else {
+        return Err(Xlsx2CsvError::InvalidOption("Missing xlsx file path".to_string()));
+    };
 
-        targets = targets
-            .into_iter()
-            .filter(|(name, _)| !patterns.iter().any(|p| p.is_match(name)))
-            .collect();
+    // Validate encoding
+    if args.outputencoding != "utf-8" && args.show_warnings() {
+        let message = "Only UTF-8 encoding is supported in this Rust implementation".to_string();
+        print_warning(format, xlsxfile, &Warning { message, sheet: None, cell: None });
     }
 
-    if targets.is_empty() {
-        return Err("No sheets found matching criteria".into());
+    let mut options = args.options()?;
+    if !args.show_warnings() {
+        options.warning = Some(Arc::new(|_: &Warning| {}));
+    } else if format == ErrorFormat::Json {
+        let file = xlsxfile.to_string();
+        options.warning = Some(Arc::new(move |warning: &Warning| print_warning(format, &file, warning)));
     }
 
-    Ok(targets)
-}
+    // The first Ctrl-C stops after the current row and removes the partial
+    // output, a second one exits right away
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&cancel);
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Cancelling, press Ctrl-C again to exit immediately");
+    })
+    .map_err(|err| Xlsx2CsvError::Custom(Box::new(err)))?;
+    options.cancel = Some(cancel);
 
-fn load_relationships<R: Read + Seek>(archive: &mut ZipArchive<R>) -> BoxResult<HashMap<String, String>> {
-    let mut map = HashMap::new();
-    let Ok(file) = archive.by_name("xl/_rels/workbook.xml.rels") else {
-        return Ok(map);
-    };
+    // Determine if we're reading from stdin
+    if xlsxfile == "-" {
+        return Err(Xlsx2CsvError::InvalidOption(
+            "Reading from STDIN is not yet supported in this implementation".to_string(),
+        ));
+    }
 
-    let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.trim_text(true);
-    let mut buf = Vec::new();
+    let selected = args.sheetname.is_some() || args.sheet.is_some() || !args.include_sheet_pattern.is_empty();
+    if args.interactive && !selected && io::stdin().is_terminal() && io::stderr().is_terminal() {
+        pick_sheets(xlsxfile, &mut options)?;
+    }
 
-    loop {
-        match reader.read_event_into(&mut buf)? {
-            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"Relationship") => {
-                let mut id = None;
-                let mut target = None;
-                for attr in e.attributes() {
-                    let attr = attr?;
-                    match attr.key.as_ref() {
-                        b"Id" => id = Some(attr.unescape_value()?.into_owned()),
-                        b"Target" => target = Some(attr.unescape_value()?.into_owned()),
-                        _ => {}
-                    }
-                }
-                if let (Some(id), Some(target)) = (id, target) {
-                    map.insert(id, target);
-                }
+    let file = open_input(xlsxfile)?;
+    // Converted before from the same content with the same arguments
+    let mut change_state = None;
+    if let Some(state_path) = &args.if_changed {
+        let state = ChangeState::load(state_path)?;
+        let input = fs::canonicalize(xlsxfile).map_or(xlsxfile.to_string(), |path| path.display().to_string());
+        let digest = checksum::file_digest(xlsxfile)?;
+        let arguments: Vec<String> = env::args().skip(1).collect();
+        let output_exists = args.outfile.as_ref().filter(|path| *path != "-").is_none_or(|path| Path::new(path).exists());
+        if output_exists && state.is_unchanged(&input, &digest, &arguments) {
+            if !args.quiet {
+                eprintln!("Skipping '{}': unchanged since the last conversion", xlsxfile);
             }
-            Event::Eof => break,
-            _ => {}
+            return Ok(());
         }
+        change_state = Some((state, input, digest, arguments));
     }
 
-    Ok(map)
-}
+    let progress_bar = progress_bar(args, &file);
+    if let Some(bar) = &progress_bar {
+        let bar = bar.clone();
+        options.progress = Some(Arc::new(move |progress: &Progress| {
+            bar.set_length(progress.total_bytes);
+            bar.set_position(progress.bytes_read);
+            bar.set_message(format!("{} ({} rows)", progress.sheet, progress.rows_written));
+        }));
+    }
 
-fn load_sheets<R: Read + Seek>(
-    archive: &mut ZipArchive<R>,
-    rels: &HashMap<String, String>,
-) -> BoxResult<Vec<(String, String)>> {
-    let file = archive.by_name("xl/workbook.xml")?;
-    let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.trim_text(true);
-    let mut buf = Vec::new();
+    let totals = args.stats.then(|| Arc::new(Totals::default()));
+    options.totals = totals.clone();
+    let input_bytes = file.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+    let started = Instant::now();
 
-    let mut sheets = Vec::new();
-    loop {
-        match reader.read_event_into(&mut buf)? {
-            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"sheet") => {
-                let mut name = None;
-                let mut rel_id = None;
-                for attr in e.attributes() {
-                    let attr = attr?;
-                    match attr.key.as_ref() {
-                        b"name" => name = Some(attr.unescape_value()?.into_owned()),
-                        b"r:id" => rel_id = Some(attr.unescape_value()?.into_owned()),
-                        _ => {}
-                    }
-                }
-                if let (Some(name), Some(rel_id)) = (name, rel_id) {
-                    if let Some(target) = rels.get(&rel_id) {
-                        sheets.push((name, normalize_sheet_path(target)));
-                    }
+    // Every output file is written under a temporary name and only renamed
+    // into place once the conversion succeeds
+    let mut staged = StagedOutputs::default();
+    if options.hyperlinks == Some(HyperlinkMode::SeparateFile) {
+        staged.stage(&mut options.hyperlinks_file);
+    }
+    staged.stage(&mut options.comments);
+    staged.stage(&mut options.validations);
+    staged.stage(&mut options.schema);
+    staged.stage(&mut options.audit);
+    // Uploads only become visible once complete, so they are not staged
+    let remote = args.outfile.as_deref().filter(|path| path.starts_with("s3://"));
+    #[cfg(not(feature = "s3"))]
+    if remote.is_some() {
+        return Err(Xlsx2CsvError::InvalidOption("s3:// outputs need xlsx2csv built with the s3 feature".to_string()));
+    }
+    #[cfg(feature = "s3")]
+    let mut upload = remote.map(S3Upload::create).transpose()?;
+    #[cfg(not(feature = "http"))]
+    if args.post.is_some() {
+        return Err(Xlsx2CsvError::InvalidOption("--post needs xlsx2csv built with the http feature".to_string()));
+    }
+    let mut outfile = args.outfile.as_ref().filter(|path| *path != "-" && remote.is_none()).map(PathBuf::from);
+    staged.stage(&mut outfile);
+    if !args.force {
+        staged.refuse_overwrite()?;
+    }
+
+    // The partial output of an interrupted conversion is kept to resume it
+    let mut resume_from = None;
+    if let Some(checkpoint_path) = &args.checkpoint {
+        let Some(temp) = outfile.clone() else {
+            return Err(Xlsx2CsvError::InvalidOption("--checkpoint needs a local output file".to_string()));
+        };
+        let checkpoint_path = PathBuf::from(checkpoint_path);
+        let digest = checksum::file_digest(xlsxfile)?;
+        if args.resume {
+            if args.checksum.is_some() {
+                return Err(Xlsx2CsvError::InvalidOption("--resume cannot be combined with --checksum".to_string()));
+            }
+            if let Some(checkpoint) = CheckpointFile::load(&checkpoint_path)? {
+                if checkpoint.sha256 != digest {
+                    return Err(Xlsx2CsvError::InvalidOption(format!(
+                        "'{}' changed since the checkpoint, convert it again without --resume",
+                        xlsxfile
+                    )));
                 }
+                options.resume = Some(checkpoint.resume_point());
+                resume_from = Some(checkpoint.output_bytes);
             }
-            Event::Eof => break,
-            _ => {}
         }
+        let output = temp.clone();
+        options.checkpoint = Some(Arc::new(move |checkpoint: &Checkpoint| {
+            CheckpointFile {
+                sha256: digest.clone(),
+                sheet: checkpoint.sheet.to_string(),
+                row: checkpoint.row,
+                complete: checkpoint.complete,
+                output_bytes: fs::metadata(&output)?.len(),
+            }
+            .save(&checkpoint_path)
+        }));
+        staged.keep = Some(temp);
     }
 
-    if sheets.is_empty() {
-        return Err("No sheets found in workbook".into());
+    #[cfg(feature = "http")]
+    let mut post = match &args.post {
+        Some(url) => {
+            let content_type = args.post_content_type.as_deref().unwrap_or(match args.format {
+                OutputFormat::Json => "application/json",
+                OutputFormat::Jsonl => "application/x-ndjson",
+                OutputFormat::Csv | OutputFormat::Long => "text/csv",
+            });
+            Some(HttpPost::start(url, content_type, &args.post_headers)?)
+        }
+        None => None,
+    };
+    // Setup output writer
+    let mut writer: Box<dyn Write + '_> = match (&outfile, resume_from) {
+        (Some(path), Some(output_bytes)) => {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .map_err(|err| io::Error::new(err.kind(), format!("Cannot resume '{}': {}", path.display(), err)))?;
+            if file.metadata()?.len() < output_bytes {
+                return Err(Xlsx2CsvError::InvalidOption(format!(
+                    "'{}' is shorter than the checkpoint, convert again without --resume",
+                    path.display()
+                )));
+            }
+            // Rows written after the checkpoint are written again
+            file.set_len(output_bytes)?;
+            file.seek(SeekFrom::End(0))?;
+            Box::new(BufWriter::new(file))
+        }
+        (Some(path), None) => {
+            let file = File::create(path)
+                .map_err(|err| io::Error::new(err.kind(), format!("Cannot create '{}': {}", path.display(), err)))?;
+            Box::new(BufWriter::new(file))
+        }
+        (None, _) => Box::new(io::stdout()),
+    };
+    #[cfg(feature = "s3")]
+    if let Some(upload) = upload.as_mut() {
+        writer = Box::new(upload);
+    }
+    #[cfg(feature = "http")]
+    if let Some(post) = post.as_mut() {
+        writer = Box::new(post);
+    }
+    let mut output_digest = None;
+    if args.checksum.is_some() {
+        let (hashing, hasher) = HashingWriter::new(writer);
+        writer = Box::new(hashing);
+        output_digest = Some(hasher);
     }
 
-    Ok(sheets)
-}
-
-fn normalize_sheet_path(target: &str) -> String {
-    let cleaned = target.trim_start_matches('/');
-    if cleaned.starts_with("xl/") {
-        cleaned.to_string()
+    let result = if args.mmap {
+        // SAFETY: the mapping is only read, and --mmap documents that the file must
+        // not be modified by other processes during the conversion
+        let map = unsafe { Mmap::map(&file)? };
+        xlsx2csv::convert(Cursor::new(&map[..]), &options, writer)
     } else {
-        format!("xl/{}", cleaned)
+        xlsx2csv::convert(file, &options, writer)
+    };
+    if let Some(bar) = progress_bar {
+        bar.finish_and_clear();
+    }
+    if let Err(err) = result {
+        staged.discard();
+        #[cfg(feature = "s3")]
+        if let Some(upload) = &upload {
+            upload.abort();
+        }
+        if is_broken_pipe(&err) {
+            return Ok(());
+        }
+        return Err(err);
+    }
+    #[cfg(feature = "http")]
+    if let Some(post) = post {
+        if let Err(err) = post.finish() {
+            staged.discard();
+            return Err(err);
+        }
+    }
+    #[cfg(feature = "s3")]
+    if let Some(upload) = upload {
+        if let Err(err) = upload.finish() {
+            staged.discard();
+            return Err(err);
+        }
+    }
+    staged.commit()?;
+    if let Some(path) = &args.checkpoint {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+    }
+    if let Some((mut state, input, digest, arguments)) = change_state {
+        state.record(&input, &digest, &arguments);
+        state.save()?;
+    }
+    if let Some(totals) = &totals {
+        print_run_stats(totals, input_bytes, started.elapsed());
+    }
+
+    if let Some(algorithm) = args.checksum {
+        if let Some(hasher) = &output_digest {
+            let output = args.outfile.as_deref().unwrap_or("-");
+            checksum::report(algorithm, output, &checksum::hex_digest(hasher))?;
+        }
+        let separate_hyperlinks = args.hyperlinks == Some(HyperlinkMode::SeparateFile);
+        let side_outputs = [
+            &args.comments,
+            &args.hyperlinks_file.clone().filter(|_| separate_hyperlinks),
+            &args.validations,
+            &args.schema,
+            &args.audit,
+        ];
+        for path in side_outputs.into_iter().flatten() {
+            checksum::report(algorithm, path, &checksum::file_digest(path)?)?;
+        }
     }
+    Ok(())
 }
 
-fn load_shared_strings<R: Read + Seek>(archive: &mut ZipArchive<R>) -> BoxResult<Vec<String>> {
-    let mut strings = Vec::new();
-    let Ok(file) = archive.by_name("xl/sharedStrings.xml") else {
-        return Ok(strings);
-    };
+fn open_input(path: &str) -> Result<File> {
+    File::open(path).map_err(|source| Xlsx2CsvError::InputFile { path: path.into(), source })
+}
 
-    let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.trim_text(false);
-    let mut buf = Vec::new();
-    let mut current = String::new();
-    let mut in_string = false;
-    let mut in_phonetic = false;
+/// A progress bar on stderr for large workbooks, unless it would mix with the
+/// output on the same terminal.
+fn progress_bar(args: &Args, file: &File) -> Option<ProgressBar> {
+    let to_stdout = args.outfile.as_deref().is_none_or(|path| path == "-");
+    if args.no_progress || args.quiet || !io::stderr().is_terminal() || (to_stdout && io::stdout().is_terminal()) {
+        return None;
+    }
+    if file.metadata().ok()?.len() < PROGRESS_THRESHOLD {
+        return None;
+    }
+    let style = ProgressStyle::with_template("{msg} [{bar:40}] {bytes}/{total_bytes} ({eta})")
+        .ok()?
+        .progress_chars("=> ");
+    Some(ProgressBar::new(0).with_style(style))
+}
 
-    loop {
-        match reader.read_event_into(&mut buf)? {
-            Event::Start(e) if eq_local(e.name().as_ref(), b"si") => {
-                current.clear();
-                in_string = true;
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"si") => {
-                if in_string {
-                    strings.push(current.clone());
-                }
-                in_string = false;
-            }
-            Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => {
-                in_phonetic = true;
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => {
-                in_phonetic = false;
-            }
-            Event::Text(t) => {
-                if in_string && !in_phonetic {
-                    current.push_str(&t.unescape()?);
-                }
-            }
-            Event::CData(t) => {
-                if in_string && !in_phonetic {
-                    current.push_str(&String::from_utf8_lossy(t.as_ref()));
-                }
-            }
-            Event::Eof => break,
-            _ => {}
+/// Writes the output file of a subcommand under a temporary name and moves it
+/// to `path` once `write` succeeds. An existing file is only replaced with `force`.
+fn write_staged(path: &Path, force: bool, write: impl FnOnce(BufWriter<File>) -> Result<()>) -> Result<()> {
+    let mut staged = StagedOutputs::default();
+    let mut temp = Some(path.to_path_buf());
+    staged.stage(&mut temp);
+    if !force {
+        staged.refuse_overwrite()?;
+    }
+    let file = File::create(temp.unwrap_or_default())
+        .map_err(|err| io::Error::new(err.kind(), format!("Cannot create '{}': {}", path.display(), err)))?;
+    match write(BufWriter::new(file)) {
+        Ok(()) => staged.commit(),
+        Err(err) => {
+            staged.discard();
+            Err(err)
         }
     }
-
-    Ok(strings)
 }
 
-fn convert_sheet<R: Read + Seek, W: Write>(
-    archive: &mut ZipArchive<R>,
-    path: &str,
-    shared_strings: &[String],
-    writer: &mut csv::Writer<W>,
-    args: &Args,
-) -> BoxResult<()> {
-    let file = archive.by_name(path)?;
-    let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.trim_text(false);
-
-    let mut buf = Vec::new();
-    let mut current_row: Vec<String> = Vec::new();
-    let mut current_value = String::new();
-    let mut current_col: Option<usize> = None;
-    let mut cell_type = CellType::Number;
-    let mut in_value_tag = false;
-    let mut in_inline = false;
-    let mut in_phonetic = false;
+/// Output files written under a temporary name next to them, so that a
+/// failed or cancelled conversion never leaves a truncated file behind.
+#[derive(Default)]
+struct StagedOutputs {
+    /// Final and temporary paths
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Temporary file kept when the conversion fails, for --resume
+    keep: Option<PathBuf>,
+}
 
-    loop {
-        match reader.read_event_into(&mut buf)? {
-            Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
-                current_row.clear();
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"row") => {
-                // Skip empty rows if requested
-                if args.ignoreempty && current_row.iter().all(|s| s.is_empty()) {
-                    continue;
-                }
+impl StagedOutputs {
+    /// Points `path` at the temporary file `<path>.tmp`. Named pipes and
+    /// devices are written in place.
+    fn stage(&mut self, path: &mut Option<PathBuf>) {
+        if let Some(path) = path.as_mut().filter(|path| !fs::metadata(path).is_ok_and(|metadata| !metadata.is_file())) {
+            let mut temp = path.clone().into_os_string();
+            temp.push(".tmp");
+            let temp = PathBuf::from(temp);
+            self.files.push((std::mem::replace(path, temp.clone()), temp));
+        }
+    }
 
-                // Skip trailing empty columns if requested
-                let row_to_write = if args.skipemptycolumns {
-                    let mut trimmed = current_row.clone();
-                    while trimmed.last().map_or(false, |s| s.is_empty()) {
-                        trimmed.pop();
-                    }
-                    trimmed
-                } else {
-                    current_row.clone()
-                };
+    /// Fails if one of the final paths already exists.
+    fn refuse_overwrite(&self) -> Result<()> {
+        match self.files.iter().find(|(path, _)| path.exists()) {
+            Some((path, _)) => Err(Xlsx2CsvError::OutputExists(path.clone())),
+            None => Ok(()),
+        }
+    }
 
-                writer.write_record(&row_to_write)?;
-            }
-            Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
-                current_value.clear();
-                current_col = None;
-                cell_type = CellType::Number;
-                in_value_tag = false;
-                in_inline = false;
-                in_phonetic = false;
-
-                for attr in e.attributes() {
-                    let attr = attr?;
-                    match attr.key.as_ref() {
-                        b"t" => {
-                            let v = attr.unescape_value()?.into_owned();
-                            cell_type = match v.as_str() {
-                                "s" => CellType::SharedString,
-                                "b" => CellType::Bool,
-                                "inlineStr" => CellType::InlineStr,
-                                "str" => CellType::PlainStr,
-                                "e" => CellType::Error,
-                                _ => CellType::Number,
-                            };
-                        }
-                        b"r" => {
-                            let reference = attr.unescape_value()?.into_owned();
-                            current_col = column_index(&reference);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
-                place_cell(&mut current_row, current_col, String::new());
-            }
-            Event::Start(e) if eq_local(e.name().as_ref(), b"v") => {
-                in_value_tag = true;
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"v") => {
-                in_value_tag = false;
+    /// Moves the temporary files into place.
+    fn commit(self) -> Result<()> {
+        for (path, temp) in &self.files {
+            if let Err(err) = fs::rename(temp, path) {
+                self.discard();
+                return Err(Xlsx2CsvError::Io(io::Error::new(
+                    err.kind(),
+                    format!("could not rename '{}' to '{}': {}", temp.display(), path.display(), err),
+                )));
             }
-            Event::Start(e) if eq_local(e.name().as_ref(), b"is") => {
-                in_inline = true;
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"is") => {
-                in_inline = false;
-            }
-            Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => {
-                in_phonetic = true;
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => {
-                in_phonetic = false;
-            }
-            Event::Text(t) => {
-                if (in_value_tag || in_inline) && !in_phonetic {
-                    current_value.push_str(&t.unescape()?);
-                }
-            }
-            Event::CData(t) => {
-                if (in_value_tag || in_inline) && !in_phonetic {
-                    current_value.push_str(&String::from_utf8_lossy(t.as_ref()));
-                }
-            }
-            Event::End(e) if eq_local(e.name().as_ref(), b"c") => {
-                let mut value = match cell_type {
-                    CellType::SharedString => match current_value.trim().parse::<usize>() {
-                        Ok(idx) => shared_strings.get(idx).cloned().unwrap_or_default(),
-                        Err(_) => current_value.clone(),
-                    },
-                    CellType::Bool => match current_value.trim() {
-                        "1" => "true".to_string(),
-                        "0" => "false".to_string(),
-                        other => other.to_string(),
-                    },
-                    CellType::InlineStr | CellType::PlainStr | CellType::Error => {
-                        current_value.clone()
-                    }
-                    CellType::Number => current_value.clone(),
-                };
-
-                // Apply line break handling if requested
-                if args.no_line_breaks {
-                    value = value.replace('\r', " ").replace('\n', " ").replace('\t', " ");
-                } else if args.escape {
-                    value = value
-                        .replace('\r', "\\r")
-                        .replace('\n', "\\n")
-                        .replace('\t', "\\t");
-                }
+        }
+        Ok(())
+    }
 
-                place_cell(&mut current_row, current_col, value);
+    /// Deletes the temporary files a failed conversion had started writing.
+    fn discard(&self) {
+        for (_, temp) in self.files.iter().filter(|(_, temp)| self.keep.as_ref() != Some(temp)) {
+            if let Err(err) = fs::remove_file(temp) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Warning: could not remove partial output '{}': {}", temp.display(), err);
+                }
             }
-            Event::Eof => break,
-            _ => {}
         }
     }
-
-    Ok(())
 }
 
-fn place_cell(row: &mut Vec<String>, col_idx: Option<usize>, value: String) {
-    let idx = col_idx.unwrap_or_else(|| row.len());
-    if row.len() <= idx {
-        row.resize(idx + 1, String::new());
+fn parse_delimiter(s: &str) -> Result<u8> {
+    match s {
+        "tab" | "\\t" | "x09" => Ok(b'\t'),
+        s if s.len() == 1 => Ok(s.as_bytes()[0]),
+        _ => Err(Xlsx2CsvError::InvalidOption(format!("Invalid delimiter: {}", s))),
     }
-    row[idx] = value;
 }
 
-fn column_index(cell_ref: &str) -> Option<usize> {
-    let mut col = 0usize;
-    let mut has_column = false;
-    for c in cell_ref.chars() {
-        if c.is_ascii_alphabetic() {
-            has_column = true;
-            col = col * 26 + (c.to_ascii_uppercase() as usize - b'A' as usize + 1);
-        } else {
-            break;
-        }
-    }
-    if has_column {
-        Some(col.saturating_sub(1))
-    } else {
-        None
+fn parse_escape_sequence(s: &str) -> Result<String> {
+    Ok(s.replace("\\n", "\n")
+        .replace("\\r", "\r")
+        .replace("\\t", "\t")
+        .replace("\\f", "\x0C")
+        .replace("x07", "\x07")
+        .replace("x09", "\t"))
+}
+
+fn parse_quote_style(s: &str) -> Result<QuoteStyle> {
+    match s {
+        "none" => Ok(QuoteStyle::Never),
+        "minimal" => Ok(QuoteStyle::Necessary),
+        "nonnumeric" => Ok(QuoteStyle::NonNumeric),
+        "all" => Ok(QuoteStyle::Always),
+        _ => Err(Xlsx2CsvError::InvalidOption(format!("Invalid quoting style: {}", s))),
     }
 }
 
-fn eq_local(name: &[u8], expected: &[u8]) -> bool {
-    let local = name
-        .rsplit(|&b| b == b':')
-        .next()
-        .unwrap_or(name);
-    local == expected
+/// Parses a byte count with an optional K, M or G suffix (powers of 1024).
+fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let digits = s.trim().trim_end_matches(['B', 'b']);
+    let (number, unit) = match digits.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&digits[..idx], c.to_ascii_uppercase()),
+        _ => (digits, ' '),
+    };
+    let shift = match unit {
+        ' ' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return Err(format!("unknown unit in '{}', expected K, M or G", s)),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size '{}', expected ex. 512M", s))
 }
 
-fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
-    if let Some(io_err) = err.downcast_ref::<io::Error>() {
-        if io_err.kind() == io::ErrorKind::BrokenPipe {
-            return true;
-        }
+/// Lists the sheets on stderr and converts those whose numbers are typed in,
+/// by narrowing `options` to them. Workbooks with one sheet are converted
+/// without asking.
+fn pick_sheets(xlsxfile: &str, options: &mut Options) -> Result<()> {
+    let sheets = meta::list_sheets(&mut ZipArchive::new(open_input(xlsxfile)?)?)?;
+    if sheets.len() < 2 {
+        return Ok(());
     }
-    if let Some(csv_err) = err.downcast_ref::<csv::Error>() {
-        if let csv::ErrorKind::Io(io_err) = csv_err.kind() {
-            if io_err.kind() == io::ErrorKind::BrokenPipe {
-                return true;
+    eprintln!("Sheets in {}:", xlsxfile);
+    for sheet in &sheets {
+        let rows = sheet.dimension.as_deref().and_then(dimension_rows);
+        let rows = rows.map_or_else(|| "rows unknown".to_string(), |rows| format!("{} rows", rows));
+        eprintln!("{:>4}) {} ({})", sheet.index, sheet.name, rows);
+    }
+    loop {
+        eprint!("Sheets to convert, ex. '1,3' or '2-4', 'a' for all [1]: ");
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Err(Xlsx2CsvError::Cancelled);
+        }
+        let answer = answer.trim();
+        if answer == "a" || answer == "all" {
+            options.all_sheets = true;
+            return Ok(());
+        }
+        match parse_sheet_numbers(if answer.is_empty() { "1" } else { answer }, sheets.len()) {
+            Some(numbers) => {
+                options.all_sheets = true;
+                options.include_sheet_patterns =
+                    numbers.into_iter().map(|n| format!("^{}$", regex::escape(&sheets[n - 1].name))).collect();
+                return Ok(());
             }
+            None => eprintln!("Enter sheet numbers between 1 and {}", sheets.len()),
         }
     }
-    if let Some(source) = err.source() {
-        return is_broken_pipe(source);
+}
+
+/// Rows spanned by a `<dimension ref>` such as `A1:D100`.
+fn dimension_rows(dimension: &str) -> Option<usize> {
+    let row = |cell: &str| cell.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse::<usize>().ok();
+    let (first, last) = dimension.split_once(':').unwrap_or((dimension, dimension));
+    Some(row(last)? + 1 - row(first)?.min(row(last)?))
+}
+
+/// Comma separated sheet numbers and ranges, each between 1 and `count`.
+fn parse_sheet_numbers(answer: &str, count: usize) -> Option<Vec<usize>> {
+    let mut numbers = Vec::new();
+    for part in answer.split(',').map(str::trim) {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let (first, last): (usize, usize) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+        if first < 1 || last > count || first > last {
+            return None;
+        }
+        numbers.extend(first..=last);
     }
-    false
+    Some(numbers)
+}
+
+fn parse_rename_map(s: &str) -> Result<Vec<(String, String)>> {
+    s.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((old, new)) => Ok((old.trim().to_string(), new.trim().to_string())),
+            None => Err(Xlsx2CsvError::InvalidOption(format!("Invalid rename pair: {}", pair))),
+        })
+        .collect()
 }
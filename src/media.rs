@@ -0,0 +1,89 @@
+//! Inventory and extraction of embedded media for the `media` subcommand.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::read::ZipArchive;
+
+use crate::archive::Bounded;
+use crate::{Result, Xlsx2CsvError};
+
+/// Archive folders holding images and embedded objects.
+const MEDIA_DIRS: [&str; 2] = ["xl/media/", "xl/embeddings/"];
+
+/// Writes a csv listing (path,size) of every media part and, when `extract_to`
+/// is given, copies each part into that directory under its path below `xl/`,
+/// ex. `media/image1.png`. Existing files are only replaced with `force`, and
+/// nothing is extracted when one would be.
+pub fn media<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, extract_to: Option<&Path>, force: bool, out: W) -> Result<()> {
+    // Archive index and size of each media part, with where it is extracted to
+    let mut parts: Vec<(usize, String, u64, Option<PathBuf>)> = Vec::new();
+    for idx in 0..archive.len() {
+        let entry = archive.by_index_raw(idx)?;
+        let name = entry.name().to_string();
+        if entry.is_dir() || !MEDIA_DIRS.iter().any(|dir| name.starts_with(dir)) {
+            continue;
+        }
+        let target = extract_to.zip(relative_path(&name)).map(|(dir, path)| dir.join(path));
+        parts.push((idx, name, entry.size(), target));
+    }
+    if let Some(dir) = extract_to {
+        if !force {
+            if let Some(path) = parts.iter().filter_map(|(.., target)| target.as_ref()).find(|path| path.exists()) {
+                return Err(Xlsx2CsvError::OutputExists(path.clone()));
+            }
+        }
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["path", "size"])?;
+    for (idx, name, size, target) in parts {
+        wtr.write_record([name.as_str(), &size.to_string()])?;
+        if let Some(path) = target {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(&path)?;
+            io::copy(&mut Bounded::entry(archive.by_index(idx)?), &mut file)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// The path of a media part below `xl/`, `None` for names with `..`, drive
+/// letters or no file name, so archive paths cannot escape the directory.
+fn relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.strip_prefix("xl/")?.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            _ if part.contains(':') => return None,
+            _ => path.push(part),
+        }
+    }
+    (path.components().count() > 1).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::relative_path;
+    use std::path::PathBuf;
+
+    #[test]
+    fn parts_keep_their_folder_below_xl() {
+        assert_eq!(relative_path("xl/media/image1.png"), Some(PathBuf::from("media/image1.png")));
+        assert_eq!(relative_path("xl/embeddings/sub/./a.bin"), Some(PathBuf::from("embeddings/sub/a.bin")));
+    }
+
+    #[test]
+    fn paths_leaving_the_directory_are_refused() {
+        assert_eq!(relative_path("xl/media/../../evil.txt"), None);
+        assert_eq!(relative_path("xl/media/..\\..\\evil.txt"), None);
+        assert_eq!(relative_path("xl/media/C:evil.txt"), None);
+        assert_eq!(relative_path("xl/media/"), None);
+        assert_eq!(relative_path("docProps/app.xml"), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Workbook-level metadata for the `meta` and `names` subcommands and `--list-sheets`.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{BufReader, Read, Seek, Write};
+use zip::read::ZipArchive;
+
+use crate::archive::{eq_local, load_relationships, open_part};
+pub use crate::workbook::list_sheets;
+use crate::workbook::load_sheets;
+use crate::Result;
+
+/// A `<definedName>` entry of the workbook.
+pub struct DefinedName {
+    pub name: String,
+    /// 0-based index of the sheet the name is local to, `None` for workbook scope.
+    pub local_sheet_id: Option<usize>,
+    pub refers_to: String,
+    pub hidden: bool,
+}
+
+/// Settings and names declared in `xl/workbook.xml`.
+pub struct WorkbookInfo {
+    pub date1904: bool,
+    pub defined_names: Vec<DefinedName>,
+}
+
+pub fn load_workbook_info<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<WorkbookInfo> {
+    let file = open_part(archive, "xl/workbook.xml")?;
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+
+    let mut info = WorkbookInfo {
+        date1904: false,
+        defined_names: Vec::new(),
+    };
+    let mut current: Option<DefinedName> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"workbookPr") => {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"date1904" {
+                        let value = attr.unescape_value()?;
+                        info.date1904 = value == "1" || value == "true";
+                    }
+                }
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"definedName") => {
+                let mut name = DefinedName {
+                    name: String::new(),
+                    local_sheet_id: None,
+                    refers_to: String::new(),
+                    hidden: false,
+                };
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"name" => name.name = attr.unescape_value()?.into_owned(),
+                        b"localSheetId" => name.local_sheet_id = attr.unescape_value()?.trim().parse().ok(),
+                        b"hidden" => {
+                            let value = attr.unescape_value()?;
+                            name.hidden = value == "1" || value == "true";
+                        }
+                        _ => {}
+                    }
+                }
+                current = Some(name);
+            }
+            Event::Text(t) => {
+                if let Some(name) = current.as_mut() {
+                    name.refers_to.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"definedName") => {
+                if let Some(name) = current.take() {
+                    info.defined_names.push(name);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(info)
+}
+
+/// Reads the leaf elements of a document properties part (docProps/core.xml,
+/// docProps/app.xml) as name/value pairs, in document order.
+fn read_properties<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<Vec<(String, String)>> {
+    let mut props = Vec::new();
+    let Ok(file) = open_part(archive, path) else {
+        return Ok(props);
+    };
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut depth = 0usize;
+    let mut current: Option<(String, String)> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                depth += 1;
+                // Only direct children of the root carry simple values
+                current = (depth == 2).then(|| {
+                    let name = e.name();
+                    let local = name.as_ref().rsplit(|&b| b == b':').next().unwrap_or_default();
+                    (String::from_utf8_lossy(local).into_owned(), String::new())
+                });
+            }
+            Event::Text(t) => {
+                if let Some((_, value)) = current.as_mut() {
+                    value.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(_) => {
+                if let Some(prop) = current.take() {
+                    if !prop.1.is_empty() {
+                        props.push(prop);
+                    }
+                }
+                depth = depth.saturating_sub(1);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(props)
+}
+
+/// Prints document properties, sheet count, defined names and the date system.
+pub fn print_meta<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, out: &mut W) -> Result<()> {
+    const CORE: [(&str, &str); 8] = [
+        ("title", "title"),
+        ("subject", "subject"),
+        ("creator", "creator"),
+        ("keywords", "keywords"),
+        ("description", "description"),
+        ("lastModifiedBy", "last modified by"),
+        ("created", "created"),
+        ("modified", "modified"),
+    ];
+    const APP: [(&str, &str); 3] = [
+        ("Application", "application"),
+        ("AppVersion", "app version"),
+        ("Company", "company"),
+    ];
+
+    let core = read_properties(archive, "docProps/core.xml")?;
+    let app = read_properties(archive, "docProps/app.xml")?;
+    for (props, keys) in [(&core, &CORE[..]), (&app, &APP[..])] {
+        for (key, label) in keys {
+            if let Some((_, value)) = props.iter().find(|(name, _)| name == key) {
+                writeln!(out, "{}: {}", label, value)?;
+            }
+        }
+    }
+
+    let rels = load_relationships(archive)?;
+    let sheets = load_sheets(archive, &rels, false)?;
+    let info = load_workbook_info(archive)?;
+    writeln!(out, "sheets: {}", sheets.len())?;
+    writeln!(out, "date system: {}", if info.date1904 { "1904" } else { "1900" })?;
+    writeln!(out, "defined names: {}", info.defined_names.len())?;
+    for name in &info.defined_names {
+        writeln!(out, "  {} = {}", name.name, name.refers_to)?;
+    }
+    Ok(())
+}
+
+/// Writes every defined name as csv: name, scope (sheet name or "Workbook"),
+/// refers-to formula and hidden flag.
+pub fn print_names<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, out: W) -> Result<()> {
+    let rels = load_relationships(archive)?;
+    let sheets = load_sheets(archive, &rels, false)?;
+    let info = load_workbook_info(archive)?;
+
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["name", "scope", "refers_to", "hidden"])?;
+    for name in &info.defined_names {
+        let scope = match name.local_sheet_id {
+            Some(idx) => sheets.get(idx).map_or("", |sheet| sheet.name.as_str()),
+            None => "Workbook",
+        };
+        wtr.write_record([
+            name.name.as_str(),
+            scope,
+            &name.refers_to,
+            if name.hidden { "true" } else { "false" },
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes every sheet as csv: 1-based index, name, visibility, relationship
+/// id, part path and declared dimension.
+pub fn print_sheets<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, out: W) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["index", "name", "visibility", "rel_id", "path", "dimension"])?;
+    for sheet in list_sheets(archive)? {
+        wtr.write_record([
+            sheet.index.to_string().as_str(),
+            &sheet.name,
+            sheet.visibility.name(),
+            &sheet.rel_id,
+            &sheet.path,
+            sheet.dimension.as_deref().unwrap_or(""),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
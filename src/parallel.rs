@@ -0,0 +1,366 @@
+//! Conversion of several sheets, or of chunks of rows of a sheet, at once on
+//! a pool of worker threads.
+//!
+//! The main thread reads the parts and writes the side outputs in sheet
+//! order, the workers convert the parts into buffers, and the buffers are
+//! appended to the output in order again.
+
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{debug, instrument};
+use zip::read::ZipArchive;
+
+use crate::annotations::CellAnnotations;
+use crate::archive::{eq_local, RawPart};
+use crate::autofilter::AutoFilter;
+use crate::progress::Progress;
+use crate::schema::SheetSchema;
+use crate::shared_strings::SharedStrings;
+use crate::sheet::convert_sheet;
+use crate::spill::{MemoryBudget, SpillBuffer};
+use crate::{
+    row_writer, sheet_error, write_long_header, write_sheet_delimiter, Conversion, Options, Result, SheetInfo,
+    SideOutputs,
+};
+
+/// Uncompressed worksheet XML per chunk of `--parallel-rows`, extended to the
+/// end of the row it stops in.
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A sheet and what its conversion needs besides the XML, shared by the jobs
+/// of its chunks.
+struct SheetWork {
+    index: usize,
+    name: String,
+    path: String,
+    /// Uncompressed size of the worksheet part
+    size: u64,
+    annotations: CellAnnotations,
+    autofilter: Option<AutoFilter>,
+    kept_columns: Option<Vec<bool>>,
+    phonetic_columns: Option<Vec<bool>>,
+}
+
+/// A whole sheet or a chunk of its rows handed to a worker.
+struct Job {
+    sheet: Arc<SheetWork>,
+    part: Box<dyn Read + Send>,
+    /// Uncompressed size of the part
+    size: u64,
+    /// Uncompressed bytes of the sheet up to the end of the part
+    end: u64,
+    /// Rows of the sheet before the part
+    row_offset: usize,
+    /// Whether the part starts its sheet
+    first: bool,
+}
+
+/// The rows a job converted, in the output format, and the schema of a whole
+/// sheet.
+struct Output {
+    /// Position of the job within the conversion
+    seq: usize,
+    sheet: Arc<SheetWork>,
+    end: u64,
+    result: Result<(SpillBuffer, u64, Option<SheetSchema>)>,
+}
+
+/// Converts `targets` on `threads` workers, writing them to `writer` in
+/// order, and returns their schemas and the writer. With
+/// [`Options::parallel_rows`] the sheets are split into chunks of rows.
+/// Everything converted ahead of the part being written is held in memory, or
+/// in temporary files past half of [`Options::max_memory`].
+#[instrument(skip_all)]
+pub fn convert_sheets<R: Read + Seek, W: Write>(
+    archive: &mut ZipArchive<R>,
+    targets: Vec<SheetInfo>,
+    threads: usize,
+    conversion: &Conversion,
+    shared_strings: &mut SharedStrings,
+    side_outputs: &mut SideOutputs,
+    mut writer: W,
+) -> Result<(Vec<SheetSchema>, W)> {
+    // Workers need the whole table, there is no single sheet to start early for
+    let mut shared_strings = shared_strings.share()?;
+    let options = conversion.options;
+    debug!(threads, sheets = targets.len(), parallel_rows = options.parallel_rows, "converting in parallel");
+
+    // A chunk does not know how far into its sheet it is, so progress is
+    // reported as the chunks are written
+    let chunk_options = Options {
+        progress: None,
+        ..options.clone()
+    };
+    let worker_conversion = Conversion {
+        options: if options.parallel_rows { &chunk_options } else { options },
+        transforms: conversion.transforms,
+        external_links: conversion.external_links,
+        number_formats: conversion.number_formats,
+        column_formats: conversion.column_formats,
+        cell_styles: conversion.cell_styles,
+        date1904: conversion.date1904,
+    };
+
+    let budget = MemoryBudget::new(options.max_memory.map(|max| max / 2));
+    let (job_sender, jobs) = mpsc::sync_channel::<(usize, Job)>(threads);
+    let jobs = Mutex::new(jobs);
+    let schemas = thread::scope(|scope| -> Result<Vec<SheetSchema>> {
+        let (output_sender, outputs) = mpsc::channel();
+        for idx in 0..threads {
+            let jobs = &jobs;
+            let conversion = &worker_conversion;
+            let budget = &budget;
+            let output_sender = output_sender.clone();
+            let mut shared_strings = shared_strings.share()?;
+            thread::Builder::new().name(format!("convert-{}", idx)).spawn_scoped(scope, move || {
+                while let Some((seq, job)) = next_job(jobs) {
+                    let output = convert_job(seq, job, conversion, &mut shared_strings, budget);
+                    // A closed channel means the conversion failed on another part
+                    if output_sender.send(output).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        }
+        drop(output_sender);
+
+        let mut ordered = OrderedOutput::default();
+        let mut seq = 0;
+        // Hands a job to the workers and writes what they finished meanwhile,
+        // false when the workers are gone
+        let mut send = |job: Job, writer: &mut W| -> Result<bool> {
+            if job_sender.send((seq, job)).is_err() {
+                return Ok(false);
+            }
+            seq += 1;
+            while let Ok(output) = outputs.try_recv() {
+                ordered.push(output, writer, options)?;
+            }
+            Ok(true)
+        };
+        for (index, SheetInfo { name, path, .. }) in targets.into_iter().enumerate() {
+            let (annotations, autofilter) = side_outputs.load_sheet(archive, options, &name, &path)?;
+            let kept_columns = conversion.kept_columns(archive, &path, side_outputs)?;
+            let phonetic_columns = conversion.phonetic_columns(archive, &path, &mut shared_strings, side_outputs)?;
+            let sent = if options.parallel_rows {
+                let (part, size) = side_outputs.sheet_part(archive, &path)?;
+                let sheet = SheetWork {
+                    index,
+                    name,
+                    path,
+                    size,
+                    annotations,
+                    autofilter,
+                    kept_columns,
+                    phonetic_columns,
+                };
+                send_chunks(part, Arc::new(sheet), |job| send(job, &mut writer))?
+            } else {
+                // Inflated already when the side outputs read it, else inflated by the worker
+                let (part, size) = match side_outputs.cached_part(&path) {
+                    Some(part) => {
+                        let size = part.size()?;
+                        (part.into_reader()?, size)
+                    }
+                    None => {
+                        let part = RawPart::read(archive, &path)?;
+                        let size = part.size;
+                        (part.into_reader(), size)
+                    }
+                };
+                let sheet = SheetWork {
+                    index,
+                    name,
+                    path,
+                    size,
+                    annotations,
+                    autofilter,
+                    kept_columns,
+                    phonetic_columns,
+                };
+                let job = Job {
+                    size,
+                    end: size,
+                    part,
+                    sheet: Arc::new(sheet),
+                    row_offset: 0,
+                    first: true,
+                };
+                send(job, &mut writer)?
+            };
+            if !sent {
+                break;
+            }
+        }
+        drop(job_sender);
+        for output in outputs {
+            ordered.push(output, &mut writer, options)?;
+        }
+        Ok(ordered.schemas)
+    })?;
+    writer.flush()?;
+    Ok((schemas, writer))
+}
+
+fn next_job(jobs: &Mutex<Receiver<(usize, Job)>>) -> Option<(usize, Job)> {
+    jobs.lock().ok()?.recv().ok()
+}
+
+/// Splits a worksheet part into chunks of whole rows and sends a job for
+/// each, returning false when the workers are gone.
+fn send_chunks(
+    mut part: impl Read,
+    sheet: Arc<SheetWork>,
+    mut send: impl FnMut(Job) -> Result<bool>,
+) -> Result<bool> {
+    let mut pending = Vec::new();
+    let mut end = 0;
+    let mut row_offset = 0;
+    let mut first = true;
+    loop {
+        let read = (&mut part).take(CHUNK_SIZE).read_to_end(&mut pending)?;
+        let eof = (read as u64) < CHUNK_SIZE;
+        let split = match last_row_end(&pending) {
+            _ if eof => pending.len(),
+            Some(split) => split,
+            // A row longer than the chunk so far
+            None => continue,
+        };
+        let rest = pending.split_off(split);
+        let chunk = std::mem::replace(&mut pending, rest);
+        let rows = count_rows(&chunk);
+        end += chunk.len() as u64;
+        let job = Job {
+            sheet: Arc::clone(&sheet),
+            size: chunk.len() as u64,
+            end,
+            part: Box::new(Cursor::new(chunk)),
+            row_offset,
+            first,
+        };
+        if !send(job)? {
+            return Ok(false);
+        }
+        if eof {
+            return Ok(true);
+        }
+        row_offset += rows;
+        first = false;
+    }
+}
+
+/// The length of `xml` up to the end of its last `</row>` tag.
+fn last_row_end(xml: &[u8]) -> Option<usize> {
+    let mut search = xml.len();
+    while let Some(start) = xml[..search].windows(2).rposition(|pair| pair == b"</") {
+        let tag = &xml[start + 2..search];
+        if let Some(len) = tag.iter().position(|&b| b == b'>') {
+            if eq_local(tag[..len].trim_ascii_end(), b"row") {
+                return Some(start + 2 + len + 1);
+            }
+        }
+        search = start;
+    }
+    None
+}
+
+/// The number of `<row>` start tags in `xml`.
+fn count_rows(xml: &[u8]) -> usize {
+    xml.iter()
+        .enumerate()
+        .filter(|&(idx, &b)| b == b'<' && is_row_start(&xml[idx + 1..]))
+        .count()
+}
+
+fn is_row_start(tag: &[u8]) -> bool {
+    match tag.iter().position(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'/' | b'>')) {
+        Some(len) => eq_local(&tag[..len], b"row"),
+        None => false,
+    }
+}
+
+fn convert_job(
+    seq: usize,
+    job: Job,
+    conversion: &Conversion,
+    shared_strings: &mut SharedStrings,
+    budget: &MemoryBudget,
+) -> Output {
+    let Job {
+        sheet: work,
+        part,
+        size,
+        end,
+        row_offset,
+        first,
+    } = job;
+    let options = conversion.options;
+    let mut sheet = conversion.sheet(&work.name, &work.path, size, &work.annotations, work.autofilter.as_ref());
+    sheet.after_header = !first;
+    sheet.row_offset = row_offset;
+    sheet.kept_columns = work.kept_columns.as_deref();
+    sheet.phonetic_columns = work.phonetic_columns.as_deref();
+    let mut schema = conversion.schema(&work.name).filter(|_| first);
+
+    // The separators between sheets go with the rows, so that the buffers only need appending
+    let mut writer = row_writer(options, SpillBuffer::new(budget));
+    let separator = match work.index {
+        _ if !first => Ok(()),
+        0 => write_long_header(&mut writer, options),
+        _ => write_sheet_delimiter(&mut writer, options),
+    };
+    let result = separator
+        .and_then(|()| {
+            convert_sheet(part, &sheet, shared_strings, &mut writer, options, conversion.transforms, schema.as_mut())
+        })
+        .and_then(|rows| Ok((writer.finish()?, rows, schema)));
+    Output {
+        seq,
+        sheet: work,
+        end,
+        result,
+    }
+}
+
+/// Writes the parts finished by the workers in order.
+#[derive(Default)]
+struct OrderedOutput {
+    next: usize,
+    waiting: BTreeMap<usize, Output>,
+    schemas: Vec<SheetSchema>,
+    /// Index and rows written so far of the sheet being written, for progress reports
+    sheet_index: usize,
+    rows_written: u64,
+}
+
+impl OrderedOutput {
+    fn push<W: Write>(&mut self, output: Output, writer: &mut W, options: &Options) -> Result<()> {
+        self.waiting.insert(output.seq, output);
+        while let Some(output) = self.waiting.remove(&self.next) {
+            let sheet = &output.sheet;
+            let (rows, rows_written, schema) = output.result.map_err(|err| sheet_error(sheet.name.clone(), err))?;
+            rows.write_to(writer)?;
+            self.schemas.extend(schema);
+            self.next += 1;
+            if options.parallel_rows {
+                if sheet.index != self.sheet_index {
+                    self.sheet_index = sheet.index;
+                    self.rows_written = 0;
+                }
+                self.rows_written += rows_written;
+                if let Some(callback) = &options.progress {
+                    callback(&Progress {
+                        sheet: &sheet.name,
+                        bytes_read: output.end,
+                        total_bytes: sheet.size,
+                        rows_written: self.rows_written,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
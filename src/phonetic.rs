@@ -0,0 +1,87 @@
+//! Finding the columns of a sheet `--phonetic only` writes readings for.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{BufReader, Read};
+
+use crate::archive::eq_local;
+use crate::shared_strings::SharedStrings;
+use crate::sheet::column_index;
+use crate::Result;
+
+/// Scans the worksheet XML in `part` and returns by 0-based column whether
+/// the column holds a shared or inline string with a phonetic reading.
+/// Columns from `max_columns` on are left out.
+pub fn reading_columns(part: impl Read, shared_strings: &mut SharedStrings, max_columns: usize) -> Result<Vec<bool>> {
+    let mut reader = Reader::from_reader(BufReader::new(part));
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+    let mut columns = Vec::new();
+    let mut mark = |col: usize| {
+        // The conversion fails on or clips these cells
+        if col >= max_columns {
+            return;
+        }
+        if columns.len() <= col {
+            columns.resize(col + 1, false);
+        }
+        columns[col] = true;
+    };
+    let mut col = 0;
+    let mut next_col = 0;
+    let mut shared = false;
+    let mut in_value = false;
+    let mut value = String::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"row") => next_col = 0,
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                col = next_col;
+                shared = false;
+                value.clear();
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"r" => col = column_index(&attr.unescape_value()?).unwrap_or(next_col),
+                        b"t" => shared = attr.unescape_value()?.trim() == "s",
+                        _ => {}
+                    }
+                }
+                next_col = col.saturating_add(1);
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"v") => in_value = true,
+            Event::End(e) if eq_local(e.name().as_ref(), b"v") => in_value = false,
+            Event::Text(t) if in_value => value.push_str(&t.unescape()?),
+            // Only inline strings have phonetic runs in the sheet itself
+            Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => mark(col),
+            Event::End(e) if shared && eq_local(e.name().as_ref(), b"c") => {
+                if let Ok(idx) = value.trim().parse() {
+                    if shared_strings.reading(idx)?.is_some_and(|reading| !reading.is_empty()) {
+                        mark(col);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reading_columns;
+    use crate::shared_strings::{SharedStrings, StringArena};
+
+    #[test]
+    fn inline_readings_mark_their_column_below_the_limit() {
+        let sheet = r#"<worksheet><sheetData>
+<row r="1"><c r="B1" t="inlineStr"><is><t>山田</t><rPh sb="0" eb="2"><t>ヤマダ</t></rPh></is></c></row>
+<row r="2"><c r="ZZZZZZZZZZZZZZZZ2" t="inlineStr"><is><t>x</t><rPh sb="0" eb="1"><t>エックス</t></rPh></is></c><c/></row>
+</sheetData></worksheet>"#;
+        let mut shared_strings = SharedStrings::from(StringArena::default());
+        assert_eq!(reading_columns(sheet.as_bytes(), &mut shared_strings, 10).unwrap(), [false, true]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Streaming the output as the body of an HTTP POST request for `--post`.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use crate::{Result, Xlsx2CsvError};
+
+/// Size of the chunks handed to the request thread.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Chunks waiting to be sent, bounding the memory held when the endpoint is slow.
+const QUEUED_CHUNKS: usize = 16;
+
+/// A POST request whose body is written through `Write`. The body is sent with
+/// chunked transfer encoding by another thread while it is written, and the
+/// response is only checked by [`HttpPost::finish`]; dropping it unfinished
+/// breaks off the request.
+pub struct HttpPost {
+    url: String,
+    buffer: Vec<u8>,
+    chunks: Option<SyncSender<io::Result<Vec<u8>>>>,
+    request: Option<JoinHandle<Result<()>>>,
+}
+
+impl HttpPost {
+    /// Starts the request to `url` with this `Content-Type` and extra
+    /// `Name: value` headers.
+    pub fn start(url: &str, content_type: &str, headers: &[String]) -> Result<Self> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Xlsx2CsvError::InvalidOption(format!("--post needs an http:// or https:// URL, not '{}'", url)));
+        }
+        let mut request = ureq::post(url).set("Content-Type", content_type);
+        for header in headers {
+            let Some((name, value)) = header.split_once(':') else {
+                return Err(Xlsx2CsvError::InvalidOption(format!("--post-header '{}' is not 'Name: value'", header)));
+            };
+            request = request.set(name.trim(), value.trim());
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_CHUNKS);
+        let target = url.to_string();
+        let body = ChunkReader { chunks: receiver, chunk: Vec::new(), pos: 0 };
+        let request = thread::spawn(move || match request.send(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                Err(io::Error::other(format!("POST {} failed with status {}: {}", target, status, message.trim())).into())
+            }
+            Err(err) => Err(io::Error::other(format!("POST failed: {}", err)).into()),
+        });
+        Ok(HttpPost {
+            url: url.to_string(),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            chunks: Some(sender),
+            request: Some(request),
+        })
+    }
+
+    /// Ends the body and waits for the response, failing unless its status
+    /// is a success.
+    pub fn finish(mut self) -> Result<()> {
+        self.send_buffer()?;
+        self.chunks = None;
+        self.wait()
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+        let sent = self.chunks.as_ref().is_some_and(|chunks| chunks.send(Ok(chunk)).is_ok());
+        if sent {
+            return Ok(());
+        }
+        // The request ended before its body, report why
+        self.chunks = None;
+        match self.wait() {
+            Err(Xlsx2CsvError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err.to_string())),
+            Ok(()) => Err(io::Error::other(format!("POST {} ended before the whole output was sent", self.url))),
+        }
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        match self.request.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other(format!("POST {} failed", self.url)).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An unfinished request is broken off, so the endpoint does not take the
+/// partial output of a failed conversion for a complete one.
+impl Drop for HttpPost {
+    fn drop(&mut self) {
+        if let Some(chunks) = self.chunks.take() {
+            // Without a receiver the request has already ended
+            let _ = chunks.send(Err(io::Error::other("conversion failed")));
+            let _ = self.wait();
+        }
+    }
+}
+
+impl Write for HttpPost {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+/// The request body, read from the chunks written to [`HttpPost`].
+struct ChunkReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The writer is done
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,86 @@
+//! Aligned terminal tables for the `preview` subcommand.
+
+use std::io::{Read, Seek, Write};
+
+use crate::{Cell, Result, Workbook};
+
+/// Characters shown of a cell, longer values are cut with an ellipsis.
+const MAX_WIDTH: usize = 40;
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes the first `rows` rows of `sheet` (a name or a 1-based number, the
+/// first sheet without it) as a table with aligned columns. The first row is
+/// shown as the header; with `color` it is bold and error cells are red.
+pub fn print_preview<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    sheet: Option<&str>,
+    rows: usize,
+    color: bool,
+    mut out: W,
+) -> Result<()> {
+    let name = workbook.resolve_sheet(sheet)?;
+    let mut table = Vec::new();
+    for row in workbook.sheet(&name)?.rows().take(rows) {
+        table.push(row?);
+    }
+
+    let mut widths = vec![0; table.iter().map(Vec::len).max().unwrap_or(0)];
+    let texts: Vec<Vec<String>> = table.iter().map(|row| row.iter().map(cell_text).collect()).collect();
+    for row in &texts {
+        for (width, text) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+
+    for (idx, (row, text)) in table.iter().zip(&texts).enumerate() {
+        let mut line = String::new();
+        for (col, width) in widths.iter().enumerate() {
+            if col > 0 {
+                line.push_str(" | ");
+            }
+            let value = text.get(col).map_or("", String::as_str);
+            let pad = " ".repeat(width - value.chars().count());
+            let style = match row.get(col) {
+                _ if !color => None,
+                _ if idx == 0 => Some(BOLD),
+                Some(Cell::Error(_)) => Some(RED),
+                _ => None,
+            };
+            if let Some(style) = style {
+                line.push_str(style);
+            }
+            // Numbers are right-aligned below the header
+            if idx > 0 && matches!(row.get(col), Some(Cell::Number(_))) {
+                line.push_str(&pad);
+                line.push_str(value);
+            } else {
+                line.push_str(value);
+                line.push_str(&pad);
+            }
+            if style.is_some() {
+                line.push_str(RESET);
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+        if idx == 0 {
+            let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+            writeln!(out, "{}", rule.join("-+-"))?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The text of a cell on one line and at most [`MAX_WIDTH`] characters.
+fn cell_text(cell: &Cell) -> String {
+    let text: String = cell.to_string().chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    if text.chars().count() <= MAX_WIDTH {
+        return text;
+    }
+    let mut cut: String = text.chars().take(MAX_WIDTH - 1).collect();
+    cut.push('…');
+    cut
+}
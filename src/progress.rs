@@ -0,0 +1,75 @@
+//! Progress reporting for long conversions, see [`Options::progress`](crate::Options::progress),
+//! and checkpoints to resume them from, see [`Options::checkpoint`](crate::Options::checkpoint).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::Result;
+
+/// Rows read between two progress reports.
+pub(crate) const REPORT_INTERVAL: usize = 1000;
+
+/// Rows written between two checkpoints.
+pub(crate) const CHECKPOINT_INTERVAL: u64 = 100_000;
+
+/// Where a conversion stands within the sheet being converted.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress<'a> {
+    /// Name of the sheet being converted
+    pub sheet: &'a str,
+    /// Uncompressed bytes of the worksheet XML parsed so far
+    pub bytes_read: u64,
+    /// Uncompressed size of the worksheet XML
+    pub total_bytes: u64,
+    /// Rows written for this sheet so far, header included
+    pub rows_written: u64,
+}
+
+/// Called every 1000 rows of a sheet and once when the sheet is done.
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// The last row of a sheet written to the output, which is flushed before.
+#[derive(Clone, Copy, Debug)]
+pub struct Checkpoint<'a> {
+    pub sheet: &'a str,
+    /// 1-based sheet row of the last row written
+    pub row: usize,
+    /// Whether the sheet is converted to its end
+    pub complete: bool,
+}
+
+/// Called every 100000 rows written and once when a sheet is done; an error
+/// stops the conversion.
+pub type CheckpointCallback = Arc<dyn Fn(&Checkpoint) -> Result<()> + Send + Sync>;
+
+/// Where an interrupted conversion stopped, from its last [`Checkpoint`],
+/// see [`Options::resume`](crate::Options::resume).
+#[derive(Clone, Debug)]
+pub struct ResumePoint {
+    pub sheet: String,
+    /// Last sheet row written, `None` when the sheet was converted to its end
+    pub row: Option<usize>,
+}
+
+/// Counts of what a conversion wrote, see [`Options::totals`](crate::Options::totals).
+/// Sheets converted on several threads add to the same counts.
+#[derive(Debug, Default)]
+pub struct Totals {
+    /// Sheets converted to the end
+    pub sheets: AtomicU64,
+    /// Rows written, headers included
+    pub rows: AtomicU64,
+    /// Cells of the rows written
+    pub cells: AtomicU64,
+    /// Cells of the rows written that are empty
+    pub empty_cells: AtomicU64,
+}
+
+impl Totals {
+    pub(crate) fn add_row(&self, record: &[String]) {
+        let empty = record.iter().filter(|value| value.is_empty()).count();
+        self.rows.fetch_add(1, Ordering::Relaxed);
+        self.cells.fetch_add(record.len() as u64, Ordering::Relaxed);
+        self.empty_cells.fetch_add(empty as u64, Ordering::Relaxed);
+    }
+}
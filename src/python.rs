@@ -0,0 +1,142 @@
+//! The `pyxlsx2csv` Python module, behind the `python` feature.
+//!
+//! ```python
+//! import pyxlsx2csv
+//!
+//! csv_text = pyxlsx2csv.convert("input.xlsx", sheet="Data", delimiter=";")
+//! for row in pyxlsx2csv.rows("input.xlsx", sheet="Data"):
+//!     print(row)  # str, float, bool or None per cell, dates as ISO strings
+//! ```
+
+use pyo3::exceptions::{PyKeyError, PyOSError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyString};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::{Cell, ConverterBuilder, NewlineHandling, OutputFormat, Rows, Workbook, Xlsx2CsvError};
+
+impl From<Xlsx2CsvError> for PyErr {
+    fn from(err: Xlsx2CsvError) -> Self {
+        match err {
+            Xlsx2CsvError::Io(err) => PyOSError::new_err(err.to_string()),
+            Xlsx2CsvError::SheetNotFound { .. } | Xlsx2CsvError::SheetIndexOutOfRange { .. } => {
+                PyKeyError::new_err(err.to_string())
+            }
+            err => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Converts the workbook at `path` and returns the output as a string.
+/// Options take the names of the library settings, ex. `all_sheets=True`,
+/// `delimiter=";"`, `format="jsonl"`, `date_format="%Y/%m/%d"`, `range="A1:D20"`.
+#[pyfunction]
+#[pyo3(signature = (path, sheet=None, **options))]
+fn convert(py: Python<'_>, path: PathBuf, sheet: Option<String>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let mut builder = ConverterBuilder::new();
+    if let Some(name) = sheet {
+        builder.sheet(name);
+    }
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            apply_option(&mut builder, &key.extract::<String>()?, &value)?;
+        }
+    }
+    let options = builder.build()?;
+    let output = py.detach(|| -> Result<Vec<u8>, Xlsx2CsvError> {
+        let mut output = Vec::new();
+        crate::convert(BufReader::new(File::open(&path)?), &options, &mut output)?;
+        Ok(output)
+    })?;
+    String::from_utf8(output).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+fn apply_option(builder: &mut ConverterBuilder, key: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    match key {
+        "sheet_index" => builder.sheet_index(value.extract()?),
+        "all_sheets" => builder.all_sheets(value.extract()?),
+        "delimiter" => builder.delimiter(single_byte(&value.extract::<String>()?, key)?),
+        "sheet_delimiter" => builder.sheet_delimiter(value.extract()?),
+        "format" => builder.format(value_enum::<OutputFormat>(&value.extract::<String>()?, key)?),
+        "newline_handling" => builder.newline_handling(value_enum::<NewlineHandling>(&value.extract::<String>()?, key)?),
+        "ignore_empty" => builder.ignore_empty(value.extract()?),
+        "skip_empty_columns" => builder.skip_empty_columns(value.extract()?),
+        "formulas" => builder.formulas(value.extract()?),
+        "infer_types" => builder.infer_types(value.extract()?),
+        "date_format" => builder.date_format(value.extract::<String>()?),
+        "range" => builder.range(value.extract::<String>()?),
+        "where" => builder.where_expr(value.extract::<String>()?),
+        "snake_case_headers" => builder.snake_case_headers(value.extract()?),
+        "dedup" => builder.dedup(value.extract()?),
+        "add_row_number" => builder.add_row_number(value.extract()?),
+        "add_source_ref" => builder.add_source_ref(value.extract()?),
+        _ => return Err(PyTypeError::new_err(format!("convert() got an unexpected option '{}'", key))),
+    };
+    Ok(())
+}
+
+fn single_byte(value: &str, option: &str) -> PyResult<u8> {
+    match value.as_bytes() {
+        [byte] => Ok(*byte),
+        _ => Err(PyValueError::new_err(format!("Invalid {}: {}", option, value))),
+    }
+}
+
+fn value_enum<T: ValueEnum>(value: &str, option: &str) -> PyResult<T> {
+    T::from_str(value, true).map_err(|_| PyValueError::new_err(format!("Invalid {}: {}", option, value)))
+}
+
+/// Iterator over the rows of a sheet as lists of str, float, bool or None.
+#[pyclass(name = "Rows", unsendable)]
+struct PyRows {
+    rows: Rows<'static>,
+}
+
+#[pymethods]
+impl PyRows {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Vec<Bound<'py, PyAny>>>> {
+        let Some(row) = self.rows.next().transpose()? else {
+            return Ok(None);
+        };
+        Ok(Some(row.iter().map(|cell| cell_to_py(py, cell)).collect()))
+    }
+}
+
+/// Iterates the rows of a sheet, the first one when `sheet` is not given,
+/// parsing them as they are requested.
+#[pyfunction]
+#[pyo3(signature = (path, sheet=None))]
+fn rows(path: PathBuf, sheet: Option<String>) -> PyResult<PyRows> {
+    let mut workbook = Workbook::open(path)?;
+    let name = match sheet {
+        Some(name) => name,
+        None => workbook.sheet_names().next().unwrap_or_default().to_string(),
+    };
+    let sheet = workbook.sheet(&name)?.into_owned()?;
+    Ok(PyRows { rows: sheet.rows() })
+}
+
+fn cell_to_py<'py>(py: Python<'py>, cell: &Cell) -> Bound<'py, PyAny> {
+    match cell {
+        Cell::Empty => py.None().into_bound(py),
+        Cell::String(s) | Cell::Date(s) | Cell::Error(s) => PyString::new(py, s).into_any(),
+        Cell::Number(n) => PyFloat::new(py, *n).into_any(),
+        Cell::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+    }
+}
+
+#[pymodule]
+fn pyxlsx2csv(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add_function(wrap_pyfunction!(rows, m)?)?;
+    m.add_class::<PyRows>()?;
+    Ok(())
+}
@@ -0,0 +1,916 @@
+//! A small SQL dialect over the rows of one sheet for the `query` subcommand.
+//!
+//! ```text
+//! query   := "SELECT" item ("," item)* "FROM" sheet
+//!            ("WHERE" cond)? ("GROUP" "BY" column ("," column)*)?
+//!            ("ORDER" "BY" key ("ASC" | "DESC")? ("," key ("ASC" | "DESC")?)*)?
+//!            ("LIMIT" number)?
+//! item    := "*" | value ("AS" name)?
+//! value   := column | string | number
+//!          | ("COUNT" | "SUM" | "AVG" | "MIN" | "MAX") "(" ("*" | column) ")"
+//! cond    := and ("OR" and)*
+//! and     := not ("AND" not)*
+//! not     := "NOT" not | "(" cond ")" | value op value
+//! op      := "=" | "!=" | "<>" | "<" | "<=" | ">" | ">="
+//! ```
+//!
+//! The first row of the sheet names the columns. Columns are written as
+//! bare names, in double quotes or backticks; strings and the sheet name may
+//! be in single quotes. `ORDER BY` keys are output column names, aliases or
+//! 1-based positions. Values compare as numbers when both sides are numbers,
+//! as text otherwise.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use crate::{Cell, Result, Workbook, Xlsx2CsvError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Column(String),
+    Str(String),
+    Num(f64),
+    /// An aggregate over a column, or over every row for `COUNT(*)`
+    Aggregate(Aggregate, Option<String>),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    All,
+    Value { value: Value, label: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Cond {
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Cmp(CmpOp, Value, Value),
+}
+
+#[derive(Clone, Debug)]
+enum SortKey {
+    Name(String),
+    Position(usize),
+}
+
+/// A parsed query, its columns still referred to by name.
+#[derive(Debug)]
+struct Query {
+    items: Vec<Item>,
+    sheet: String,
+    filter: Option<Cond>,
+    group_by: Vec<String>,
+    order_by: Vec<(SortKey, bool)>,
+    limit: Option<usize>,
+}
+
+/// Runs `sql` against the sheet it selects from and writes the result as csv
+/// with a header row.
+pub fn run_query<R: Read + Seek, W: Write>(workbook: &mut Workbook<R>, sql: &str, out: W) -> Result<()> {
+    run(workbook, Parser::new(sql)?.query()?, out)
+}
+
+/// Writes one row per distinct value of the `group_by` columns of `sheet`
+/// (a name or a 1-based number, the first sheet without it) holding those
+/// values followed by the `aggregates`, each over a column or, for a count,
+/// over every row when the column is `None`. Groups come in the order they
+/// first occur.
+pub fn print_aggregate<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    sheet: Option<&str>,
+    group_by: &[String],
+    aggregates: &[(Aggregate, Option<String>)],
+    out: W,
+) -> Result<()> {
+    let mut items: Vec<Item> = group_by
+        .iter()
+        .map(|name| Item::Value {
+            value: Value::Column(name.clone()),
+            label: name.clone(),
+        })
+        .collect();
+    for (aggregate, column) in aggregates {
+        if *aggregate != Aggregate::Count && column.is_none() {
+            return Err(Xlsx2CsvError::Query(format!("{} needs a column, not '*'", aggregate_label(*aggregate, None))));
+        }
+        items.push(Item::Value {
+            value: Value::Aggregate(*aggregate, column.clone()),
+            label: aggregate_label(*aggregate, column.as_deref()),
+        });
+    }
+    if items.is_empty() {
+        return Err(Xlsx2CsvError::Query("Nothing to aggregate, give a group or an aggregate".to_string()));
+    }
+    let query = Query {
+        items,
+        sheet: workbook.resolve_sheet(sheet)?,
+        filter: None,
+        group_by: group_by.to_vec(),
+        order_by: Vec::new(),
+        limit: None,
+    };
+    run(workbook, query, out)
+}
+
+fn run<R: Read + Seek, W: Write>(workbook: &mut Workbook<R>, query: Query, out: W) -> Result<()> {
+    let name = workbook.resolve_sheet(Some(&query.sheet))?;
+    let mut rows = workbook.sheet(&name)?.rows();
+    let header: Vec<String> = match rows.next() {
+        Some(row) => row?.iter().map(Cell::to_string).collect(),
+        None => Vec::new(),
+    };
+    let plan = Plan::new(&query, &header)?;
+    let keys = plan.sort_keys(&query.order_by)?;
+
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    writer.write_record(&plan.labels)?;
+
+    // Plain projections without sorting stream, stopping at the limit
+    if !plan.aggregated && keys.is_empty() {
+        let mut written = 0;
+        for row in rows {
+            if query.limit.is_some_and(|limit| written >= limit) {
+                break;
+            }
+            let row = texts(row?);
+            if plan.keeps(&row) {
+                writer.write_record(plan.project(&row))?;
+                written += 1;
+            }
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    let mut results: Vec<Vec<String>> = Vec::new();
+    if plan.aggregated {
+        // Groups in the order they first occur
+        let mut groups: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut states: Vec<Vec<AggregateState>> = Vec::new();
+        let mut firsts: Vec<Vec<String>> = Vec::new();
+        for row in rows {
+            let row = texts(row?);
+            if !plan.keeps(&row) {
+                continue;
+            }
+            let key: Vec<String> = plan.group_by.iter().map(|&col| cell(&row, col).to_string()).collect();
+            let group = *groups.entry(key).or_insert_with(|| {
+                states.push(plan.columns.iter().map(|_| AggregateState::default()).collect());
+                firsts.push(row.clone());
+                states.len() - 1
+            });
+            for (state, column) in states[group].iter_mut().zip(&plan.columns) {
+                if let Output::Aggregate(_, col) = column {
+                    state.add(col.map(|col| cell(&row, col)));
+                }
+            }
+        }
+        // An aggregate without GROUP BY has one result row, even over no rows
+        if states.is_empty() && plan.group_by.is_empty() {
+            states.push(plan.columns.iter().map(|_| AggregateState::default()).collect());
+            firsts.push(Vec::new());
+        }
+        for (state, first) in states.iter().zip(&firsts) {
+            results.push(
+                plan.columns
+                    .iter()
+                    .zip(state)
+                    .map(|(column, state)| match column {
+                        Output::Aggregate(aggregate, _) => state.result(*aggregate),
+                        other => plan.output(other, first),
+                    })
+                    .collect(),
+            );
+        }
+    } else {
+        for row in rows {
+            let row = texts(row?);
+            if plan.keeps(&row) {
+                results.push(plan.project(&row));
+            }
+        }
+    }
+
+    if !keys.is_empty() {
+        results.sort_by(|a, b| {
+            keys.iter()
+                .map(|&(col, descending)| {
+                    let ordering = compare_text(cell(a, col), cell(b, col));
+                    if descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+    for row in results.iter().take(query.limit.unwrap_or(usize::MAX)) {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The output column label of an aggregate, as in `SUM(Amount)`.
+fn aggregate_label(aggregate: Aggregate, column: Option<&str>) -> String {
+    format!("{}({})", format!("{:?}", aggregate).to_uppercase(), column.unwrap_or("*"))
+}
+
+fn texts(row: Vec<Cell>) -> Vec<String> {
+    row.iter().map(Cell::to_string).collect()
+}
+
+fn cell(row: &[String], col: usize) -> &str {
+    row.get(col).map_or("", String::as_str)
+}
+
+fn number(text: &str) -> Option<f64> {
+    text.trim().parse().ok()
+}
+
+/// Orders two values as numbers when both are, as text otherwise.
+fn compare_text(a: &str, b: &str) -> Ordering {
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// One output column with its source resolved to a column position.
+enum Output {
+    Column(usize),
+    Literal(String),
+    Aggregate(Aggregate, Option<usize>),
+}
+
+/// A query bound to the header of its sheet.
+struct Plan {
+    labels: Vec<String>,
+    columns: Vec<Output>,
+    filter: Option<BoundCond>,
+    group_by: Vec<usize>,
+    aggregated: bool,
+}
+
+enum BoundCond {
+    Not(Box<BoundCond>),
+    And(Box<BoundCond>, Box<BoundCond>),
+    Or(Box<BoundCond>, Box<BoundCond>),
+    Cmp(CmpOp, Output, Output),
+}
+
+impl Plan {
+    fn new(query: &Query, header: &[String]) -> Result<Self> {
+        let column = |name: &str| -> Result<usize> {
+            header.iter().position(|h| h == name).ok_or_else(|| Xlsx2CsvError::UnknownColumn {
+                option: "query".to_string(),
+                name: name.to_string(),
+            })
+        };
+        let group_by = query.group_by.iter().map(|name| column(name)).collect::<Result<Vec<_>>>()?;
+        let aggregated = !group_by.is_empty()
+            || query.items.iter().any(|item| matches!(item, Item::Value { value: Value::Aggregate(..), .. }));
+
+        let mut labels = Vec::new();
+        let mut columns = Vec::new();
+        for item in &query.items {
+            match item {
+                Item::All if aggregated => {
+                    return Err(Xlsx2CsvError::Query("SELECT * cannot be combined with GROUP BY or aggregates".to_string()))
+                }
+                Item::All => {
+                    labels.extend(header.iter().cloned());
+                    columns.extend((0..header.len()).map(Output::Column));
+                }
+                Item::Value { value, label } => {
+                    let output = bind_value(value, &column)?;
+                    if let Output::Column(col) = output {
+                        if aggregated && !group_by.contains(&col) {
+                            return Err(Xlsx2CsvError::Query(format!(
+                                "Column '{}' must appear in GROUP BY or be used in an aggregate",
+                                header[col]
+                            )));
+                        }
+                    }
+                    labels.push(label.clone());
+                    columns.push(output);
+                }
+            }
+        }
+        let filter = query.filter.as_ref().map(|cond| bind_cond(cond, &column)).transpose()?;
+        Ok(Plan {
+            labels,
+            columns,
+            filter,
+            group_by,
+            aggregated,
+        })
+    }
+
+    fn keeps(&self, row: &[String]) -> bool {
+        self.filter.as_ref().is_none_or(|cond| self.test(cond, row))
+    }
+
+    fn test(&self, cond: &BoundCond, row: &[String]) -> bool {
+        match cond {
+            BoundCond::Not(inner) => !self.test(inner, row),
+            BoundCond::And(l, r) => self.test(l, row) && self.test(r, row),
+            BoundCond::Or(l, r) => self.test(l, row) || self.test(r, row),
+            BoundCond::Cmp(op, l, r) => {
+                let ordering = compare_text(&self.output(l, row), &self.output(r, row));
+                match op {
+                    CmpOp::Eq => ordering == Ordering::Equal,
+                    CmpOp::Ne => ordering != Ordering::Equal,
+                    CmpOp::Lt => ordering == Ordering::Less,
+                    CmpOp::Le => ordering != Ordering::Greater,
+                    CmpOp::Gt => ordering == Ordering::Greater,
+                    CmpOp::Ge => ordering != Ordering::Less,
+                }
+            }
+        }
+    }
+
+    fn output(&self, output: &Output, row: &[String]) -> String {
+        match output {
+            Output::Column(col) => cell(row, *col).to_string(),
+            Output::Literal(text) => text.clone(),
+            Output::Aggregate(..) => String::new(),
+        }
+    }
+
+    fn project(&self, row: &[String]) -> Vec<String> {
+        self.columns.iter().map(|column| self.output(column, row)).collect()
+    }
+
+    /// Output column positions and directions of the ORDER BY keys.
+    fn sort_keys(&self, order_by: &[(SortKey, bool)]) -> Result<Vec<(usize, bool)>> {
+        order_by
+            .iter()
+            .map(|(key, descending)| {
+                let col = match key {
+                    SortKey::Position(position) if *position >= 1 && *position <= self.labels.len() => Some(position - 1),
+                    SortKey::Position(_) => None,
+                    SortKey::Name(name) => self.labels.iter().position(|label| label == name),
+                };
+                let key = match key {
+                    SortKey::Name(name) => name.clone(),
+                    SortKey::Position(position) => position.to_string(),
+                };
+                col.map(|col| (col, *descending))
+                    .ok_or_else(|| Xlsx2CsvError::Query(format!("ORDER BY {} is not an output column", key)))
+            })
+            .collect()
+    }
+}
+
+fn bind_value(value: &Value, column: &impl Fn(&str) -> Result<usize>) -> Result<Output> {
+    Ok(match value {
+        Value::Column(name) => Output::Column(column(name)?),
+        Value::Str(text) => Output::Literal(text.clone()),
+        Value::Num(n) => Output::Literal(n.to_string()),
+        Value::Aggregate(aggregate, name) => Output::Aggregate(*aggregate, name.as_deref().map(column).transpose()?),
+    })
+}
+
+fn bind_cond(cond: &Cond, column: &impl Fn(&str) -> Result<usize>) -> Result<BoundCond> {
+    Ok(match cond {
+        Cond::Not(inner) => BoundCond::Not(Box::new(bind_cond(inner, column)?)),
+        Cond::And(l, r) => BoundCond::And(Box::new(bind_cond(l, column)?), Box::new(bind_cond(r, column)?)),
+        Cond::Or(l, r) => BoundCond::Or(Box::new(bind_cond(l, column)?), Box::new(bind_cond(r, column)?)),
+        Cond::Cmp(op, l, r) => {
+            let (l, r) = (bind_value(l, column)?, bind_value(r, column)?);
+            if matches!(l, Output::Aggregate(..)) || matches!(r, Output::Aggregate(..)) {
+                return Err(Xlsx2CsvError::Query("Aggregates cannot be used in WHERE".to_string()));
+            }
+            BoundCond::Cmp(*op, l, r)
+        }
+    })
+}
+
+/// The running value of one aggregate within one group.
+#[derive(Default)]
+struct AggregateState {
+    /// Rows for `COUNT(*)`, non-empty cells otherwise
+    count: u64,
+    sum: f64,
+    /// Cells that are numbers, averaged by AVG
+    numbers: u64,
+    min: Option<String>,
+    max: Option<String>,
+}
+
+impl AggregateState {
+    /// Adds one row, `value` being the cell of the aggregated column.
+    fn add(&mut self, value: Option<&str>) {
+        let Some(value) = value else {
+            self.count += 1;
+            return;
+        };
+        if value.is_empty() {
+            return;
+        }
+        self.count += 1;
+        if let Some(n) = number(value) {
+            self.sum += n;
+            self.numbers += 1;
+        }
+        if self.min.as_deref().is_none_or(|min| compare_text(value, min) == Ordering::Less) {
+            self.min = Some(value.to_string());
+        }
+        if self.max.as_deref().is_none_or(|max| compare_text(value, max) == Ordering::Greater) {
+            self.max = Some(value.to_string());
+        }
+    }
+
+    fn result(&self, aggregate: Aggregate) -> String {
+        match aggregate {
+            Aggregate::Count => self.count.to_string(),
+            Aggregate::Sum => self.sum.to_string(),
+            Aggregate::Avg if self.numbers == 0 => String::new(),
+            Aggregate::Avg => (self.sum / self.numbers as f64).to_string(),
+            Aggregate::Min => self.min.clone().unwrap_or_default(),
+            Aggregate::Max => self.max.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A bare word, keywords included
+    Word(String),
+    /// A name in double quotes or backticks
+    Quoted(String),
+    Str(String),
+    Num(f64),
+    Symbol(&'static str),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    const SYMBOLS: [&str; 11] = ["<>", "!=", "<=", ">=", "=", "<", ">", "(", ")", ",", "*"];
+
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' || c == '`' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    // A doubled quote stands for the quote itself
+                    Some((_, q)) if q == c && chars.peek().is_some_and(|&(_, next)| next == c) => {
+                        chars.next();
+                        value.push(c);
+                    }
+                    Some((_, q)) if q == c => break,
+                    Some((_, other)) => value.push(other),
+                    None => return Err(Xlsx2CsvError::Query("Unterminated quote in query".to_string())),
+                }
+            }
+            tokens.push(if c == '\'' { Token::Str(value) } else { Token::Quoted(value) });
+        } else if c.is_ascii_digit() || c == '.' || c == '-' {
+            let mut end = start + c.len_utf8();
+            chars.next();
+            while let Some(&(i, d)) = chars.peek() {
+                if d.is_ascii_digit() || d == '.' || d == 'e' || d == 'E' {
+                    end = i + d.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &src[start..end];
+            let num = text.parse().map_err(|_| Xlsx2CsvError::Query(format!("Invalid number in query: {}", text)))?;
+            tokens.push(Token::Num(num));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&(_, d)) = chars.peek() {
+                if d.is_alphanumeric() || d == '_' {
+                    word.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Word(word));
+        } else {
+            let rest = &src[start..];
+            let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) else {
+                return Err(Xlsx2CsvError::Query(format!("Unexpected character in query: '{}'", c)));
+            };
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+const KEYWORDS: [&str; 13] = [
+    "SELECT", "FROM", "WHERE", "GROUP", "ORDER", "BY", "LIMIT", "AS", "AND", "OR", "NOT", "ASC", "DESC",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(sql: &str) -> Result<Self> {
+        Ok(Parser {
+            tokens: tokenize(sql)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(Xlsx2CsvError::Query(format!("Expected {} in query, found {}", keyword, self.describe())))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(Xlsx2CsvError::Query(format!("Expected '{}' in query, found {}", symbol, self.describe())))
+        }
+    }
+
+    /// The next token as shown in error messages.
+    fn describe(&self) -> String {
+        match self.peek() {
+            None => "the end".to_string(),
+            Some(Token::Word(word) | Token::Quoted(word)) => format!("'{}'", word),
+            Some(Token::Str(text)) => format!("'{}'", text),
+            Some(Token::Num(n)) => n.to_string(),
+            Some(Token::Symbol(symbol)) => format!("'{}'", symbol),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        self.expect_keyword("SELECT")?;
+        let mut items = vec![self.item()?];
+        while self.eat_symbol(",") {
+            items.push(self.item()?);
+        }
+        self.expect_keyword("FROM")?;
+        let sheet = match self.next() {
+            Some(Token::Str(name) | Token::Quoted(name) | Token::Word(name)) => name,
+            Some(Token::Num(n)) => n.to_string(),
+            _ => return Err(Xlsx2CsvError::Query("Expected a sheet name after FROM".to_string())),
+        };
+        let filter = if self.eat_keyword("WHERE") { Some(self.or()?) } else { None };
+        let mut group_by = Vec::new();
+        if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            group_by.push(self.column()?);
+            while self.eat_symbol(",") {
+                group_by.push(self.column()?);
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let key = match self.next() {
+                    Some(Token::Num(n)) if n >= 1.0 && n.fract() == 0.0 => SortKey::Position(n as usize),
+                    Some(Token::Word(name) | Token::Quoted(name)) => SortKey::Name(self.call_label(name)?),
+                    _ => return Err(Xlsx2CsvError::Query("Expected a column after ORDER BY".to_string())),
+                };
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push((key, descending));
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.next() {
+                Some(Token::Num(n)) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+                _ => return Err(Xlsx2CsvError::Query("Expected a row count after LIMIT".to_string())),
+            }
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            return Err(Xlsx2CsvError::Query(format!("Unexpected {} in query", self.describe())));
+        }
+        Ok(Query {
+            items,
+            sheet,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    /// In ORDER BY, `SUM(x)` refers to the output column labelled so.
+    fn call_label(&mut self, name: String) -> Result<String> {
+        if !self.eat_symbol("(") {
+            return Ok(name);
+        }
+        let argument = if self.eat_symbol("*") { "*".to_string() } else { self.column()? };
+        self.expect_symbol(")")?;
+        Ok(format!("{}({})", name.to_uppercase(), argument))
+    }
+
+    fn item(&mut self) -> Result<Item> {
+        if self.eat_symbol("*") {
+            return Ok(Item::All);
+        }
+        let value = self.value()?;
+        let label = if self.eat_keyword("AS") {
+            match self.next() {
+                Some(Token::Word(name) | Token::Quoted(name) | Token::Str(name)) => name,
+                _ => return Err(Xlsx2CsvError::Query("Expected a name after AS".to_string())),
+            }
+        } else {
+            match &value {
+                Value::Column(name) | Value::Str(name) => name.clone(),
+                Value::Num(n) => n.to_string(),
+                Value::Aggregate(aggregate, column) => aggregate_label(*aggregate, column.as_deref()),
+            }
+        };
+        Ok(Item::Value { value, label })
+    }
+
+    fn column(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Quoted(name)) => Ok(name),
+            Some(Token::Word(name)) if !KEYWORDS.iter().any(|keyword| name.eq_ignore_ascii_case(keyword)) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                Err(Xlsx2CsvError::Query(format!("Expected a column in query, found {}", self.describe())))
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek().cloned() {
+            Some(Token::Str(text)) => {
+                self.pos += 1;
+                Ok(Value::Str(text))
+            }
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Value::Num(n))
+            }
+            Some(Token::Word(word)) if self.tokens.get(self.pos + 1) == Some(&Token::Symbol("(")) => {
+                let aggregate = match word.to_uppercase().as_str() {
+                    "COUNT" => Aggregate::Count,
+                    "SUM" => Aggregate::Sum,
+                    "AVG" => Aggregate::Avg,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(Xlsx2CsvError::Query(format!("Unknown function in query: {}", word))),
+                };
+                self.pos += 2;
+                let column = if self.eat_symbol("*") {
+                    if aggregate != Aggregate::Count {
+                        return Err(Xlsx2CsvError::Query(format!("{}(*) is not supported, name a column", word)));
+                    }
+                    None
+                } else {
+                    Some(self.column()?)
+                };
+                self.expect_symbol(")")?;
+                Ok(Value::Aggregate(aggregate, column))
+            }
+            _ => Ok(Value::Column(self.column()?)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Cond> {
+        let mut cond = self.and()?;
+        while self.eat_keyword("OR") {
+            cond = Cond::Or(Box::new(cond), Box::new(self.and()?));
+        }
+        Ok(cond)
+    }
+
+    fn and(&mut self) -> Result<Cond> {
+        let mut cond = self.not()?;
+        while self.eat_keyword("AND") {
+            cond = Cond::And(Box::new(cond), Box::new(self.not()?));
+        }
+        Ok(cond)
+    }
+
+    fn not(&mut self) -> Result<Cond> {
+        if self.eat_keyword("NOT") {
+            return Ok(Cond::Not(Box::new(self.not()?)));
+        }
+        if self.eat_symbol("(") {
+            let cond = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(cond);
+        }
+        let left = self.value()?;
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => CmpOp::Eq,
+            Some(Token::Symbol("!=" | "<>")) => CmpOp::Ne,
+            Some(Token::Symbol("<")) => CmpOp::Lt,
+            Some(Token::Symbol("<=")) => CmpOp::Le,
+            Some(Token::Symbol(">")) => CmpOp::Gt,
+            Some(Token::Symbol(">=")) => CmpOp::Ge,
+            _ => {
+                self.pos -= 1;
+                return Err(Xlsx2CsvError::Query(format!("Expected a comparison in query, found {}", self.describe())));
+            }
+        };
+        let right = self.value()?;
+        Ok(Cond::Cmp(op, left, right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::io::Cursor;
+
+    fn workbook(rows: &[&[&str]]) -> Workbook<Cursor<Vec<u8>>> {
+        Workbook::new(Cursor::new(fixtures::workbook(rows))).unwrap()
+    }
+
+    const SALES: &[&[&str]] = &[
+        &["Region", "Rep", "Amount", "Unit Price"],
+        &["North", "Ann", "100", "2"],
+        &["South", "Bob", "50", "1.5"],
+        &["North", "Cid", "25.5", ""],
+        &["East", "Dee", "n/a", "3"],
+        &["South", "Eve", "", "10"],
+    ];
+
+    fn query(rows: &[&[&str]], sql: &str) -> Result<String> {
+        let mut out = Vec::new();
+        run_query(&mut workbook(rows), sql, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn query_error(sql: &str) -> String {
+        match Parser::new(sql).and_then(|mut parser| parser.query()) {
+            Ok(query) => panic!("{} parsed as {:?}", sql, query),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn malformed_queries_are_rejected() {
+        assert!(query_error("FROM Sales").contains("Expected SELECT"));
+        assert!(query_error("SELECT Rep").contains("Expected FROM"));
+        assert!(query_error("SELECT Rep FROM").contains("sheet name"));
+        assert!(query_error("SELECT Rep FROM Sales WHERE Amount").contains("Expected a comparison"));
+        assert!(query_error("SELECT Rep FROM Sales WHERE (Amount > 1").contains("Expected ')'"));
+        assert!(query_error("SELECT Rep FROM Sales GROUP Rep").contains("Expected BY"));
+        assert!(query_error("SELECT Rep FROM Sales LIMIT -1").contains("row count"));
+        assert!(query_error("SELECT Rep FROM Sales LIMIT 1 OFFSET 2").contains("Unexpected 'OFFSET'"));
+        assert!(query_error("SELECT MEDIAN(Amount) FROM Sales").contains("Unknown function"));
+        assert!(query_error("SELECT SUM(*) FROM Sales").contains("SUM(*)"));
+        assert!(query_error("SELECT 'open FROM Sales").contains("Unterminated quote"));
+        assert!(query_error("SELECT Rep FROM Sales WHERE Amount ~ 1").contains("Unexpected character"));
+        assert!(query_error("SELECT FROM Sales").contains("Expected a column"));
+    }
+
+    #[test]
+    fn unknown_columns_and_bad_grouping_fail_when_bound() {
+        assert!(matches!(
+            query(SALES, "SELECT Price FROM Sales"),
+            Err(Xlsx2CsvError::UnknownColumn { name, .. }) if name == "Price"
+        ));
+        assert!(matches!(query(SALES, "SELECT Rep, COUNT(*) FROM Sales GROUP BY Region"), Err(Xlsx2CsvError::Query(_))));
+        assert!(matches!(query(SALES, "SELECT *, COUNT(*) FROM Sales"), Err(Xlsx2CsvError::Query(_))));
+    }
+
+    #[test]
+    fn where_compares_numbers_and_text() {
+        assert_eq!(
+            query(SALES, "SELECT Rep FROM Sales WHERE Amount > 30 AND NOT Region = 'East'").unwrap(),
+            "Rep\nAnn\nBob\n"
+        );
+        // 100 sorts before 50 as text but not as a number; n/a compares as text
+        assert_eq!(query(SALES, "SELECT Rep FROM Sales WHERE Amount >= 50").unwrap(), "Rep\nAnn\nBob\nDee\n");
+        assert_eq!(
+            query(SALES, "SELECT Rep FROM Sales WHERE Region = 'South' OR (Region <> 'North' AND Rep != 'Dee')").unwrap(),
+            "Rep\nBob\nEve\n"
+        );
+    }
+
+    #[test]
+    fn quoted_identifiers_and_aliases() {
+        // An empty cell compares as text, so it sorts before 2
+        assert_eq!(
+            query(SALES, "SELECT Rep AS \"Sales Rep\", `Unit Price` FROM 'Sales' WHERE \"Unit Price\" < 2").unwrap(),
+            "Sales Rep,Unit Price\nBob,1.5\nCid,\n"
+        );
+        // Keywords can be columns when quoted
+        let rows: &[&[&str]] = &[&["order", "it's"], &["1", "a"], &["2", "b"]];
+        assert_eq!(query(rows, "SELECT \"it's\" FROM Sales WHERE \"order\" = 2").unwrap(), "it's\nb\n");
+        assert_eq!(query(rows, "SELECT \"it's\" FROM Sales WHERE \"it's\" = 'a'").unwrap(), "it's\na\n");
+    }
+
+    #[test]
+    fn group_by_with_aggregates_over_mixed_cells() {
+        assert_eq!(
+            query(SALES, "SELECT Region, COUNT(*), COUNT(Amount), SUM(Amount), AVG(Amount), MIN(Rep), MAX(Amount) FROM Sales GROUP BY Region").unwrap(),
+            "Region,COUNT(*),COUNT(Amount),SUM(Amount),AVG(Amount),MIN(Rep),MAX(Amount)\n\
+             North,2,2,125.5,62.75,Ann,100\n\
+             South,2,1,50,50,Bob,50\n\
+             East,1,1,0,,Dee,n/a\n"
+        );
+    }
+
+    #[test]
+    fn aggregates_over_no_rows() {
+        assert_eq!(
+            query(SALES, "SELECT COUNT(*), SUM(Amount), AVG(Amount), MIN(Amount) FROM Sales WHERE Region = 'West'").unwrap(),
+            "COUNT(*),SUM(Amount),AVG(Amount),MIN(Amount)\n0,0,,\n"
+        );
+        assert_eq!(
+            query(SALES, "SELECT Region, COUNT(*) FROM Sales WHERE Region = 'West' GROUP BY Region").unwrap(),
+            "Region,COUNT(*)\n"
+        );
+    }
+
+    #[test]
+    fn order_by_and_limit() {
+        assert_eq!(
+            query(SALES, "SELECT Rep, Amount FROM Sales WHERE Amount > 30 ORDER BY Amount DESC LIMIT 2").unwrap(),
+            "Rep,Amount\nDee,n/a\nAnn,100\n"
+        );
+        assert_eq!(
+            query(SALES, "SELECT Region, SUM(Amount) AS total FROM Sales GROUP BY Region ORDER BY total, 1 LIMIT 2").unwrap(),
+            "Region,total\nEast,0\nSouth,50\n"
+        );
+        assert_eq!(
+            query(SALES, "SELECT Region, SUM(Amount) FROM Sales GROUP BY Region ORDER BY SUM(Amount) DESC").unwrap(),
+            "Region,SUM(Amount)\nNorth,125.5\nSouth,50\nEast,0\n"
+        );
+        assert_eq!(query(SALES, "SELECT Rep FROM Sales LIMIT 0").unwrap(), "Rep\n");
+        assert_eq!(query(SALES, "SELECT * FROM Sales LIMIT 1").unwrap(), "Region,Rep,Amount,Unit Price\nNorth,Ann,100,2\n");
+    }
+}
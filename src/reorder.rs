@@ -0,0 +1,103 @@
+//! Sorting of worksheet rows that a writer emitted out of order, for
+//! `--reorder-rows`.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::Read;
+use std::ops::Range;
+use tracing::debug;
+
+use crate::archive::eq_local;
+use crate::sheet::row_number;
+use crate::Result;
+
+/// A `<row>` element of the sheet XML by byte ranges.
+struct RowSpan {
+    number: usize,
+    /// The start tag, or the whole element when it is empty
+    tag: Range<usize>,
+    /// The cells between the start and end tags
+    content: Range<usize>,
+    /// End of the element
+    end: usize,
+    empty: bool,
+}
+
+/// Rewrites worksheet XML so that its `<row>` elements are in ascending row
+/// order, merging the cells of a row that appears more than once into its
+/// first occurrence. Rows without an `r` attribute follow the row before them
+/// in the document. The whole sheet is held in memory.
+pub fn reorder_rows(mut part: impl Read) -> Result<Vec<u8>> {
+    let mut xml = Vec::new();
+    part.read_to_end(&mut xml)?;
+
+    let mut reader = Reader::from_reader(xml.as_slice());
+    reader.check_end_names(false);
+    let mut rows: Vec<RowSpan> = Vec::new();
+    let mut name = Vec::new();
+    let mut previous = 0;
+    loop {
+        let start = reader.buffer_position();
+        match reader.read_event()? {
+            Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
+                previous = row_number(&e)?.unwrap_or(previous + 1);
+                name = e.name().as_ref().to_vec();
+                let end = reader.buffer_position();
+                rows.push(RowSpan {
+                    number: previous,
+                    tag: start..end,
+                    content: end..end,
+                    end,
+                    empty: false,
+                });
+            }
+            Event::Empty(e) if eq_local(e.name().as_ref(), b"row") => {
+                previous = row_number(&e)?.unwrap_or(previous + 1);
+                let end = reader.buffer_position();
+                rows.push(RowSpan {
+                    number: previous,
+                    tag: start..end,
+                    content: end..end,
+                    end,
+                    empty: true,
+                });
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"row") => {
+                if let Some(row) = rows.last_mut() {
+                    row.content.end = start;
+                    row.end = reader.buffer_position();
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if rows.windows(2).all(|pair| pair[0].number < pair[1].number) {
+        return Ok(xml);
+    }
+
+    let prefix = 0..rows[0].tag.start;
+    let suffix = rows[rows.len() - 1].end..xml.len();
+    debug!(rows = rows.len(), "reordering rows");
+    rows.sort_by_key(|row| row.number);
+
+    let mut sorted = Vec::with_capacity(xml.len());
+    sorted.extend_from_slice(&xml[prefix]);
+    for group in rows.chunk_by(|a, b| a.number == b.number) {
+        let Some(full) = group.iter().find(|row| !row.empty) else {
+            sorted.extend_from_slice(&xml[group[0].tag.clone()]);
+            continue;
+        };
+        // The cells of a repeated row go under the start tag of the first
+        // occurrence that has any
+        sorted.extend_from_slice(&xml[full.tag.clone()]);
+        for row in group {
+            sorted.extend_from_slice(&xml[row.content.clone()]);
+        }
+        sorted.extend_from_slice(b"</");
+        sorted.extend_from_slice(&name);
+        sorted.push(b'>');
+    }
+    sorted.extend_from_slice(&xml[suffix]);
+    Ok(sorted)
+}
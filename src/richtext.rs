@@ -0,0 +1,201 @@
+//! Rendering of rich text runs (`<r>` elements) for `--rich-text` and of
+//! phonetic runs (`<rPh>`) for `--phonetic`.
+
+use clap::ValueEnum;
+use quick_xml::events::BytesStart;
+
+use crate::archive::eq_local;
+use crate::Result;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum RichText {
+    Plain,
+    Markdown,
+    Html,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Phonetic {
+    /// drop phonetic runs
+    Strip,
+    /// append the reading in parentheses after the text
+    Append,
+    /// keep the text and write the reading to a `<header>_phonetic` column after its column
+    Only,
+}
+
+#[derive(Clone, Copy, Default)]
+struct RunStyle {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+}
+
+/// Accumulates the text of a shared or inline string, wrapping formatted runs
+/// according to the selected [`RichText`] mode.
+pub struct StringBuilder {
+    mode: RichText,
+    phonetic_mode: Phonetic,
+    text: String,
+    phonetic: String,
+    run: Option<(RunStyle, String)>,
+    in_properties: bool,
+}
+
+impl StringBuilder {
+    pub fn new(mode: RichText, phonetic_mode: Phonetic) -> Self {
+        StringBuilder {
+            mode,
+            phonetic_mode,
+            text: String::new(),
+            phonetic: String::new(),
+            run: None,
+            in_properties: false,
+        }
+    }
+
+    /// Handles a start or empty element inside the string.
+    pub fn start(&mut self, e: &BytesStart) -> Result<()> {
+        if self.mode == RichText::Plain {
+            return Ok(());
+        }
+        let name = e.name();
+        let name = name.as_ref();
+        if eq_local(name, b"r") {
+            self.run = Some((RunStyle::default(), String::new()));
+        } else if eq_local(name, b"rPr") {
+            self.in_properties = true;
+        } else if self.in_properties {
+            let Some((style, _)) = self.run.as_mut() else {
+                return Ok(());
+            };
+            // <b/> switches formatting on, <b val="0"/> explicitly off
+            let mut enabled = true;
+            for attr in e.attributes() {
+                let attr = attr?;
+                if attr.key.as_ref() == b"val" {
+                    let value = attr.unescape_value()?;
+                    enabled = !matches!(value.as_ref(), "0" | "false" | "none");
+                }
+            }
+            if eq_local(name, b"b") {
+                style.bold = enabled;
+            } else if eq_local(name, b"i") {
+                style.italic = enabled;
+            } else if eq_local(name, b"u") {
+                style.underline = enabled;
+            } else if eq_local(name, b"strike") {
+                style.strike = enabled;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the end of an element inside the string.
+    pub fn end(&mut self, name: &[u8]) {
+        if eq_local(name, b"rPr") {
+            self.in_properties = false;
+        } else if eq_local(name, b"r") {
+            if let Some((style, text)) = self.run.take() {
+                self.text.push_str(&self.render(style, &text));
+            }
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        match self.run.as_mut() {
+            Some((_, text)) => text.push_str(s),
+            None if self.mode == RichText::Html => self.text.push_str(&escape_html(s)),
+            None => self.text.push_str(s),
+        }
+    }
+
+    /// Adds text found inside an `<rPh>` phonetic run.
+    pub fn push_phonetic(&mut self, s: &str) {
+        if self.phonetic_mode != Phonetic::Strip {
+            self.phonetic.push_str(s);
+        }
+    }
+
+    /// Returns the accumulated string and resets the builder.
+    pub fn finish(&mut self) -> String {
+        self.finish_with_reading().0
+    }
+
+    /// Returns the accumulated string and, with [`Phonetic::Only`], its
+    /// reading kept apart, then resets the builder.
+    pub fn finish_with_reading(&mut self) -> (String, String) {
+        if let Some((style, text)) = self.run.take() {
+            self.text.push_str(&self.render(style, &text));
+        }
+        self.in_properties = false;
+        let phonetic = std::mem::take(&mut self.phonetic);
+        let text = std::mem::take(&mut self.text);
+        match self.phonetic_mode {
+            _ if phonetic.is_empty() => (text, phonetic),
+            Phonetic::Strip => (text, String::new()),
+            Phonetic::Append => (format!("{} ({})", text.trim_end(), phonetic), String::new()),
+            Phonetic::Only => (text, phonetic),
+        }
+    }
+
+    fn render(&self, style: RunStyle, text: &str) -> String {
+        match self.mode {
+            RichText::Plain => text.to_string(),
+            RichText::Html => {
+                let mut out = escape_html(text);
+                for (on, tag) in [
+                    (style.strike, "s"),
+                    (style.underline, "u"),
+                    (style.italic, "i"),
+                    (style.bold, "b"),
+                ] {
+                    if on {
+                        out = format!("<{tag}>{out}</{tag}>");
+                    }
+                }
+                out
+            }
+            RichText::Markdown => {
+                // Markers must hug the text, so surrounding whitespace stays outside
+                let trimmed = text.trim();
+                if trimmed.is_empty() {
+                    return text.to_string();
+                }
+                let start = text.len() - text.trim_start().len();
+                let end = start + trimmed.len();
+                let mut marked = trimmed.to_string();
+                for (on, marker) in [(style.strike, "~~"), (style.italic, "*"), (style.bold, "**")] {
+                    if on {
+                        marked = format!("{marker}{marked}{marker}");
+                    }
+                }
+                format!("{}{}{}", &text[..start], marked, &text[end..])
+            }
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finish(phonetic: Phonetic) -> (String, String) {
+        let mut builder = StringBuilder::new(RichText::Plain, phonetic);
+        builder.push_str("山田 ");
+        builder.push_phonetic("ヤマダ");
+        builder.finish_with_reading()
+    }
+
+    #[test]
+    fn phonetic_modes() {
+        assert_eq!(finish(Phonetic::Strip), ("山田 ".to_string(), String::new()));
+        assert_eq!(finish(Phonetic::Append), ("山田 (ヤマダ)".to_string(), String::new()));
+        assert_eq!(finish(Phonetic::Only), ("山田 ".to_string(), "ヤマダ".to_string()));
+    }
+}
@@ -0,0 +1,286 @@
+//! Lazy row-by-row reading of a worksheet for the [`Workbook`](crate::Workbook) API.
+
+use quick_xml::events::{BytesStart, Event};
+use csv::StringRecord;
+use quick_xml::Reader;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::archive::eq_local;
+use crate::richtext::{Phonetic, RichText, StringBuilder};
+use crate::shared_strings::StringArena;
+use crate::sheet::{column_index, column_name, row_number, CellType, MAX_COLUMNS};
+use crate::{styles, Result, Xlsx2CsvError};
+
+/// A cell value read by [`Rows`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    Empty,
+    String(String),
+    Number(f64),
+    Bool(bool),
+    /// A number shown in a date or time format, as ISO 8601 text
+    Date(String),
+    /// An error value such as `#DIV/0!`
+    Error(String),
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Empty => Ok(()),
+            Cell::String(s) | Cell::Date(s) | Cell::Error(s) => f.write_str(s),
+            Cell::Number(n) => write!(f, "{}", n),
+            Cell::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// Iterator over the rows of a sheet, parsing one `<row>` per call. Rows
+/// absent from the sheet XML are skipped and cells missing inside a row are
+/// [`Cell::Empty`]. A cell past the last worksheet column (XFD) is an
+/// [`Xlsx2CsvError::ColumnLimit`] error.
+pub struct Rows<'a> {
+    reader: Reader<BufReader<Box<dyn Read + 'a>>>,
+    buf: Vec<u8>,
+    shared_strings: Arc<StringArena>,
+    date_styles: Arc<[bool]>,
+    date1904: bool,
+    row_number: usize,
+    done: bool,
+}
+
+impl<'a> Rows<'a> {
+    pub(crate) fn new(
+        file: Box<dyn Read + 'a>,
+        shared_strings: Arc<StringArena>,
+        date_styles: Arc<[bool]>,
+        date1904: bool,
+    ) -> Self {
+        let mut reader = Reader::from_reader(BufReader::new(file));
+        reader.trim_text(false);
+        Rows {
+            reader,
+            buf: Vec::new(),
+            shared_strings,
+            date_styles,
+            date1904,
+            row_number: 0,
+            done: false,
+        }
+    }
+
+    /// The 1-based sheet row of the row returned last.
+    pub fn row_number(&self) -> usize {
+        self.row_number
+    }
+
+    fn read_row(&mut self) -> Result<Option<Vec<Cell>>> {
+        let mut row: Vec<Cell> = Vec::new();
+        let mut col = 0;
+        // Cells without an `r` attribute follow the previous cell of their row
+        let mut next_col = 0;
+        let mut cell_type = CellType::Number;
+        let mut style: Option<usize> = None;
+        let mut value = String::new();
+        let mut inline = StringBuilder::new(RichText::Plain, Phonetic::Strip);
+        let mut in_value = false;
+        let mut in_inline = false;
+        let mut in_phonetic = false;
+
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
+                    row.clear();
+                    next_col = 0;
+                    self.row_number = row_number(&e)?.unwrap_or(self.row_number + 1);
+                }
+                Event::End(e) if eq_local(e.name().as_ref(), b"row") => return Ok(Some(row)),
+                Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
+                    let reference;
+                    (reference, cell_type, style) = cell_attributes(&e)?;
+                    col = self.checked_column(reference.unwrap_or(next_col))?;
+                    next_col = col + 1;
+                    value.clear();
+                }
+                Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                    let (reference, _, _) = cell_attributes(&e)?;
+                    let col = self.checked_column(reference.unwrap_or(next_col))?;
+                    next_col = col + 1;
+                    place(&mut row, col, Cell::Empty);
+                }
+                Event::Start(e) if eq_local(e.name().as_ref(), b"v") => in_value = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"v") => in_value = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"is") => in_inline = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"is") => in_inline = false,
+                Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = true,
+                Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => in_phonetic = false,
+                Event::Text(t) if in_inline && !in_phonetic => inline.push_str(&t.unescape()?),
+                Event::Text(t) if in_value => value.push_str(&t.unescape()?),
+                Event::CData(t) if in_value || (in_inline && !in_phonetic) => {
+                    let text = String::from_utf8_lossy(t.as_ref());
+                    if in_inline {
+                        inline.push_str(&text);
+                    } else {
+                        value.push_str(&text);
+                    }
+                }
+                Event::End(e) if eq_local(e.name().as_ref(), b"c") => {
+                    let cell = self.cell(cell_type, style, std::mem::take(&mut value), inline.finish());
+                    place(&mut row, col, cell);
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Fails on a column no worksheet has, which only a corrupted cell
+    /// reference gives, before the row is sized to it.
+    fn checked_column(&self, col: usize) -> Result<usize> {
+        if col < MAX_COLUMNS {
+            Ok(col)
+        } else {
+            Err(Xlsx2CsvError::ColumnLimit {
+                cell: format!("{}{}", column_name(col), self.row_number),
+                limit: MAX_COLUMNS,
+            })
+        }
+    }
+
+    fn cell(&self, cell_type: CellType, style: Option<usize>, value: String, inline: String) -> Cell {
+        match cell_type {
+            CellType::SharedString => match value.trim().parse::<usize>() {
+                Ok(idx) => Cell::String(self.shared_strings.get(idx).unwrap_or_default().to_string()),
+                Err(_) => Cell::String(value),
+            },
+            CellType::InlineStr => Cell::String(inline),
+            CellType::PlainStr => Cell::String(value),
+            CellType::Bool => Cell::Bool(matches!(value.trim(), "1" | "true")),
+            CellType::Error => Cell::Error(value),
+            CellType::Number if value.is_empty() => Cell::Empty,
+            CellType::Number => {
+                let Ok(number) = value.trim().parse::<f64>() else {
+                    return Cell::String(value);
+                };
+                let is_date = style.is_some_and(|s| self.date_styles.get(s) == Some(&true));
+                match styles::serial_to_iso(number, self.date1904) {
+                    Some(date) if is_date => Cell::Date(date),
+                    _ => Cell::Number(number),
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<Cell>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_row();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+/// Reads the column, type and style index of a `<c>` element.
+fn cell_attributes(e: &BytesStart) -> Result<(Option<usize>, CellType, Option<usize>)> {
+    let mut col = None;
+    let mut cell_type = CellType::Number;
+    let mut style = None;
+    for attr in e.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"r" => col = column_index(&attr.unescape_value()?),
+            b"t" => cell_type = CellType::from_attr(&attr.unescape_value()?),
+            b"s" => style = attr.unescape_value()?.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Ok((col, cell_type, style))
+}
+
+/// Iterator deserializing the data rows of a sheet into `T`, created by
+/// [`Sheet::deserialize`](crate::Sheet::deserialize).
+///
+/// Fields are matched to the header row by name, as `csv::Reader::deserialize`
+/// does: numbers and booleans parse from their cell values, empty cells
+/// become `None` for `Option` fields, and dates arrive as ISO 8601 strings
+/// that a `deserialize_with` function can turn into the date type of choice.
+pub struct DeserializeRows<'a, T> {
+    rows: Rows<'a>,
+    header: Option<StringRecord>,
+    _record: PhantomData<T>,
+}
+
+impl<'a, T: DeserializeOwned> DeserializeRows<'a, T> {
+    pub(crate) fn new(mut rows: Rows<'a>) -> Result<Self> {
+        let header = rows.next().transpose()?.map(|row| record(&row));
+        Ok(DeserializeRows {
+            rows,
+            header,
+            _record: PhantomData,
+        })
+    }
+
+    /// The header row the fields are matched against, if the sheet has one.
+    pub fn header(&self) -> Option<&StringRecord> {
+        self.header.as_ref()
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for DeserializeRows<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.rows.next()? {
+            Ok(row) => row,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(record(&row).deserialize(self.header.as_ref()).map_err(Into::into))
+    }
+}
+
+fn record(row: &[Cell]) -> StringRecord {
+    row.iter().map(|cell| cell.to_string()).collect()
+}
+
+fn place(row: &mut Vec<Cell>, col: usize, cell: Cell) {
+    if row.len() <= col {
+        row.resize(col + 1, Cell::Empty);
+    }
+    row[col] = cell;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cell;
+    use crate::{fixtures, Workbook, Xlsx2CsvError};
+    use std::io::Cursor;
+
+    #[test]
+    fn corrupted_column_reference_is_an_error() {
+        let xlsx = fixtures::xlsx(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+<row r="1"><c r="A1"><v>1</v></c><c r="XFD1"><v>2</v></c></row>
+<row r="2"><c r="A2"><v>1</v></c><c r="ZZZZZZZZZZZZZZZZ2"/></row>
+</sheetData></worksheet>"#,
+        );
+        let mut workbook = Workbook::new(Cursor::new(xlsx)).unwrap();
+        let mut rows = workbook.sheet("Sales").unwrap().rows();
+        let first = rows.next().unwrap().unwrap();
+        assert_eq!(first.len(), 16384);
+        assert_eq!(first.last(), Some(&Cell::Number(2.0)));
+        assert!(matches!(rows.next(), Some(Err(Xlsx2CsvError::ColumnLimit { limit: 16384, .. }))));
+        assert!(rows.next().is_none());
+    }
+}
@@ -0,0 +1,264 @@
+//! Streaming uploads of the output to S3-compatible storage for `s3://`
+//! output paths, signed with AWS Signature Version 4.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::checksum::to_hex;
+use crate::styles::civil_from_days;
+use crate::{Result, Xlsx2CsvError};
+
+/// Size of the uploaded parts, S3 requires at least 5 MiB for all but the last.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// An object written through `Write`: the output is sent in parts of a
+/// multipart upload as it grows, or with a single request when it fits in one
+/// part, and becomes visible once [`S3Upload::finish`] completes the upload.
+pub struct S3Upload {
+    agent: ureq::Agent,
+    credentials: Credentials,
+    /// Scheme and host the requests go to
+    endpoint: String,
+    host: String,
+    /// Path of the object, with the bucket for path-style endpoints
+    path: String,
+    buffer: Vec<u8>,
+    upload_id: Option<String>,
+    /// ETags of the uploaded parts, in part number order
+    parts: Vec<String>,
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+/// Whether `path` names an object in S3-compatible storage.
+pub fn is_s3_url(path: &str) -> bool {
+    path.starts_with("s3://")
+}
+
+impl S3Upload {
+    /// Prepares the upload of `url` (`s3://bucket/key`). The credentials come
+    /// from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`,
+    /// the region from `AWS_REGION` or `AWS_DEFAULT_REGION` (us-east-1 without
+    /// them), and other S3-compatible services are reached through
+    /// `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL` with path-style requests.
+    pub fn create(url: &str) -> Result<Self> {
+        let Some((bucket, key)) = url.strip_prefix("s3://").and_then(|path| path.split_once('/')) else {
+            return Err(Xlsx2CsvError::InvalidOption(format!("'{}' is not an s3://bucket/key URL", url)));
+        };
+        if bucket.is_empty() || key.is_empty() {
+            return Err(Xlsx2CsvError::InvalidOption(format!("'{}' is not an s3://bucket/key URL", url)));
+        }
+        let (Ok(access_key_id), Ok(secret_access_key)) = (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) else {
+            return Err(Xlsx2CsvError::InvalidOption(
+                "s3:// outputs need AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY".to_string(),
+            ));
+        };
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let key = uri_encode(key, false);
+        let (endpoint, path) = match env::var("AWS_ENDPOINT_URL_S3").or_else(|_| env::var("AWS_ENDPOINT_URL")) {
+            Ok(endpoint) => (endpoint.trim_end_matches('/').to_string(), format!("/{}/{}", uri_encode(bucket, true), key)),
+            Err(_) => (format!("https://{}.s3.{}.amazonaws.com", bucket, region), format!("/{}", key)),
+        };
+        let host = endpoint.split_once("://").map_or(endpoint.as_str(), |(_, host)| host).to_string();
+        Ok(S3Upload {
+            agent: ureq::Agent::new(),
+            credentials: Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                region,
+            },
+            endpoint,
+            host,
+            path,
+            buffer: Vec::with_capacity(PART_SIZE),
+            upload_id: None,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Sends what is left of the output and makes the object visible.
+    pub fn finish(mut self) -> Result<()> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            let body = std::mem::take(&mut self.buffer);
+            self.send("PUT", &[], &body)?;
+            return Ok(());
+        };
+        if !self.buffer.is_empty() {
+            self.upload_part()?;
+        }
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (number, etag) in (1..).zip(&self.parts) {
+            body.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", number, etag));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let response = self.send("POST", &[("uploadId", &upload_id)], body.as_bytes())?;
+        // Errors of the completion can come with a 200 status
+        if response.contains("<Error>") {
+            self.abort();
+            return Err(io::Error::other(format!("S3 upload failed: {}", response)).into());
+        }
+        Ok(())
+    }
+
+    /// Drops the parts uploaded so far, when the conversion failed.
+    pub fn abort(&self) {
+        if let Some(upload_id) = &self.upload_id {
+            if let Err(err) = self.send("DELETE", &[("uploadId", upload_id)], &[]) {
+                eprintln!("Warning: could not abort the upload of '{}': {}", self.path, err);
+            }
+        }
+    }
+
+    fn upload_part(&mut self) -> io::Result<()> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let response = self.send("POST", &[("uploads", "")], &[])?;
+                let upload_id = xml_element(&response, "UploadId")
+                    .ok_or_else(|| io::Error::other(format!("S3 did not start the upload: {}", response)))?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+        let number = (self.parts.len() + 1).to_string();
+        let body = std::mem::take(&mut self.buffer);
+        let etag = self.send_part(&[("partNumber", &number), ("uploadId", &upload_id)], &body)?;
+        self.parts.push(etag);
+        self.buffer = body;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn send_part(&self, query: &[(&str, &str)], body: &[u8]) -> io::Result<String> {
+        let response = self.request("PUT", query, body)?;
+        response
+            .header("ETag")
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("S3 did not return the ETag of an uploaded part"))
+    }
+
+    /// Sends a signed request, returning the body of the response.
+    fn send(&self, method: &str, query: &[(&str, &str)], body: &[u8]) -> io::Result<String> {
+        let mut text = String::new();
+        self.request(method, query, body)?.into_reader().read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    fn request(&self, method: &str, query: &[(&str, &str)], body: &[u8]) -> io::Result<ureq::Response> {
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (uri_encode(k, true), uri_encode(v, true))).collect();
+        query.sort();
+        let query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let (date, time) = timestamp();
+        let payload_hash = to_hex(&Sha256::digest(body));
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", time.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, self.path, query, canonical_headers, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.credentials.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", time, scope, to_hex(&Sha256::digest(&canonical_request)));
+        let mut key = hmac(format!("AWS4{}", self.credentials.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.credentials.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key_id,
+            scope,
+            signed_headers,
+            to_hex(&hmac(&key, string_to_sign.as_bytes()))
+        );
+
+        let mut url = format!("{}{}", self.endpoint, self.path);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+        let mut request = self.agent.request(method, &url).set("Authorization", &authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                let message = xml_element(&message, "Message").unwrap_or(message);
+                Err(io::Error::other(format!("S3 {} {} failed with status {}: {}", method, url, status, message)))
+            }
+            Err(err) => Err(io::Error::other(format!("S3 {} failed: {}", method, err))),
+        }
+    }
+}
+
+impl Write for S3Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(PART_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == PART_SIZE {
+            self.upload_part()?;
+        }
+        Ok(len)
+    }
+
+    /// Parts are only sent once full, a flush keeps the rest buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The signing date (`YYYYMMDD`) and time (`YYYYMMDDTHHMMSSZ`), in UTC.
+fn timestamp() -> (String, String) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{}T{:02}{:02}{:02}Z", date, time / 3600, time / 60 % 60, time % 60);
+    (date, time)
+}
+
+/// Percent-encodes everything but unreserved characters, and slashes unless
+/// `encode_slash`.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The text of the first `<name>` element of an XML response.
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].to_string())
+}
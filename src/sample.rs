@@ -0,0 +1,73 @@
+//! Reservoir sampling of the rows of a sheet for the `sample` subcommand.
+
+use std::io::{Read, Seek, Write};
+
+use crate::{Cell, Result, Workbook};
+
+/// Writes the header row of `sheet` (a name or a 1-based number, the first
+/// sheet without it) and `count` of its other rows picked uniformly at
+/// random, in sheet order. The sheet is read once, holding only the sample;
+/// the same `seed` picks the same rows.
+pub fn print_sample<R: Read + Seek, W: Write>(
+    workbook: &mut Workbook<R>,
+    sheet: Option<&str>,
+    count: usize,
+    seed: u64,
+    out: W,
+) -> Result<()> {
+    let name = workbook.resolve_sheet(sheet)?;
+    let mut rows = workbook.sheet(&name)?.rows();
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(out);
+    if let Some(header) = rows.next() {
+        writer.write_record(record(&header?))?;
+    }
+
+    let mut random = SplitMix64(seed);
+    // Rows by their position among the data rows
+    let mut reservoir: Vec<(u64, Vec<Cell>)> = Vec::with_capacity(count);
+    for (seen, row) in (0u64..).zip(rows) {
+        let row = row?;
+        if reservoir.len() < count {
+            reservoir.push((seen, row));
+        } else {
+            let slot = random.below(seen + 1);
+            if slot < count as u64 {
+                reservoir[slot as usize] = (seen, row);
+            }
+        }
+    }
+    reservoir.sort_by_key(|(seen, _)| *seen);
+    for (_, row) in &reservoir {
+        writer.write_record(record(row))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn record(row: &[Cell]) -> Vec<String> {
+    row.iter().map(Cell::to_string).collect()
+}
+
+/// The SplitMix64 generator, small and good enough to pick sample rows.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, without the bias of a plain modulo.
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Column type inference for `--schema` and `CREATE TABLE` generation for `--ddl`.
+
+use clap::ValueEnum;
+use serde_json::json;
+use std::io::{self, Write};
+
+use crate::Result;
+
+/// Number of distinct example values kept per column.
+const MAX_EXAMPLES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum InferredType {
+    Int,
+    Float,
+    Bool,
+    Date,
+    String,
+}
+
+impl InferredType {
+    pub fn name(self) -> &'static str {
+        match self {
+            InferredType::Int => "int",
+            InferredType::Float => "float",
+            InferredType::Bool => "bool",
+            InferredType::Date => "date",
+            InferredType::String => "string",
+        }
+    }
+}
+
+/// How a numeric cell's style presents it. Date-styled serials are
+/// reported as dates even when they are written as plain numbers.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum DateStyle {
+    #[default]
+    None,
+    Date,
+    DateTime,
+}
+
+/// What has been observed in one column. A type stays possible until a
+/// non-empty value contradicts it.
+pub struct ColumnSchema {
+    pub name: String,
+    pub nullable: bool,
+    pub max_length: usize,
+    pub examples: Vec<String>,
+    values: usize,
+    maybe_int: bool,
+    maybe_float: bool,
+    maybe_bool: bool,
+    maybe_date: bool,
+    /// Whether any date value carried a time of day
+    has_time: bool,
+}
+
+impl ColumnSchema {
+    fn new(name: &str) -> Self {
+        ColumnSchema {
+            name: name.to_string(),
+            nullable: false,
+            max_length: 0,
+            examples: Vec::new(),
+            values: 0,
+            maybe_int: true,
+            maybe_float: true,
+            maybe_bool: true,
+            maybe_date: true,
+            has_time: false,
+        }
+    }
+
+    fn observe(&mut self, value: &str, style: DateStyle, preserve_leading_zeros: bool) {
+        if value.is_empty() {
+            self.nullable = true;
+            return;
+        }
+        self.values += 1;
+        self.max_length = self.max_length.max(value.chars().count());
+        if self.examples.len() < MAX_EXAMPLES && !self.examples.iter().any(|e| e == value) {
+            self.examples.push(value.to_string());
+        }
+
+        if style != DateStyle::None {
+            self.maybe_int = false;
+            self.maybe_float = false;
+            self.maybe_bool = false;
+            self.has_time |= self.maybe_date && style == DateStyle::DateTime;
+            return;
+        }
+
+        let trimmed = value.trim();
+        // Identifiers like 00420 lose their zeros in a numeric column
+        let padded = preserve_leading_zeros && trimmed.len() > 1 && trimmed.starts_with('0') && trimmed.as_bytes()[1].is_ascii_digit();
+        self.maybe_int &= !padded && trimmed.parse::<i64>().is_ok();
+        self.maybe_float &= !padded && trimmed.parse::<f64>().is_ok_and(f64::is_finite);
+        self.maybe_bool &= trimmed.eq_ignore_ascii_case("true") || trimmed.eq_ignore_ascii_case("false");
+        self.maybe_date &= is_iso_date(trimmed);
+        self.has_time |= self.maybe_date && trimmed.len() > 10;
+    }
+
+    /// The narrowest type matching every non-empty value. Columns without any
+    /// value are reported as strings.
+    pub fn inferred_type(&self) -> InferredType {
+        if self.values == 0 {
+            InferredType::String
+        } else if self.maybe_bool {
+            InferredType::Bool
+        } else if self.maybe_int {
+            InferredType::Int
+        } else if self.maybe_float {
+            InferredType::Float
+        } else if self.maybe_date {
+            InferredType::Date
+        } else {
+            InferredType::String
+        }
+    }
+}
+
+/// Columns of one converted sheet, named after its header row.
+pub struct SheetSchema {
+    pub name: String,
+    pub rows: usize,
+    pub columns: Vec<ColumnSchema>,
+    /// infer values with leading zeros as strings
+    preserve_leading_zeros: bool,
+}
+
+impl SheetSchema {
+    pub fn new(name: &str, preserve_leading_zeros: bool) -> Self {
+        SheetSchema {
+            name: name.to_string(),
+            rows: 0,
+            columns: Vec::new(),
+            preserve_leading_zeros,
+        }
+    }
+
+    pub fn set_header(&mut self, header: &[String]) {
+        self.columns = header.iter().map(|name| ColumnSchema::new(name)).collect();
+    }
+
+    /// Records a data row. Cells past the header get `column_N` columns and
+    /// cells missing from the row make their column nullable. `styles` marks
+    /// the date-styled numeric cells of the row.
+    pub fn observe(&mut self, row: &[String], styles: &[DateStyle]) {
+        while self.columns.len() < row.len() {
+            let mut column = ColumnSchema::new(&format!("column_{}", self.columns.len() + 1));
+            // Earlier rows had no value here
+            column.nullable = self.rows > 0;
+            self.columns.push(column);
+        }
+        for (idx, column) in self.columns.iter_mut().enumerate() {
+            let style = styles.get(idx).copied().unwrap_or_default();
+            column.observe(row.get(idx).map(String::as_str).unwrap_or_default(), style, self.preserve_leading_zeros);
+        }
+        self.rows += 1;
+    }
+}
+
+/// Accepts `YYYY-MM-DD`, optionally followed by a time after `T` or a space.
+pub(crate) fn is_iso_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() < 10 || !matches!(b.get(10), None | Some(b'T' | b' ')) {
+        return false;
+    }
+    let digits = |range: std::ops::Range<usize>| b[range].iter().all(u8::is_ascii_digit);
+    if !(digits(0..4) && b[4] == b'-' && digits(5..7) && b[7] == b'-' && digits(8..10)) {
+        return false;
+    }
+    let month: u32 = s[5..7].parse().unwrap_or(0);
+    let day: u32 = s[8..10].parse().unwrap_or(0);
+    (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SqlDialect {
+    Postgres,
+    Mysql,
+    Duckdb,
+}
+
+impl SqlDialect {
+    fn quote_identifier(self, name: &str) -> String {
+        match self {
+            SqlDialect::Mysql => format!("`{}`", name.replace('`', "``")),
+            SqlDialect::Postgres | SqlDialect::Duckdb => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    fn column_type(self, column: &ColumnSchema) -> String {
+        let date = if column.has_time { "TIMESTAMP" } else { "DATE" };
+        match (self, column.inferred_type()) {
+            (SqlDialect::Mysql, InferredType::Date) if column.has_time => "DATETIME".to_string(),
+            (_, InferredType::Date) => date.to_string(),
+            (_, InferredType::Int) => "BIGINT".to_string(),
+            (SqlDialect::Postgres, InferredType::Float) => "DOUBLE PRECISION".to_string(),
+            (_, InferredType::Float) => "DOUBLE".to_string(),
+            (_, InferredType::Bool) => "BOOLEAN".to_string(),
+            (SqlDialect::Mysql, InferredType::String) if column.max_length <= 16383 => {
+                format!("VARCHAR({})", column.max_length.max(1))
+            }
+            (SqlDialect::Duckdb, InferredType::String) => "VARCHAR".to_string(),
+            (_, InferredType::String) => "TEXT".to_string(),
+        }
+    }
+}
+
+/// Writes one `CREATE TABLE` statement per sheet. Table and column names are
+/// converted to unique snake_case identifiers.
+pub fn write_ddl<W: Write>(schemas: &[SheetSchema], dialect: SqlDialect, out: &mut W) -> Result<()> {
+    let mut tables: Vec<String> = schemas.iter().map(|sheet| sheet.name.clone()).collect();
+    crate::transform::snake_case_names(&mut tables);
+
+    for (i, (sheet, table)) in schemas.iter().zip(&tables).enumerate() {
+        let mut names: Vec<String> = sheet.columns.iter().map(|column| column.name.clone()).collect();
+        crate::transform::snake_case_names(&mut names);
+
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "CREATE TABLE {} (", dialect.quote_identifier(table))?;
+        for (j, (column, name)) in sheet.columns.iter().zip(&names).enumerate() {
+            writeln!(
+                out,
+                "    {} {}{}{}",
+                dialect.quote_identifier(name),
+                dialect.column_type(column),
+                if column.nullable || sheet.rows == 0 { "" } else { " NOT NULL" },
+                if j + 1 < names.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(out, ");")?;
+    }
+    Ok(())
+}
+
+/// Quotes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes the schemas as a JSON document `{"sheets": [...]}`.
+pub fn write_schema<W: Write>(schemas: &[SheetSchema], out: &mut W) -> Result<()> {
+    let sheets: Vec<_> = schemas
+        .iter()
+        .map(|sheet| {
+            let columns: Vec<_> = sheet
+                .columns
+                .iter()
+                .map(|column| {
+                    json!({
+                        "name": column.name,
+                        "type": column.inferred_type().name(),
+                        "nullable": column.nullable,
+                        "max_length": column.max_length,
+                        "examples": column.examples,
+                    })
+                })
+                .collect();
+            json!({"name": sheet.name, "rows": sheet.rows, "columns": columns})
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &json!({ "sheets": sheets })).map_err(io::Error::from)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn date_styled_serials_are_dates() {
+        let mut schema = SheetSchema::new("s", false);
+        schema.set_header(&row(&["when", "at", "count"]));
+        schema.observe(&row(&["45000", "45000", "3"]), &[DateStyle::Date, DateStyle::Date]);
+        schema.observe(&row(&["45001", "45000.25", "4"]), &[DateStyle::Date, DateStyle::DateTime]);
+        let types: Vec<_> = schema.columns.iter().map(|column| column.inferred_type().name()).collect();
+        assert_eq!(types, ["date", "date", "int"]);
+        assert!(!schema.columns[0].has_time);
+        assert!(schema.columns[1].has_time);
+    }
+
+    #[test]
+    fn unstyled_serials_stay_numbers() {
+        let mut schema = SheetSchema::new("s", false);
+        schema.set_header(&row(&["when"]));
+        schema.observe(&row(&["45000"]), &[DateStyle::Date]);
+        schema.observe(&row(&["45001"]), &[]);
+        assert_eq!(schema.columns[0].inferred_type().name(), "string");
+    }
+
+    #[test]
+    fn ddl_maps_date_styled_columns() {
+        let mut schema = SheetSchema::new("Sales", false);
+        schema.set_header(&row(&["When", "At"]));
+        schema.observe(&row(&["45000", "45000.25"]), &[DateStyle::Date, DateStyle::DateTime]);
+        let ddl = |dialect| {
+            let mut out = Vec::new();
+            write_ddl(std::slice::from_ref(&schema), dialect, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(ddl(SqlDialect::Postgres), "CREATE TABLE \"sales\" (\n    \"when\" DATE NOT NULL,\n    \"at\" TIMESTAMP NOT NULL\n);\n");
+        assert!(ddl(SqlDialect::Mysql).contains("`at` DATETIME NOT NULL"));
+        assert!(ddl(SqlDialect::Duckdb).contains("\"at\" TIMESTAMP NOT NULL"));
+    }
+
+    #[test]
+    fn schema_json_keeps_field_order() {
+        let mut schema = SheetSchema::new("Sheet \"1\"", false);
+        schema.set_header(&row(&["id"]));
+        schema.observe(&row(&["7"]), &[]);
+        let mut out = Vec::new();
+        write_schema(&[schema], &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["sheets"][0]["name"], "Sheet \"1\"");
+        assert_eq!(value["sheets"][0]["columns"][0]["type"], "int");
+        assert!(text.find("\"name\": \"id\"").unwrap() < text.find("\"type\"").unwrap());
+        assert!(text.ends_with("}\n"));
+    }
+}
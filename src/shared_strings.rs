@@ -0,0 +1,551 @@
+//! The shared string table.
+
+use flate2::read::DeflateDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use tracing::{debug, instrument};
+use zip::read::ZipArchive;
+use zip::CompressionMethod;
+
+use crate::archive::{eq_local, open_part, Bounded};
+use crate::limits::{XmlGuard, XmlLimits};
+use crate::richtext::{Phonetic, RichText, StringBuilder};
+use crate::sheet::InvalidUtf8;
+use crate::text::{decode_text, unescape_text};
+use crate::{Result, SheetInfo};
+
+const SHARED_STRINGS_PART: &str = "xl/sharedStrings.xml";
+
+/// Strings sent at once by the background parser.
+const BATCH_SIZE: usize = 4096;
+
+/// Uncompressed size of `xl/sharedStrings.xml` above which the table is kept
+/// in a temporary file instead of memory, unless `--max-memory` sets the limit.
+const DISK_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// The shared string table of a conversion, which may still be parsed on a
+/// background thread while the first sheet is converted. Lookups past the
+/// strings received so far wait for the parser to get there.
+pub struct SharedStrings {
+    store: Store,
+    pending: Option<Receiver<Result<StringArena>>>,
+    /// With `--phonetic only` the reading of each string is stored after it
+    readings: bool,
+}
+
+enum Store {
+    Memory(StringArena),
+    Disk(DiskStrings),
+    /// A complete table used by several conversions at once, see [`SharedStrings::share`]
+    Shared(Arc<Store>),
+}
+
+/// Strings stored back to back in one buffer and found by their end offsets,
+/// instead of taking an allocation each.
+#[derive(Debug, Default)]
+pub struct StringArena {
+    text: String,
+    ends: Vec<usize>,
+}
+
+impl StringArena {
+    /// Appends a string after the last one.
+    pub fn push(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.ends.push(self.text.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// The string at `idx`, `None` when the arena is shorter.
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        let end = *self.ends.get(idx)?;
+        let start = if idx == 0 { 0 } else { self.ends[idx - 1] };
+        Some(&self.text[start..end])
+    }
+
+    /// Iterates the strings in order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.len()).filter_map(|idx| self.get(idx))
+    }
+
+    fn append(&mut self, other: StringArena) {
+        let offset = self.text.len();
+        self.text.push_str(&other.text);
+        self.ends.extend(other.ends.iter().map(|end| end + offset));
+    }
+}
+
+impl SharedStrings {
+    /// The string at `idx`, `None` when the table is shorter.
+    pub fn get(&mut self, idx: usize) -> Result<Option<&str>> {
+        let entry = if self.readings { idx.saturating_mul(2) } else { idx };
+        self.entry(entry)
+    }
+
+    /// The phonetic reading of the string at `idx`, only kept for
+    /// `--phonetic only`.
+    pub fn reading(&mut self, idx: usize) -> Result<Option<&str>> {
+        if !self.readings {
+            return Ok(None);
+        }
+        self.entry(idx.saturating_mul(2).saturating_add(1))
+    }
+
+    fn entry(&mut self, idx: usize) -> Result<Option<&str>> {
+        while idx >= self.store.len() {
+            let Some(pending) = &self.pending else {
+                break;
+            };
+            match pending.recv() {
+                Ok(Ok(batch)) => {
+                    self.store.extend(batch)?;
+                    // Take what else is ready, the disk store remaps once per lookup miss
+                    loop {
+                        match pending.try_recv() {
+                            Ok(Ok(batch)) => self.store.extend(batch)?,
+                            Ok(Err(err)) => {
+                                self.pending = None;
+                                return Err(err);
+                            }
+                            // Nothing ready yet, or the parser is done
+                            Err(_) => break,
+                        }
+                    }
+                }
+                Ok(Err(err)) => {
+                    self.pending = None;
+                    return Err(err);
+                }
+                // The parser is done
+                Err(_) => self.pending = None,
+            }
+        }
+        self.store.get(idx)
+    }
+
+    /// Waits for the background parser to finish and returns a handle on the
+    /// complete table that can be moved to another thread, ex. to convert
+    /// sheets in parallel.
+    pub fn share(&mut self) -> Result<SharedStrings> {
+        self.entry(usize::MAX)?;
+        // Map every string of a disk table before it can only be read
+        self.store.get(self.store.len().saturating_sub(1))?;
+        let store = match std::mem::replace(&mut self.store, Store::Memory(StringArena::default())) {
+            Store::Shared(store) => store,
+            store => Arc::new(store),
+        };
+        self.store = Store::Shared(Arc::clone(&store));
+        Ok(SharedStrings {
+            store: Store::Shared(store),
+            pending: None,
+            readings: self.readings,
+        })
+    }
+}
+
+impl From<StringArena> for SharedStrings {
+    fn from(strings: StringArena) -> Self {
+        SharedStrings {
+            store: Store::Memory(strings),
+            pending: None,
+            readings: false,
+        }
+    }
+}
+
+impl Store {
+    /// A disk store for parts over `threshold` where a temporary file can be
+    /// created, memory otherwise.
+    fn for_size(bytes: u64, threshold: u64) -> Self {
+        if bytes <= threshold {
+            return Store::Memory(StringArena::default());
+        }
+        match DiskStrings::new() {
+            Ok(disk) => {
+                debug!(bytes, "keeping shared strings in a temporary file");
+                Store::Disk(disk)
+            }
+            Err(err) => {
+                debug!(error = %err, "no temporary file, keeping shared strings in memory");
+                Store::Memory(StringArena::default())
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Store::Memory(strings) => strings.len(),
+            Store::Disk(disk) => disk.ends.len(),
+            Store::Shared(store) => store.len(),
+        }
+    }
+
+    fn push(&mut self, s: &str) -> Result<()> {
+        match self {
+            Store::Memory(strings) => strings.push(s),
+            Store::Disk(disk) => disk.push(s)?,
+            Store::Shared(_) => unreachable!("shared tables are complete"),
+        }
+        Ok(())
+    }
+
+    fn extend(&mut self, batch: StringArena) -> Result<()> {
+        match self {
+            Store::Memory(strings) => strings.append(batch),
+            Store::Disk(disk) => {
+                for s in batch.iter() {
+                    disk.push(s)?;
+                }
+            }
+            Store::Shared(_) => unreachable!("shared tables are complete"),
+        }
+        Ok(())
+    }
+
+    fn get(&mut self, idx: usize) -> Result<Option<&str>> {
+        match self {
+            Store::Memory(strings) => Ok(strings.get(idx)),
+            Store::Disk(disk) => Ok(disk.get(idx)?),
+            Store::Shared(store) => Ok(store.get_mapped(idx)),
+        }
+    }
+
+    /// Like `get`, for tables that no longer change.
+    fn get_mapped(&self, idx: usize) -> Option<&str> {
+        match self {
+            Store::Memory(strings) => strings.get(idx),
+            Store::Disk(disk) => disk.get_mapped(idx),
+            Store::Shared(store) => store.get_mapped(idx),
+        }
+    }
+}
+
+/// Strings appended to an unnamed temporary file and read back through a
+/// memory map, with only their end offsets held in memory.
+struct DiskStrings {
+    file: BufWriter<File>,
+    ends: Vec<u64>,
+    map: Option<Mmap>,
+}
+
+impl DiskStrings {
+    fn new() -> io::Result<Self> {
+        Ok(DiskStrings {
+            file: BufWriter::new(tempfile::tempfile()?),
+            ends: Vec::new(),
+            map: None,
+        })
+    }
+
+    fn push(&mut self, s: &str) -> io::Result<()> {
+        self.file.write_all(s.as_bytes())?;
+        let start = self.ends.last().copied().unwrap_or(0);
+        self.ends.push(start + s.len() as u64);
+        Ok(())
+    }
+
+    fn get(&mut self, idx: usize) -> io::Result<Option<&str>> {
+        let Some(&end) = self.ends.get(idx) else {
+            return Ok(None);
+        };
+        if end > 0 && self.map.as_ref().is_none_or(|map| (map.len() as u64) < end) {
+            self.file.flush()?;
+            // SAFETY: the file is private to this process and only ever appended to,
+            // so the mapped range does not change while it is borrowed
+            self.map = Some(unsafe { Mmap::map(self.file.get_ref())? });
+        }
+        Ok(self.get_mapped(idx))
+    }
+
+    /// The string at `idx` if it is already mapped.
+    fn get_mapped(&self, idx: usize) -> Option<&str> {
+        let end = *self.ends.get(idx)?;
+        let start = if idx == 0 { 0 } else { self.ends[idx - 1] };
+        if start == end {
+            return Some("");
+        }
+        let bytes = self.map.as_ref()?.get(start as usize..end as usize)?;
+        // SAFETY: the range holds exactly the bytes of a `&str` written by `push`
+        Some(unsafe { std::str::from_utf8_unchecked(bytes) })
+    }
+}
+
+/// Starts parsing `xl/sharedStrings.xml` on a background thread so that the
+/// first rows can be converted before a large table is done. Only the
+/// compressed part is read up front. Where threads are not available, and for
+/// compression methods other than deflate, the table is loaded before returning.
+///
+/// With `max_memory`, tables whose XML takes more than half of it are kept in
+/// a temporary file.
+#[instrument(skip_all)]
+pub fn spawn_shared_strings<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    rich_text: RichText,
+    phonetic: Phonetic,
+    max_memory: Option<u64>,
+    limits: XmlLimits,
+    invalid_utf8: InvalidUtf8,
+) -> Result<SharedStrings> {
+    let Some(index) = archive.index_for_name(SHARED_STRINGS_PART) else {
+        debug!("workbook has no shared string table");
+        return Ok(StringArena::default().into());
+    };
+    let mut raw = archive.by_index_raw(index)?;
+    let compression = raw.compression();
+    let store = Store::for_size(raw.size(), max_memory.map_or(DISK_THRESHOLD, |max| max / 2));
+    if !matches!(compression, CompressionMethod::Stored | CompressionMethod::Deflated) {
+        drop(raw);
+        return load_into_store(archive, store, rich_text, phonetic, limits, invalid_utf8);
+    }
+    let mut compressed = Vec::with_capacity(raw.compressed_size() as usize);
+    raw.read_to_end(&mut compressed)?;
+    let size = raw.size();
+    debug!(compressed = compressed.len(), bytes = size, "parsing shared strings in the background");
+    drop(raw);
+
+    let (sender, receiver) = mpsc::sync_channel(16);
+    let spawned = thread::Builder::new().name("shared-strings".to_string()).spawn(move || {
+        let part: Box<dyn Read> = match compression {
+            CompressionMethod::Stored => Box::new(Cursor::new(compressed)),
+            _ => Box::new(Bounded::new(DeflateDecoder::new(Cursor::new(compressed)), size)),
+        };
+        let mut batch = StringArena::default();
+        let result = parse_shared_strings(BufReader::new(part), rich_text, phonetic, limits, invalid_utf8, |s, reading| {
+            batch.push(&s);
+            if phonetic == Phonetic::Only {
+                batch.push(&reading);
+            }
+            if batch.len() < BATCH_SIZE {
+                return true;
+            }
+            // A closed channel means the conversion ended early
+            sender.send(Ok(std::mem::take(&mut batch))).is_ok()
+        });
+        let _ = sender.send(result.map(|()| batch));
+    });
+    match spawned {
+        Ok(_) => Ok(SharedStrings {
+            store,
+            pending: Some(receiver),
+            readings: phonetic == Phonetic::Only,
+        }),
+        Err(err) => {
+            debug!(error = %err, "no background thread, loading shared strings first");
+            load_into_store(archive, store, rich_text, phonetic, limits, invalid_utf8)
+        }
+    }
+}
+
+/// Whether converting `sheets` may look up the shared string table. The table
+/// is skipped when the workbook has none, and when a single sheet, scanned
+/// for `t="s"` cells because its XML is no larger than the table's, has none.
+#[instrument(skip_all)]
+pub fn needs_shared_strings<R: Read + Seek>(archive: &mut ZipArchive<R>, sheets: &[SheetInfo]) -> Result<bool> {
+    let Some(table) = archive.index_for_name(SHARED_STRINGS_PART) else {
+        return Ok(false);
+    };
+    let [sheet] = sheets else {
+        return Ok(true);
+    };
+    let table_size = archive.by_index_raw(table)?.size();
+    // A missing sheet part is reported by the conversion
+    let Some(index) = archive.index_for_name(&sheet.path) else {
+        return Ok(true);
+    };
+    let part = Bounded::entry(archive.by_index(index)?);
+    if part.size() > table_size {
+        return Ok(true);
+    }
+    let found = has_shared_string_cell(part)?;
+    debug!(sheet = %sheet.name, found, "scanned sheet for shared string cells");
+    Ok(found)
+}
+
+/// Whether `xml` holds a `t="s"` attribute. Matches outside of cell tags only
+/// cost loading the table.
+fn has_shared_string_cell(mut xml: impl Read) -> Result<bool> {
+    let pattern = regex::bytes::Regex::new(r#"\bt\s*=\s*(?:"s"|'s')"#)?;
+    // Kept from the previous read for matches across the boundary
+    const OVERLAP: usize = 64;
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    loop {
+        let read = xml.read(&mut buf[len..])?;
+        if read == 0 {
+            return Ok(false);
+        }
+        len += read;
+        if pattern.is_match(&buf[..len]) {
+            return Ok(true);
+        }
+        let keep = len.min(OVERLAP);
+        buf.copy_within(len - keep..len, 0);
+        len = keep;
+    }
+}
+
+/// Reads the whole table into `store` on the calling thread.
+fn load_into_store<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    mut store: Store,
+    rich_text: RichText,
+    phonetic: Phonetic,
+    limits: XmlLimits,
+    invalid_utf8: InvalidUtf8,
+) -> Result<SharedStrings> {
+    let file = open_part(archive, SHARED_STRINGS_PART)?;
+    let mut failed = None;
+    let readings = phonetic == Phonetic::Only;
+    parse_shared_strings(BufReader::new(file), rich_text, phonetic, limits, invalid_utf8, |s, reading| {
+        let pushed = store.push(&s).and_then(|()| if readings { store.push(&reading) } else { Ok(()) });
+        match pushed {
+            Ok(()) => true,
+            Err(err) => {
+                failed = Some(err);
+                false
+            }
+        }
+    })?;
+    if let Some(err) = failed {
+        return Err(err);
+    }
+    Ok(SharedStrings {
+        store,
+        pending: None,
+        readings,
+    })
+}
+
+/// Reads `xl/sharedStrings.xml` into a table indexed by the `<v>` of `t="s"` cells.
+#[instrument(skip_all)]
+pub fn load_shared_strings<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    rich_text: RichText,
+    phonetic: Phonetic,
+) -> Result<StringArena> {
+    let mut strings = StringArena::default();
+    let Ok(file) = open_part(archive, SHARED_STRINGS_PART) else {
+        debug!("workbook has no shared string table");
+        return Ok(strings);
+    };
+    debug!(bytes = file.size(), "reading shared strings");
+
+    parse_shared_strings(BufReader::new(file), rich_text, phonetic, XmlLimits::default(), InvalidUtf8::Error, |s, _| {
+        strings.push(&s);
+        true
+    })?;
+    debug!(count = strings.len(), "loaded shared strings");
+    Ok(strings)
+}
+
+/// Parses the `<si>` entries of a shared string part in order, passing each
+/// with its reading for `--phonetic only` to `emit` until it returns false.
+fn parse_shared_strings<B: BufRead>(
+    part: B,
+    rich_text: RichText,
+    phonetic: Phonetic,
+    limits: XmlLimits,
+    invalid_utf8: InvalidUtf8,
+    mut emit: impl FnMut(String, String) -> bool,
+) -> Result<()> {
+    let mut reader = Reader::from_reader(part);
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+    let mut guard = XmlGuard::new(limits);
+    let mut current = StringBuilder::new(rich_text, phonetic);
+    let mut in_string = false;
+    let mut in_phonetic = false;
+    let mut index = 0;
+    let location = |index| move || format!("Shared string {}", index);
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        guard.check(&event)?;
+        match event {
+            Event::Start(e) if eq_local(e.name().as_ref(), b"si") => {
+                current.finish();
+                in_string = true;
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"si") => {
+                if in_string {
+                    let (text, reading) = current.finish_with_reading();
+                    if !emit(text, reading) {
+                        return Ok(());
+                    }
+                }
+                in_string = false;
+                index += 1;
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => {
+                in_phonetic = true;
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => {
+                in_phonetic = false;
+            }
+            Event::Start(e) | Event::Empty(e) if in_string && !in_phonetic => {
+                current.start(&e)?;
+            }
+            Event::End(e) if in_string && !in_phonetic => {
+                current.end(e.name().as_ref());
+            }
+            Event::Text(t) if in_string && in_phonetic => {
+                current.push_phonetic(&unescape_text(&t, invalid_utf8, location(index))?);
+            }
+            Event::Text(t) if in_string && !in_phonetic => {
+                current.push_str(&unescape_text(&t, invalid_utf8, location(index))?);
+            }
+            Event::CData(t) if in_string && !in_phonetic => {
+                current.push_str(&decode_text(&t, invalid_utf8, location(index))?);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SharedStrings, Store, StringArena};
+    use crate::Xlsx2CsvError;
+    use std::sync::mpsc;
+
+    fn batch(strings: &[&str]) -> StringArena {
+        let mut arena = StringArena::default();
+        for s in strings {
+            arena.push(s);
+        }
+        arena
+    }
+
+    #[test]
+    fn parser_error_after_a_batch_is_returned() {
+        let (sender, receiver) = mpsc::sync_channel(4);
+        sender.send(Ok(batch(&["a", "b"]))).unwrap();
+        sender.send(Ok(batch(&["c"]))).unwrap();
+        sender.send(Err(Xlsx2CsvError::InvalidOption("malformed".to_string()))).unwrap();
+        drop(sender);
+        let mut strings = SharedStrings {
+            store: Store::Memory(StringArena::default()),
+            pending: Some(receiver),
+            readings: false,
+        };
+        // The lookup waits for the first batch and drains the rest with it
+        assert!(matches!(strings.get(1), Err(Xlsx2CsvError::InvalidOption(_))));
+        assert_eq!(strings.get(2).unwrap(), Some("c"));
+        assert_eq!(strings.get(3).unwrap(), None);
+    }
+}
@@ -0,0 +1,1135 @@
+//! Streaming conversion of a worksheet part into output rows.
+
+use clap::ValueEnum;
+use quick_xml::events::{BytesText, Event};
+use quick_xml::Reader;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufReader, Cursor, Read, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use tracing::{debug, instrument, trace};
+
+use crate::annotations::CellAnnotations;
+use crate::archive::eq_local;
+use crate::autofilter::AutoFilter;
+use crate::progress::{Checkpoint, Progress, CHECKPOINT_INTERVAL, REPORT_INTERVAL};
+use crate::limits::XmlGuard;
+use crate::reorder::reorder_rows;
+use crate::richtext::StringBuilder;
+use crate::schema::{DateStyle, SheetSchema};
+use crate::shared_strings::SharedStrings;
+use crate::text::{decode_text, strip_control_chars, unescape_text};
+use crate::transform::{ColumnType, SheetTransforms, Transforms};
+use crate::warning::{warn, Warning};
+use crate::workbook::external_link_indexes;
+use crate::writer::{write_long_cells, OutputFormat, RowWriter};
+use crate::styles::{CellStyle, NumberFormat};
+use crate::{formula, styles, Options, Result, Xlsx2CsvError};
+
+/// What happens to line breaks and tabs inside cell values.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum NewlineHandling {
+    Keep,
+    Escape,
+    Space,
+}
+
+/// Where cell hyperlinks go.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum HyperlinkMode {
+    Url,
+    Append,
+    SeparateFile,
+}
+
+/// Columns in a worksheet, XFD being the last, and the default of
+/// [`Options::max_columns`](crate::Options::max_columns).
+pub(crate) const MAX_COLUMNS: usize = 16384;
+
+/// What happens to cells beyond [`Options::max_columns`](crate::Options::max_columns).
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum MaxColumnsAction {
+    Error,
+    Clip,
+}
+
+/// What happens to cells that do not fit the type `--types` gives their column.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum TypeErrors {
+    /// Keep the value with a warning
+    Warn,
+    Fail,
+}
+
+/// What happens to cell text that is not valid UTF-8.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum InvalidUtf8 {
+    /// Write U+FFFD in place of each invalid sequence
+    Replace,
+    Error,
+    /// Drop the invalid bytes
+    Skip,
+}
+
+/// A rectangular block of cells such as `B2:D20`, limiting the converted
+/// rows and columns of each sheet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellRange {
+    /// 1-based first and last row
+    pub rows: (usize, usize),
+    /// 0-based first and last column
+    pub columns: (usize, usize),
+}
+
+impl FromStr for CellRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').unwrap_or((s, s));
+        let corner = |cell: &str| {
+            let cell = cell.trim().replace('$', "");
+            column_index(&cell).zip(cell_row(&cell)).filter(|&(_, row)| row > 0)
+        };
+        match (corner(start), corner(end)) {
+            (Some((col1, row1)), Some((col2, row2))) => Ok(CellRange {
+                rows: (row1.min(row2), row1.max(row2)),
+                columns: (col1.min(col2), col1.max(col2)),
+            }),
+            _ => Err(format!("Invalid cell range '{}', expected ex. 'A1:D20'", s)),
+        }
+    }
+}
+
+impl CellRange {
+    /// Drops the cells of a row outside the range's columns. Long output
+    /// keeps the positions, since its records carry each cell's reference.
+    fn clip<T: Clone + Default>(&self, row: &mut Vec<T>, keep_positions: bool) {
+        let (first, last) = self.columns;
+        row.truncate(last + 1);
+        if keep_positions {
+            row.iter_mut().take(first).for_each(|cell| *cell = T::default());
+        } else {
+            row.drain(..first.min(row.len()));
+        }
+    }
+}
+
+/// Inserts the `--phonetic only` reading columns after the columns they read,
+/// named `<header>_phonetic` in the header row, along with their types and
+/// date styles.
+fn insert_readings(
+    record: &mut Vec<String>,
+    mut types: Option<&mut Vec<CellType>>,
+    dates: &mut Vec<DateStyle>,
+    readings: &mut [Option<String>],
+    is_header: bool,
+) {
+    for (idx, reading) in readings.iter_mut().enumerate().rev() {
+        let Some(reading) = reading.take() else {
+            continue;
+        };
+        if record.len() <= idx {
+            record.resize(idx + 1, String::new());
+        }
+        let value = if is_header { format!("{}_phonetic", record[idx]) } else { reading };
+        record.insert(idx + 1, value);
+        if let Some(types) = types.as_deref_mut() {
+            if types.len() <= idx {
+                types.resize(idx + 1, CellType::default());
+            }
+            types.insert(idx + 1, CellType::PlainStr);
+        }
+        if dates.len() > idx + 1 {
+            dates.insert(idx + 1, DateStyle::None);
+        }
+    }
+}
+
+/// Drops the cells of a row in the columns `--only-types` leaves out, the
+/// row starting at sheet column `offset`. Long output keeps the positions.
+fn keep_columns<T: Default>(row: &mut Vec<T>, kept: &[bool], offset: usize, keep_positions: bool) {
+    let is_kept = |idx: usize| kept.get(idx + offset).copied().unwrap_or(false);
+    if keep_positions {
+        row.iter_mut().enumerate().filter(|(idx, _)| !is_kept(*idx)).for_each(|(_, cell)| *cell = T::default());
+    } else {
+        let mut idx = 0;
+        row.retain(|_| {
+            idx += 1;
+            is_kept(idx - 1)
+        });
+    }
+}
+
+/// The type of a cell, from the `t` attribute of its `<c>` element.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CellType {
+    SharedString,
+    InlineStr,
+    Bool,
+    #[default]
+    Number,
+    Error,
+    PlainStr,
+}
+
+impl CellType {
+    /// Maps the `t` attribute of a `<c>` element; absent or unknown types are numbers.
+    pub fn from_attr(t: &str) -> Self {
+        Self::parse(t).unwrap_or(CellType::Number)
+    }
+
+    /// Maps the `t` attribute of a `<c>` element, `None` for types the format
+    /// does not define. ISO dates (`d`) are kept as they are, like numbers.
+    pub fn parse(t: &str) -> Option<Self> {
+        Some(match t {
+            "s" => CellType::SharedString,
+            "b" => CellType::Bool,
+            "inlineStr" => CellType::InlineStr,
+            "str" => CellType::PlainStr,
+            "e" => CellType::Error,
+            "n" | "d" => CellType::Number,
+            _ => return None,
+        })
+    }
+
+    /// Type name reported by `--format long`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CellType::SharedString | CellType::InlineStr | CellType::PlainStr => "string",
+            CellType::Bool => "bool",
+            CellType::Number => "number",
+            CellType::Error => "error",
+        }
+    }
+}
+
+/// A sheet selected for conversion and the per-sheet data loaded for it.
+pub struct SheetContext<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    /// Uncompressed size of the worksheet part
+    pub size: u64,
+    /// Set for the row chunks of `--parallel-rows` after the first one, which
+    /// continue a sheet whose header row was already written
+    pub after_header: bool,
+    /// Rows before the part, numbering the rows that lack an `r` attribute
+    pub row_offset: usize,
+    /// Last sheet row an interrupted conversion wrote; rows up to it, the
+    /// header included, are converted without being written
+    pub resume_after: Option<usize>,
+    /// Whether `--only-types` keeps each column, by 0-based sheet column
+    pub kept_columns: Option<&'a [bool]>,
+    /// Whether `--phonetic only` adds a reading column after each column, by
+    /// 0-based sheet column
+    pub phonetic_columns: Option<&'a [bool]>,
+    pub annotations: &'a CellAnnotations,
+    pub autofilter: Option<&'a AutoFilter>,
+    /// External workbook targets by link index - 1, only set for --report-external-links
+    pub external_links: &'a [String],
+    /// The number format of each cell style, only loaded for --infer-types, --dateformat and
+    /// --preserve-leading-zeros; dates only for the first two
+    pub number_formats: &'a [NumberFormat],
+    /// The number format of each cell style with its dates, only loaded for
+    /// --only-types, --schema and --ddl
+    pub column_formats: &'a [NumberFormat],
+    /// The look of each cell style, only loaded for --style-columns
+    pub cell_styles: &'a [CellStyle],
+    pub date1904: bool,
+}
+
+/// Converts the worksheet XML read from `part`, writing its rows to `writer`,
+/// and returns the number of rows written.
+#[instrument(skip_all, fields(sheet = sheet.name, path = sheet.path))]
+pub fn convert_sheet<P: Read, W: Write>(
+    part: P,
+    sheet: &SheetContext,
+    shared_strings: &mut SharedStrings,
+    writer: &mut RowWriter<W>,
+    options: &Options,
+    transforms: &Transforms,
+    mut schema: Option<&mut SheetSchema>,
+) -> Result<u64> {
+    let total_bytes = sheet.size;
+    debug!(bytes = total_bytes, "reading worksheet");
+    let part: Box<dyn Read + '_> = if options.reorder_rows {
+        Box::new(Cursor::new(reorder_rows(part)?))
+    } else {
+        Box::new(part)
+    };
+    let mut reader = Reader::from_reader(BufReader::new(part));
+    reader.trim_text(false);
+    // Row chunks end the elements their predecessors started
+    reader.check_end_names(false);
+
+    let mut buf = Vec::new();
+    let mut guard = XmlGuard::new(options.into());
+    let mut current_row = RowBuffer::default();
+    // The cells of current_row for the row-level steps, reusing its strings from row to row
+    let mut record: Vec<String> = Vec::new();
+    let long_format = options.format == OutputFormat::Long;
+    let track_types = long_format || options.infer_types;
+    // Cell types by column, only tracked for --format long and --infer-types, and
+    // formulas by column for --format long
+    let mut current_types: Vec<CellType> = Vec::new();
+    let mut current_formulas: Vec<String> = Vec::new();
+    let mut current_styles: Vec<usize> = Vec::new();
+    // Readings by column for --phonetic only, set in the columns that get a reading column
+    let mut current_readings: Vec<Option<String>> = Vec::new();
+    // Date-styled numeric cells by column, only tracked for --schema and --ddl
+    let track_dates = schema.is_some();
+    let mut current_dates: Vec<DateStyle> = Vec::new();
+    let mut inline_reading = String::new();
+    let mut current_value = String::new();
+    let mut inline_value = StringBuilder::new(options.rich_text, options.phonetic);
+    let mut current_col = 0;
+    // Cells without an `r` attribute follow the previous cell of their row
+    let mut next_col = 0;
+    let mut cell_type = CellType::Number;
+    let mut current_style: Option<usize> = None;
+    let mut in_value_tag = false;
+    let mut in_inline = false;
+    let mut in_phonetic = false;
+    let mut sheet_transforms: Option<SheetTransforms> = if sheet.after_header {
+        Some(transforms.bind(sheet.name, &[])?)
+    } else {
+        None
+    };
+    let newline_handling = options.newline_handling;
+    let mut current_row_number = sheet.row_offset;
+    let mut clipped_warned = false;
+    let mut in_formula = false;
+    let track_formulas = options.formulas || !sheet.external_links.is_empty();
+    let mut current_formula = String::new();
+    let mut shared_formula_id: Option<String> = None;
+    // Shared formula masters by `si`: formula text, row number and column
+    let mut shared_formulas: HashMap<String, (String, usize, usize)> = HashMap::new();
+    let mut seen_data = false;
+    let mut blank_run = 0usize;
+    // Data rows left by the filters, for --every
+    let mut filtered_rows = 0usize;
+    let mut rows_read = 0usize;
+    let mut rows_written = 0u64;
+    let report = |bytes_read: u64, rows_written: u64| {
+        if let Some(callback) = &options.progress {
+            callback(&Progress {
+                sheet: sheet.name,
+                bytes_read,
+                total_bytes,
+                rows_written,
+            });
+        }
+    };
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        guard.check(&event)?;
+        match event {
+            Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
+                if options.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                    return Err(Xlsx2CsvError::Cancelled);
+                }
+                rows_read += 1;
+                if rows_read.is_multiple_of(REPORT_INTERVAL) {
+                    trace!(rows_read, rows_written, bytes_read = reader.buffer_position(), "progress");
+                    report(reader.buffer_position() as u64, rows_written);
+                }
+                current_row.clear();
+                current_types.clear();
+                current_formulas.clear();
+                current_styles.clear();
+                current_readings.clear();
+                current_dates.clear();
+                next_col = 0;
+
+                let previous = current_row_number;
+                current_row_number = row_number(&e)?.unwrap_or(previous + 1);
+
+                // Rows missing from the XML between two present rows are blank too
+                if let Some(stop_after) = options.stop_at_blank_row {
+                    if seen_data && current_row_number > previous + 1 {
+                        blank_run += current_row_number - previous - 1;
+                        if blank_run >= stop_after {
+                            break;
+                        }
+                    }
+                }
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"row") => {
+                current_row.copy_into(&mut record);
+                sheet.annotations.apply(current_row_number, &mut record);
+                if let Some(columns) = sheet.phonetic_columns {
+                    for col in columns.iter().enumerate().filter(|(_, has_reading)| **has_reading).map(|(col, _)| col) {
+                        if current_readings.len() <= col {
+                            current_readings.resize(col + 1, None);
+                        }
+                        current_readings[col].get_or_insert_with(String::new);
+                    }
+                }
+
+                if let Some(stop_after) = options.stop_at_blank_row {
+                    if record.iter().all(|s| s.is_empty()) {
+                        if seen_data {
+                            blank_run += 1;
+                            if blank_run >= stop_after {
+                                break;
+                            }
+                        }
+                    } else {
+                        seen_data = true;
+                        blank_run = 0;
+                    }
+                }
+
+                if sheet.autofilter.is_some_and(|f| !f.keeps(current_row_number, &record)) {
+                    continue;
+                }
+
+                if let Some(range) = &options.range {
+                    if current_row_number > range.rows.1 {
+                        break;
+                    }
+                    if current_row_number < range.rows.0 {
+                        continue;
+                    }
+                    range.clip(&mut record, long_format);
+                    range.clip(&mut current_types, long_format);
+                    range.clip(&mut current_formulas, long_format);
+                    range.clip(&mut current_styles, long_format);
+                    range.clip(&mut current_readings, long_format);
+                    range.clip(&mut current_dates, long_format);
+                }
+                if let Some(kept) = sheet.kept_columns {
+                    // The range dropped the columns before its first one
+                    let offset = options.range.filter(|_| !long_format).map_or(0, |range| range.columns.0);
+                    keep_columns(&mut record, kept, offset, long_format);
+                    keep_columns(&mut current_types, kept, offset, long_format);
+                    keep_columns(&mut current_formulas, kept, offset, long_format);
+                    keep_columns(&mut current_styles, kept, offset, long_format);
+                    keep_columns(&mut current_readings, kept, offset, long_format);
+                    keep_columns(&mut current_dates, kept, offset, long_format);
+                }
+                if sheet.phonetic_columns.is_some() {
+                    let types = track_types.then_some(&mut current_types);
+                    insert_readings(&mut record, types, &mut current_dates, &mut current_readings, sheet_transforms.is_none());
+                }
+
+                // Skip empty rows if requested
+                if options.ignore_empty && record.iter().all(|s| s.is_empty()) {
+                    continue;
+                }
+
+                // Skip trailing empty columns if requested
+                if options.skip_empty_columns {
+                    while record.last().is_some_and(|s| s.is_empty()) {
+                        record.pop();
+                    }
+                }
+
+                // The first written row of each sheet is treated as its header
+                let is_header = sheet_transforms.is_none();
+                match &mut sheet_transforms {
+                    None => {
+                        sheet_transforms = Some(transforms.bind(sheet.name, &record)?);
+                        transforms.apply_header(&mut record);
+                        check_header(&record, options)?;
+                    }
+                    Some(bound) => {
+                        if !transforms.apply_row(bound, current_row_number, &mut record) {
+                            continue;
+                        }
+                    }
+                }
+                if !is_header {
+                    filtered_rows += 1;
+                    if options.every.is_some_and(|every| !(filtered_rows - 1).is_multiple_of(every)) {
+                        continue;
+                    }
+                }
+                // Data rows go through the transformations first, so --dedup sees them
+                let resumed = sheet.resume_after.is_some_and(|after| current_row_number <= after);
+                if resumed && !is_header {
+                    continue;
+                }
+                // --explode repeats the row once per piece of its delimited cells
+                let mut exploded = match &sheet_transforms {
+                    Some(bound) if !is_header => bound.explode(&record),
+                    _ => Vec::new(),
+                };
+                let records = if exploded.is_empty() {
+                    std::slice::from_mut(&mut record)
+                } else {
+                    exploded.as_mut_slice()
+                };
+                // A checkpoint falls between rows, after all the pieces of one
+                let mut checkpoint_due = false;
+                for record in records {
+                    if let Some(schema) = schema.as_deref_mut() {
+                        if is_header {
+                            schema.set_header(record);
+                        } else {
+                            schema.observe(record, &current_dates);
+                        }
+                    }
+
+                    if long_format {
+                        if let RowWriter::Csv(w) = writer {
+                            let formulas = options.formulas.then_some(current_formulas.as_slice());
+                            let styles = options.style_columns.then_some((current_styles.as_slice(), sheet.cell_styles));
+                            write_long_cells(w, sheet.name, current_row_number, record, &current_types, formulas, styles)?;
+                        }
+                        if let Some(totals) = &options.totals {
+                            totals.add_row(record);
+                        }
+                        rows_written += 1;
+                        if options.flush_every.is_some_and(|rows| rows_written.is_multiple_of(rows)) {
+                            writer.flush()?;
+                        }
+                        continue;
+                    }
+
+                    // Provenance columns go in front so the original column positions stay intact
+                    let mut provenance = Vec::new();
+                    if options.add_row_number {
+                        provenance.push(if is_header {
+                            "row_number".to_string()
+                        } else {
+                            current_row_number.to_string()
+                        });
+                    }
+                    if options.add_source_ref {
+                        provenance.push(if is_header {
+                            "source_ref".to_string()
+                        } else {
+                            format!("{}!A{}", quote_sheet_name(sheet.name), current_row_number)
+                        });
+                    }
+                    let provenance_types = [
+                        (options.add_row_number, CellType::Number),
+                        (options.add_source_ref, CellType::PlainStr),
+                    ];
+                    if !provenance.is_empty() {
+                        record.splice(0..0, provenance);
+                    }
+
+                    match writer {
+                        RowWriter::Csv(_) if resumed => {}
+                        RowWriter::Csv(w) => w.write_record(&*record)?,
+                        RowWriter::Json(json) if is_header => json.set_header(record),
+                        RowWriter::Json(json) => {
+                            let types: Vec<CellType> = provenance_types
+                                .iter()
+                                .filter(|(enabled, _)| *enabled)
+                                .map(|&(_, cell_type)| cell_type)
+                                .chain(current_types.iter().copied())
+                                .collect();
+                            json.write_row(record, &types)?;
+                        }
+                        RowWriter::Discard(_) => {}
+                    }
+                    if let Some(totals) = &options.totals {
+                        totals.add_row(record);
+                    }
+                    rows_written += 1;
+                    if options.flush_every.is_some_and(|rows| rows_written.is_multiple_of(rows)) {
+                        writer.flush()?;
+                    }
+                    checkpoint_due |= rows_written.is_multiple_of(CHECKPOINT_INTERVAL);
+                }
+                if let Some(callback) = options.checkpoint.as_ref().filter(|_| checkpoint_due) {
+                    writer.flush()?;
+                    callback(&Checkpoint { sheet: sheet.name, row: current_row_number, complete: false })?;
+                }
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
+                current_value.clear();
+                inline_reading.clear();
+                current_col = next_col;
+                cell_type = CellType::Number;
+                current_style = None;
+                in_value_tag = false;
+                in_inline = false;
+                in_phonetic = false;
+                in_formula = false;
+                current_formula.clear();
+
+                let mut unknown_type = None;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"t" => {
+                            let t = attr.unescape_value()?;
+                            cell_type = CellType::from_attr(&t);
+                            if options.strict && CellType::parse(&t).is_none() {
+                                unknown_type = Some(t.into_owned());
+                            }
+                        }
+                        b"s" => current_style = attr.unescape_value()?.trim().parse().ok(),
+                        b"r" => current_col = column_index(&attr.unescape_value()?).unwrap_or(next_col),
+                        _ => {}
+                    }
+                }
+                next_col = current_col + 1;
+                if let Some(t) = unknown_type {
+                    return Err(Xlsx2CsvError::InvalidCell {
+                        cell: format!("{}{}", column_name(current_col), current_row_number),
+                        reason: format!("unknown cell type '{}'", t),
+                    });
+                }
+            }
+            Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                let mut idx = next_col;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"r" {
+                        idx = column_index(&attr.unescape_value()?).unwrap_or(next_col);
+                    }
+                }
+                next_col = idx + 1;
+                if within_column_limit(idx, current_row_number, sheet.name, options, &mut clipped_warned)? {
+                    current_row.place(idx, "");
+                }
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"v") => {
+                in_value_tag = true;
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"v") => {
+                in_value_tag = false;
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"is") => {
+                in_inline = true;
+                inline_value.finish();
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"is") => {
+                in_inline = false;
+                let (text, reading) = inline_value.finish_with_reading();
+                current_value.push_str(&text);
+                inline_reading = reading;
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"rPh") => {
+                in_phonetic = true;
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"rPh") => {
+                in_phonetic = false;
+            }
+            Event::Start(e) if track_formulas && eq_local(e.name().as_ref(), b"f") => {
+                in_formula = true;
+                shared_formula_id = shared_formula_si(&e)?;
+            }
+            Event::Empty(e) if track_formulas && eq_local(e.name().as_ref(), b"f") => {
+                // A shared formula follower, derived from its master by offset
+                if let Some((text, row, col)) = shared_formula_si(&e)?.and_then(|si| shared_formulas.get(&si)) {
+                    current_formula = formula::shift_references(
+                        text,
+                        current_row_number as isize - *row as isize,
+                        current_col as isize - *col as isize,
+                    );
+                }
+            }
+            Event::End(e) if track_formulas && eq_local(e.name().as_ref(), b"f") => {
+                in_formula = false;
+                if let Some(si) = shared_formula_id.take() {
+                    shared_formulas.insert(si, (current_formula.clone(), current_row_number, current_col));
+                }
+            }
+            Event::Text(t) if in_formula => {
+                current_formula.push_str(&cell_text(&t, current_col, current_row_number, options)?);
+            }
+            Event::Start(e) | Event::Empty(e) if in_inline && !in_phonetic => {
+                inline_value.start(&e)?;
+            }
+            Event::End(e) if in_inline && !in_phonetic => {
+                inline_value.end(e.name().as_ref());
+            }
+            Event::Text(t) if in_inline && !in_phonetic => {
+                inline_value.push_str(&cell_text(&t, current_col, current_row_number, options)?);
+            }
+            Event::Text(t) if in_inline && in_phonetic => {
+                inline_value.push_phonetic(&cell_text(&t, current_col, current_row_number, options)?);
+            }
+            Event::CData(t) if in_inline && !in_phonetic => {
+                inline_value.push_str(&decode_text(&t, options.invalid_utf8, || cell_name(current_col, current_row_number))?);
+            }
+            Event::Text(t) if in_value_tag && !in_phonetic => {
+                current_value.push_str(&cell_text(&t, current_col, current_row_number, options)?);
+            }
+            Event::CData(t) if in_value_tag && !in_phonetic => {
+                current_value.push_str(&decode_text(&t, options.invalid_utf8, || cell_name(current_col, current_row_number))?);
+            }
+            Event::End(e) if eq_local(e.name().as_ref(), b"c") => {
+                // Cells past --max-columns leave no trace in the per-column
+                // vectors, a corrupted reference would size them to its column
+                if !within_column_limit(current_col, current_row_number, sheet.name, options, &mut clipped_warned)? {
+                    continue;
+                }
+                if options.strict {
+                    check_value(cell_type, &current_value, current_col, current_row_number)?;
+                }
+                // The reading goes to the reading column, the text stays in place
+                if sheet.phonetic_columns.is_some_and(|columns| columns.get(current_col) == Some(&true)) {
+                    let reading = match cell_type {
+                        CellType::SharedString => match current_value.trim().parse() {
+                            Ok(idx) => shared_strings.reading(idx)?.unwrap_or_default().to_string(),
+                            Err(_) => String::new(),
+                        },
+                        CellType::InlineStr => std::mem::take(&mut inline_reading),
+                        _ => String::new(),
+                    };
+                    place_cell(&mut current_readings, current_col, Some(reading));
+                }
+                // Borrowed from the parsed text or the shared string table until
+                // the cell is placed in the row, so the common case copies once
+                let mut value: Cow<str> = match cell_type {
+                    CellType::SharedString => match current_value.trim().parse::<usize>() {
+                        Ok(idx) => match shared_strings.get(idx)? {
+                            Some(s) => Cow::Borrowed(s),
+                            None => {
+                                missing_shared_string(idx, current_col, current_row_number, sheet.name, options)?;
+                                Cow::Borrowed("")
+                            }
+                        },
+                        Err(_) => Cow::Borrowed(&current_value),
+                    },
+                    CellType::Bool => match current_value.trim() {
+                        "1" => Cow::Borrowed("true"),
+                        "0" => Cow::Borrowed("false"),
+                        other => Cow::Borrowed(other),
+                    },
+                    CellType::InlineStr | CellType::PlainStr | CellType::Error | CellType::Number => {
+                        Cow::Borrowed(&current_value)
+                    }
+                };
+
+                // Header positions start at the first column --range keeps
+                let first_col = options.range.as_ref().filter(|_| !long_format).map_or(0, |range| range.columns.0);
+                let in_range = options.range.as_ref().is_none_or(|range| current_row_number <= range.rows.1);
+                let column_type = sheet_transforms
+                    .as_ref()
+                    .filter(|_| in_range)
+                    .zip(current_col.checked_sub(first_col))
+                    .and_then(|(bound, col)| bound.column_type(col));
+                let number_format = current_style.and_then(|s| sheet.number_formats.get(s)).copied().unwrap_or_default();
+                let is_date = number_format == NumberFormat::Date
+                    || column_type.is_some_and(|(column_type, _)| column_type == ColumnType::Date);
+                let mut date_converted = false;
+                if is_date && matches!(cell_type, CellType::Number) {
+                    let date = current_value.trim().parse().ok().and_then(|serial| match &options.date_format {
+                        Some(pattern) => styles::format_serial(serial, sheet.date1904, pattern),
+                        None => styles::serial_to_iso(serial, sheet.date1904),
+                    });
+                    if let Some(date) = date {
+                        value = Cow::Owned(date);
+                        cell_type = CellType::PlainStr;
+                        date_converted = true;
+                    }
+                }
+
+                // Serials left as numbers are still dates to --schema and --ddl
+                let dated = current_style.and_then(|s| sheet.column_formats.get(s)) == Some(&NumberFormat::Date);
+                if track_dates && dated && column_type.is_none() && matches!(cell_type, CellType::Number) {
+                    let serial: f64 = value.trim().parse().unwrap_or_default();
+                    let style = if serial.fract() == 0.0 { DateStyle::Date } else { DateStyle::DateTime };
+                    place_cell(&mut current_dates, current_col, style);
+                }
+
+                if options.preserve_leading_zeros && matches!(cell_type, CellType::Number) {
+                    let digits = value.trim();
+                    let whole = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+                    match number_format {
+                        NumberFormat::ZeroPadded(width) if whole => {
+                            if digits.len() < width {
+                                value = Cow::Owned(format!("{:0>width$}", digits));
+                            }
+                            cell_type = CellType::PlainStr;
+                        }
+                        NumberFormat::Text => cell_type = CellType::PlainStr,
+                        _ => {}
+                    }
+                }
+
+                if let Some((column_type, column)) = column_type.filter(|_| !value.trim().is_empty()) {
+                    match column_type.coerce(&value).map(|coerced| (coerced != *value).then(|| coerced.into_owned())) {
+                        Some(Some(coerced)) => value = Cow::Owned(coerced),
+                        Some(None) => {}
+                        None if date_converted => {}
+                        None => type_mismatch(&value, column_type, column, current_col, current_row_number, sheet.name, options)?,
+                    }
+                    cell_type = column_type.cell_type();
+                }
+
+                if !sheet.external_links.is_empty() {
+                    for link in external_link_indexes(&current_formula) {
+                        let target = sheet.external_links.get(link - 1).map_or("?", String::as_str);
+                        let cell = format!("{}{}", column_name(current_col), current_row_number);
+                        let message = format!(
+                            "{}!{} references external workbook [{}] '{}', its cached value may be stale",
+                            quote_sheet_name(sheet.name),
+                            cell,
+                            link,
+                            target
+                        );
+                        warn(options, Warning { message, sheet: Some(sheet.name.to_string()), cell: Some(cell) });
+                    }
+                }
+
+                if track_types {
+                    place_cell(&mut current_types, current_col, cell_type);
+                }
+                if long_format {
+                    if options.formulas {
+                        place_cell(&mut current_formulas, current_col, current_formula.clone());
+                    }
+                    if options.style_columns {
+                        place_cell(&mut current_styles, current_col, current_style.unwrap_or(0));
+                    }
+                } else if options.formulas && !current_formula.is_empty() {
+                    value = Cow::Owned(format!("={}", current_formula));
+                }
+
+                if options.strip_control_chars {
+                    value = strip_control_chars(value);
+                }
+
+                // Apply line break handling if requested
+                if newline_handling != NewlineHandling::Keep && value.contains(['\r', '\n', '\t']) {
+                    value = Cow::Owned(match newline_handling {
+                        NewlineHandling::Space => value.replace(['\r', '\n', '\t'], " "),
+                        _ => value.replace('\r', "\\r").replace('\n', "\\n").replace('\t', "\\t"),
+                    });
+                }
+
+                current_row.place(current_col, &value);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // The header row is written by the first chunk of a sheet; a resumed
+    // sheet only counts the rows written since
+    let data_rows = if sheet.after_header { rows_written } else { rows_written.saturating_sub(1) };
+    if sheet.resume_after.is_none() {
+        check_data_rows(data_rows, options)?;
+    }
+    if let Some(callback) = &options.checkpoint {
+        writer.flush()?;
+        callback(&Checkpoint { sheet: sheet.name, row: current_row_number, complete: true })?;
+    }
+
+    report(total_bytes, rows_written);
+    // A sheet split into --parallel-rows chunks counts once, with its first chunk
+    if let Some(totals) = options.totals.as_ref().filter(|_| !sheet.after_header) {
+        totals.sheets.fetch_add(1, Ordering::Relaxed);
+    }
+    debug!(rows_read, rows_written, "converted sheet");
+    Ok(rows_written)
+}
+
+/// Checks a cell's column against `--max-columns`, failing or warning once
+/// per sheet depending on `--max-columns-action`.
+fn within_column_limit(
+    idx: usize,
+    row_number: usize,
+    sheet_name: &str,
+    options: &Options,
+    warned: &mut bool,
+) -> Result<bool> {
+    if idx < options.max_columns {
+        return Ok(true);
+    }
+    let reference = format!("{}{}", column_name(idx), row_number);
+    match options.max_columns_action {
+        MaxColumnsAction::Error => Err(Xlsx2CsvError::ColumnLimit {
+            cell: reference,
+            limit: options.max_columns,
+        }),
+        MaxColumnsAction::Clip => {
+            if !*warned {
+                let message = format!(
+                    "sheet '{}' has cells beyond {} columns (first at {}), clipping",
+                    sheet_name, options.max_columns, reference
+                );
+                warn(options, Warning { message, sheet: Some(sheet_name.to_string()), cell: Some(reference) });
+                *warned = true;
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Fails on a header row other than `--expect-header` or `--expect-columns` require.
+fn check_header(header: &[String], options: &Options) -> Result<()> {
+    if let Some(expected) = &options.expect_header {
+        if header != expected.as_slice() {
+            return Err(Xlsx2CsvError::Expectation(format!(
+                "header is '{}', expected '{}' (--expect-header)",
+                header.join(","),
+                expected.join(",")
+            )));
+        }
+    }
+    if let Some(expected) = options.expect_columns.filter(|&expected| expected != header.len()) {
+        return Err(Xlsx2CsvError::Expectation(format!(
+            "header has {} column(s), expected {} (--expect-columns)",
+            header.len(),
+            expected
+        )));
+    }
+    Ok(())
+}
+
+/// Fails on a count of data rows outside `--expect-rows-min` and
+/// `--expect-rows-max`, or on none with `--fail-if-empty`.
+fn check_data_rows(rows: u64, options: &Options) -> Result<()> {
+    if options.fail_if_empty && rows == 0 {
+        return Err(Xlsx2CsvError::NoDataRows);
+    }
+    if let Some(min) = options.expect_rows_min.filter(|&min| rows < min) {
+        return Err(Xlsx2CsvError::Expectation(format!(
+            "{} data row(s), expected at least {} (--expect-rows-min)",
+            rows, min
+        )));
+    }
+    if let Some(max) = options.expect_rows_max.filter(|&max| rows > max) {
+        return Err(Xlsx2CsvError::Expectation(format!(
+            "{} data row(s), expected at most {} (--expect-rows-max)",
+            rows, max
+        )));
+    }
+    Ok(())
+}
+
+/// Fails on a cell value that does not fit the cell's type, for `--strict`.
+fn check_value(cell_type: CellType, value: &str, col: usize, row_number: usize) -> Result<()> {
+    let value = value.trim();
+    let expected = match cell_type {
+        CellType::Number if !value.is_empty() && value.parse::<f64>().is_err() => "a number",
+        CellType::Bool if !matches!(value, "0" | "1") => "a boolean (0 or 1)",
+        CellType::SharedString if value.parse::<usize>().is_err() => "a shared string index",
+        _ => return Ok(()),
+    };
+    Err(Xlsx2CsvError::InvalidCell {
+        cell: format!("{}{}", column_name(col), row_number),
+        reason: format!("'{}' is not {}", value, expected),
+    })
+}
+
+/// Reports a cell that does not fit the type `--types` gives its column,
+/// which is kept as it is: a warning, or an error with `--type-errors fail`.
+fn type_mismatch(
+    value: &str,
+    column_type: ColumnType,
+    column: &str,
+    col: usize,
+    row_number: usize,
+    sheet_name: &str,
+    options: &Options,
+) -> Result<()> {
+    let cell = format!("{}{}", column_name(col), row_number);
+    let reason = format!("'{}' is not {} as --types requires for column '{}'", value.trim(), column_type.description(), column);
+    if options.type_errors == TypeErrors::Fail {
+        return Err(Xlsx2CsvError::InvalidCell { cell, reason });
+    }
+    let message = format!("{}!{}: {}, keeping it", quote_sheet_name(sheet_name), cell, reason);
+    warn(options, Warning { message, sheet: Some(sheet_name.to_string()), cell: Some(cell) });
+    Ok(())
+}
+
+/// The unescaped text of a text node inside a cell.
+fn cell_text<'a>(text: &'a BytesText, col: usize, row_number: usize, options: &Options) -> Result<Cow<'a, str>> {
+    unescape_text(text, options.invalid_utf8, || cell_name(col, row_number))
+}
+
+fn cell_name(col: usize, row_number: usize) -> String {
+    format!("Cell {}{}", column_name(col), row_number)
+}
+
+/// Reports a cell referring to a string past the end of the shared string
+/// table, which is written empty: a warning, or an error with `--strict`.
+fn missing_shared_string(index: usize, col: usize, row_number: usize, sheet_name: &str, options: &Options) -> Result<()> {
+    let cell = format!("{}{}", column_name(col), row_number);
+    if options.strict {
+        return Err(Xlsx2CsvError::SharedStringOutOfRange { cell, index });
+    }
+    let message = format!(
+        "{}!{} refers to shared string {}, past the end of the table, writing it empty",
+        quote_sheet_name(sheet_name),
+        cell,
+        index
+    );
+    warn(options, Warning { message, sheet: Some(sheet_name.to_string()), cell: Some(cell) });
+    Ok(())
+}
+
+/// The cells of the row being parsed, stored back to back in one buffer that
+/// is reused from row to row.
+#[derive(Default)]
+struct RowBuffer {
+    text: String,
+    cells: Vec<Range<usize>>,
+}
+
+impl RowBuffer {
+    fn clear(&mut self) {
+        self.text.clear();
+        self.cells.clear();
+    }
+
+    /// Puts `value` at column `idx`.
+    fn place(&mut self, idx: usize, value: &str) {
+        if self.cells.len() <= idx {
+            self.cells.resize(idx + 1, 0..0);
+        }
+        let start = self.text.len();
+        self.text.push_str(value);
+        self.cells[idx] = start..self.text.len();
+    }
+
+    /// Copies the cells into `row`, reusing the strings it already holds.
+    fn copy_into(&self, row: &mut Vec<String>) {
+        row.resize_with(self.cells.len(), String::new);
+        for (cell, range) in row.iter_mut().zip(&self.cells) {
+            cell.clear();
+            cell.push_str(&self.text[range.clone()]);
+        }
+    }
+}
+
+fn place_cell<T: Clone + Default>(row: &mut Vec<T>, idx: usize, value: T) {
+    if row.len() <= idx {
+        row.resize(idx + 1, T::default());
+    }
+    row[idx] = value;
+}
+
+/// Returns the `si` group of a shared `<f>` element, if it is one.
+fn shared_formula_si(e: &quick_xml::events::BytesStart) -> Result<Option<String>> {
+    let mut shared = false;
+    let mut si = None;
+    for attr in e.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"t" => shared = attr.unescape_value()? == "shared",
+            b"si" => si = Some(attr.unescape_value()?.into_owned()),
+            _ => {}
+        }
+    }
+    Ok(si.filter(|_| shared))
+}
+
+/// Quotes a sheet name for use in a cell reference when it contains anything
+/// besides ASCII alphanumerics and underscores.
+pub fn quote_sheet_name(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\'', "''"))
+    }
+}
+
+/// Reads the 1-based `r` attribute of a `<row>` element, if present.
+pub fn row_number(e: &quick_xml::events::BytesStart) -> Result<Option<usize>> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.as_ref() == b"r" {
+            return Ok(attr.unescape_value()?.trim().parse().ok());
+        }
+    }
+    Ok(None)
+}
+
+/// Extracts the 1-based row number from a cell reference (ex. C5 -> 5).
+pub fn cell_row(cell_ref: &str) -> Option<usize> {
+    cell_ref
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic() || c == '$')
+        .trim_start_matches('$')
+        .parse()
+        .ok()
+}
+
+pub fn column_index(cell_ref: &str) -> Option<usize> {
+    let mut col = 0usize;
+    let mut has_column = false;
+    for c in cell_ref.chars() {
+        if c.is_ascii_alphabetic() {
+            has_column = true;
+            col = col
+                .saturating_mul(26)
+                .saturating_add(c.to_ascii_uppercase() as usize - b'A' as usize + 1);
+        } else {
+            break;
+        }
+    }
+    if has_column {
+        Some(col.saturating_sub(1))
+    } else {
+        None
+    }
+}
+
+/// Converts a 0-based column index to its letters (0 -> A, 27 -> AB).
+pub fn column_name(mut idx: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'A' + (idx % 26) as u8);
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::MaxColumnsAction;
+    use crate::writer::OutputFormat;
+    use crate::{convert, fixtures, Options, Xlsx2CsvError};
+    use std::io::Cursor;
+
+    /// A sheet whose second row has a cell with a corrupted, huge column reference.
+    fn corrupted() -> Vec<u8> {
+        fixtures::xlsx(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+<row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row>
+<row r="2"><c r="A2"><v>1</v></c><c r="ZZZZZZZZZZZZZZZZ2"><v>9</v></c></row>
+</sheetData></worksheet>"#,
+        )
+    }
+
+    fn run(options: &Options) -> crate::Result<String> {
+        let mut out = Vec::new();
+        convert(Cursor::new(corrupted()), options, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn cells_past_the_column_limit_fail_before_any_bookkeeping() {
+        let modes = [
+            Options { format: OutputFormat::Long, ..Options::default() },
+            Options { format: OutputFormat::Jsonl, infer_types: true, ..Options::default() },
+            Options { format: OutputFormat::Long, max_columns: 10, ..Options::default() },
+        ];
+        for options in &modes {
+            match run(options) {
+                Err(Xlsx2CsvError::Sheet { source, .. }) => assert!(matches!(*source, Xlsx2CsvError::ColumnLimit { .. })),
+                other => panic!("expected a column limit error, got {:?}", other.map_err(|err| err.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_cells_are_dropped() {
+        let options = Options {
+            format: OutputFormat::Long,
+            max_columns: 10,
+            max_columns_action: MaxColumnsAction::Clip,
+            ..Options::default()
+        };
+        let out = run(&options).unwrap();
+        assert_eq!(out.lines().count(), 3, "{}", out);
+    }
+}
@@ -0,0 +1,108 @@
+//! Output buffers that move to temporary files once a memory budget is used
+//! up, see [`Options::max_memory`](crate::Options::max_memory).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Memory taken from the budget at once, so that small writes do not contend on it.
+const RESERVATION: u64 = 64 * 1024;
+
+/// Bytes the buffers of a conversion may still hold in memory, shared by all
+/// of them.
+#[derive(Clone)]
+pub struct MemoryBudget(Arc<AtomicU64>);
+
+impl MemoryBudget {
+    /// A budget of `bytes`, unlimited for `None`.
+    pub fn new(bytes: Option<u64>) -> Self {
+        MemoryBudget(Arc::new(AtomicU64::new(bytes.unwrap_or(u64::MAX))))
+    }
+
+    fn reserve(&self, bytes: u64) -> bool {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(bytes))
+            .is_ok()
+    }
+
+    fn release(&self, bytes: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| Some(left.saturating_add(bytes)));
+    }
+}
+
+/// Bytes kept in memory while the budget allows and in a temporary file
+/// from the first write that does not fit.
+pub struct SpillBuffer {
+    budget: MemoryBudget,
+    memory: Vec<u8>,
+    reserved: u64,
+    file: Option<BufWriter<File>>,
+}
+
+impl SpillBuffer {
+    pub fn new(budget: &MemoryBudget) -> Self {
+        SpillBuffer {
+            budget: budget.clone(),
+            memory: Vec::new(),
+            reserved: 0,
+            file: None,
+        }
+    }
+
+    /// Copies the buffered bytes to `out`.
+    pub fn write_to(mut self, out: &mut impl Write) -> io::Result<()> {
+        match self.file.take() {
+            Some(file) => {
+                let mut file = file.into_inner().map_err(|err| err.into_error())?;
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(&mut file, out)?;
+            }
+            None => out.write_all(&self.memory)?,
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<&mut BufWriter<File>> {
+        debug!(bytes = self.memory.len(), "memory budget used up, buffering output in a temporary file");
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        file.write_all(&self.memory)?;
+        self.memory = Vec::new();
+        self.budget.release(std::mem::take(&mut self.reserved));
+        Ok(self.file.insert(file))
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            return file.write(buf);
+        }
+        let needed = (self.memory.len() + buf.len()) as u64;
+        if needed > self.reserved {
+            let more = (needed - self.reserved).max(RESERVATION);
+            if !self.budget.reserve(more) {
+                return self.spill()?.write(buf);
+            }
+            self.reserved += more;
+        }
+        self.memory.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        self.budget.release(self.reserved);
+    }
+}
@@ -0,0 +1,171 @@
+//! Per-sheet statistics for the `stats` subcommand.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{BufReader, Read, Seek, Write};
+use zip::read::ZipArchive;
+
+use crate::archive::{eq_local, load_relationships, open_part};
+use crate::sheet::{column_index, CellType, MAX_COLUMNS};
+use crate::workbook::{load_sheets, SheetInfo};
+use crate::Result;
+
+/// Counts gathered while streaming one worksheet. Only cells with a value
+/// count; styled but empty cells are ignored.
+#[derive(Default)]
+struct SheetStats {
+    rows: usize,
+    columns: usize,
+    cells: usize,
+    strings: usize,
+    numbers: usize,
+    bools: usize,
+    errors: usize,
+}
+
+impl SheetStats {
+    fn add(&mut self, cell_type: CellType) {
+        self.cells += 1;
+        match cell_type {
+            CellType::SharedString | CellType::InlineStr | CellType::PlainStr => self.strings += 1,
+            CellType::Bool => self.bools += 1,
+            CellType::Error => self.errors += 1,
+            CellType::Number => self.numbers += 1,
+        }
+    }
+
+    /// Share of the used rectangle (rows × columns) that holds a value.
+    fn density(&self) -> f64 {
+        let area = self.rows as f64 * self.columns as f64;
+        if area == 0.0 {
+            0.0
+        } else {
+            self.cells as f64 / area
+        }
+    }
+}
+
+fn sheet_stats<R: Read + Seek>(archive: &mut ZipArchive<R>, sheet_path: &str) -> Result<SheetStats> {
+    let file = open_part(archive, sheet_path)?;
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+
+    let mut stats = SheetStats::default();
+    let mut row_has_value = false;
+    let mut next_col = 0;
+    let mut col = 0;
+    let mut cell_type = CellType::Number;
+    let mut has_value = false;
+    let mut in_cell = false;
+    let mut in_formula = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if eq_local(e.name().as_ref(), b"row") => {
+                row_has_value = false;
+                next_col = 0;
+            }
+            Event::End(e) if row_has_value && eq_local(e.name().as_ref(), b"row") => stats.rows += 1,
+            Event::Start(e) if eq_local(e.name().as_ref(), b"c") => {
+                in_cell = true;
+                has_value = false;
+                col = next_col;
+                cell_type = CellType::Number;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"t" => cell_type = CellType::from_attr(&attr.unescape_value()?),
+                        b"r" => col = column_index(&attr.unescape_value()?).unwrap_or(col),
+                        _ => {}
+                    }
+                }
+                next_col = col.saturating_add(1);
+            }
+            Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"r" {
+                        next_col = column_index(&attr.unescape_value()?).unwrap_or(next_col);
+                    }
+                }
+                next_col = next_col.saturating_add(1);
+            }
+            Event::Start(e) if in_cell && eq_local(e.name().as_ref(), b"f") => in_formula = true,
+            Event::End(e) if in_cell && eq_local(e.name().as_ref(), b"f") => in_formula = false,
+            Event::Text(t) if in_cell && !in_formula && !t.is_empty() => has_value = true,
+            Event::CData(t) if in_cell && !in_formula && !t.is_empty() => has_value = true,
+            Event::End(e) if eq_local(e.name().as_ref(), b"c") => {
+                in_cell = false;
+                if has_value {
+                    row_has_value = true;
+                    // A corrupted reference past XFD counts as the last column
+                    stats.columns = stats.columns.max(col.min(MAX_COLUMNS - 1) + 1);
+                    stats.add(cell_type);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(stats)
+}
+
+/// Writes one csv line per sheet with row, column and cell counts, the number
+/// of cells of each type and the data density.
+pub fn print_stats<R: Read + Seek, W: Write>(archive: &mut ZipArchive<R>, out: W) -> Result<()> {
+    let rels = load_relationships(archive)?;
+    let sheets = load_sheets(archive, &rels, false)?;
+
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["sheet", "rows", "columns", "cells", "string", "number", "bool", "error", "density"])?;
+    for SheetInfo { name, path, .. } in &sheets {
+        let stats = sheet_stats(archive, path)?;
+        wtr.write_record([
+            name.clone(),
+            stats.rows.to_string(),
+            stats.columns.to_string(),
+            stats.cells.to_string(),
+            stats.strings.to_string(),
+            stats.numbers.to_string(),
+            stats.bools.to_string(),
+            stats.errors.to_string(),
+            format!("{:.3}", stats.density()),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sheet_stats, SheetStats};
+    use crate::fixtures;
+    use crate::sheet::CellType;
+    use std::io::Cursor;
+    use zip::ZipArchive;
+
+    #[test]
+    fn cells_count_by_type() {
+        let mut stats = SheetStats::default();
+        for cell_type in [CellType::SharedString, CellType::InlineStr, CellType::PlainStr, CellType::Number, CellType::Bool, CellType::Error] {
+            stats.add(cell_type);
+        }
+        assert_eq!((stats.cells, stats.strings, stats.numbers, stats.bools, stats.errors), (6, 3, 1, 1, 1));
+    }
+
+    #[test]
+    fn corrupted_reference_caps_the_columns() {
+        let xlsx = fixtures::xlsx(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+<row r="1"><c r="A1"><v>1</v></c><c r="ZZZZZZZZZZZZZZZZ1"><v>2</v></c><c/></row>
+</sheetData></worksheet>"#,
+        );
+        let mut archive = ZipArchive::new(Cursor::new(xlsx)).unwrap();
+        let stats = sheet_stats(&mut archive, "xl/worksheets/sheet1.xml").unwrap();
+        assert_eq!((stats.rows, stats.columns, stats.cells), (1, 16384, 2));
+        assert!(stats.density() > 0.0);
+    }
+}
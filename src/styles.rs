@@ -0,0 +1,386 @@
+//! Cell styles from `xl/styles.xml`, used to recognise date cells, the
+//! number formats `--preserve-leading-zeros` follows and the look of the
+//! cells `--style-columns` writes.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek};
+use tracing::instrument;
+use zip::read::ZipArchive;
+
+use crate::archive::{eq_local, open_part};
+use crate::Result;
+
+/// What the number format of a cell format does to the numbers it shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberFormat {
+    #[default]
+    General,
+    /// A date or time
+    Date,
+    /// The `@` format, showing numbers as they are typed
+    Text,
+    /// A format of only zeros, ex. `00000`, padding whole numbers to this many digits
+    ZeroPadded(usize),
+}
+
+/// The parts of a cell format `--style-columns` writes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CellStyle {
+    /// The number format code, empty for locale dependent built-in formats
+    pub number_format: String,
+    /// The pattern fill color as `#RRGGBB`, or `theme:N` for a theme color;
+    /// empty without a fill
+    pub fill: String,
+    pub bold: bool,
+}
+
+/// A color as `styles.xml` gives it, resolved once the palette is known.
+#[derive(Clone)]
+enum Color {
+    Rgb(String),
+    Indexed(usize),
+    Theme(String),
+}
+
+/// The default palette of `indexed` colors, replaced by `<indexedColors>`.
+const INDEXED_COLORS: [&str; 64] = [
+    "000000", "FFFFFF", "FF0000", "00FF00", "0000FF", "FFFF00", "FF00FF", "00FFFF", "000000", "FFFFFF", "FF0000", "00FF00", "0000FF",
+    "FFFF00", "FF00FF", "00FFFF", "800000", "008000", "000080", "808000", "800080", "008080", "C0C0C0", "808080", "9999FF", "993366",
+    "FFFFCC", "CCFFFF", "660066", "FF8080", "0066CC", "CCCCFF", "000080", "FF00FF", "FFFF00", "00FFFF", "800080", "800000", "008080",
+    "0000FF", "00CCFF", "CCFFFF", "CCFFCC", "FFFF99", "99CCFF", "FF99CC", "CC99FF", "FFCC99", "3366FF", "33CCCC", "99CC00", "FFCC00",
+    "FF9900", "FF6600", "666699", "969696", "003366", "339966", "003300", "333300", "993300", "993366", "333399", "333333",
+];
+
+/// For each cell format (`<xf>` of `<cellXfs>`, referenced by a cell's `s`
+/// attribute) whether its number format displays a date or time.
+pub fn load_date_styles<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<bool>> {
+    Ok(load_number_formats(archive)?.iter().map(|format| *format == NumberFormat::Date).collect())
+}
+
+/// The kind of number format of each cell format, `<xf>` of `<cellXfs>`
+/// referenced by a cell's `s` attribute.
+#[instrument(skip_all)]
+pub fn load_number_formats<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<NumberFormat>> {
+    let mut styles = Vec::new();
+    let Ok(file) = open_part(archive, "xl/styles.xml") else {
+        return Ok(styles);
+    };
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut custom_formats: HashMap<u32, NumberFormat> = HashMap::new();
+    let mut in_cell_xfs = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"numFmt") => {
+                let mut id = None;
+                let mut code = String::new();
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"numFmtId" => id = attr.unescape_value()?.trim().parse().ok(),
+                        b"formatCode" => code = attr.unescape_value()?.into_owned(),
+                        _ => {}
+                    }
+                }
+                if let Some(id) = id {
+                    custom_formats.insert(id, classify_format(&code));
+                }
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"cellXfs") => in_cell_xfs = true,
+            Event::End(e) if eq_local(e.name().as_ref(), b"cellXfs") => in_cell_xfs = false,
+            Event::Start(e) | Event::Empty(e) if in_cell_xfs && eq_local(e.name().as_ref(), b"xf") => {
+                let mut id = 0;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"numFmtId" {
+                        id = attr.unescape_value()?.trim().parse().unwrap_or(0);
+                    }
+                }
+                styles.push(custom_formats.get(&id).copied().unwrap_or_else(|| builtin_format(id)));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(styles)
+}
+
+/// The number format code, fill color and boldness of each cell format,
+/// `<xf>` of `<cellXfs>` referenced by a cell's `s` attribute.
+#[instrument(skip_all)]
+pub fn load_cell_styles<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<CellStyle>> {
+    let Ok(file) = open_part(archive, "xl/styles.xml") else {
+        return Ok(Vec::new());
+    };
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut custom_formats: HashMap<u32, String> = HashMap::new();
+    // Bold flag of each <font> and color of each <fill>; differential formats have their own
+    let mut fonts: Vec<bool> = Vec::new();
+    let mut fills: Vec<Option<Color>> = Vec::new();
+    let mut palette: Vec<String> = Vec::new();
+    // numFmtId, fontId and fillId of each cell format
+    let mut xfs: Vec<(u32, usize, usize)> = Vec::new();
+    let mut section = Vec::new();
+    let mut patterned = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if [b"fonts".as_slice(), b"fills", b"cellXfs", b"indexedColors"].contains(&e.local_name().as_ref()) => {
+                section = e.local_name().as_ref().to_vec();
+            }
+            Event::End(e) if e.local_name().as_ref() == section => section.clear(),
+            Event::Start(e) | Event::Empty(e) => {
+                let name = e.local_name();
+                let attribute = |key: &[u8]| -> Result<Option<String>> {
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        if attr.key.local_name().as_ref() == key {
+                            return Ok(Some(attr.unescape_value()?.into_owned()));
+                        }
+                    }
+                    Ok(None)
+                };
+                match (section.as_slice(), name.as_ref()) {
+                    (_, b"numFmt") => {
+                        if let (Some(id), Some(code)) = (attribute(b"numFmtId")?, attribute(b"formatCode")?) {
+                            if let Ok(id) = id.trim().parse() {
+                                custom_formats.insert(id, code);
+                            }
+                        }
+                    }
+                    (b"fonts", b"font") => fonts.push(false),
+                    (b"fonts", b"b") => {
+                        if let Some(bold) = fonts.last_mut() {
+                            *bold = !matches!(attribute(b"val")?.as_deref(), Some("0" | "false"));
+                        }
+                    }
+                    (b"fills", b"fill") => fills.push(None),
+                    (b"fills", b"patternFill") => patterned = attribute(b"patternType")?.is_some_and(|pattern| pattern != "none"),
+                    (b"fills", b"fgColor") if patterned => {
+                        let color = if let Some(rgb) = attribute(b"rgb")? {
+                            Some(Color::Rgb(rgb))
+                        } else if let Some(index) = attribute(b"indexed")? {
+                            index.trim().parse().ok().map(Color::Indexed)
+                        } else {
+                            attribute(b"theme")?.map(Color::Theme)
+                        };
+                        if let Some(fill) = fills.last_mut() {
+                            *fill = color;
+                        }
+                    }
+                    (b"cellXfs", b"xf") => {
+                        let id = |value: Option<String>| value.and_then(|value| value.trim().parse().ok()).unwrap_or(0);
+                        xfs.push((id(attribute(b"numFmtId")?) as u32, id(attribute(b"fontId")?), id(attribute(b"fillId")?)));
+                    }
+                    (b"indexedColors", b"rgbColor") => palette.push(attribute(b"rgb")?.unwrap_or_default()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    // The palette comes after the fills that use it
+    let color_text = |color: &Color| match color {
+        Color::Rgb(rgb) => format!("#{}", rgb.get(rgb.len().saturating_sub(6)..).unwrap_or_default().to_ascii_uppercase()),
+        Color::Indexed(index) => palette
+            .get(*index)
+            .map(String::as_str)
+            .or_else(|| INDEXED_COLORS.get(*index).copied())
+            .map_or_else(String::new, |rgb| format!("#{}", &rgb[rgb.len().saturating_sub(6)..]).to_ascii_uppercase()),
+        Color::Theme(theme) => format!("theme:{}", theme),
+    };
+    Ok(xfs
+        .into_iter()
+        .map(|(format, font, fill)| CellStyle {
+            number_format: custom_formats.get(&format).cloned().unwrap_or_else(|| builtin_format_code(format).to_string()),
+            fill: fills.get(fill).and_then(Option::as_ref).map(color_text).unwrap_or_default(),
+            bold: fonts.get(font).copied().unwrap_or(false),
+        })
+        .collect())
+}
+
+/// The code of a built-in number format, empty for the locale dependent ones.
+fn builtin_format_code(id: u32) -> &'static str {
+    match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => "",
+    }
+}
+
+/// The kind of a built-in number format; dates and times include the East Asian ones.
+fn builtin_format(id: u32) -> NumberFormat {
+    match id {
+        14..=22 | 27..=36 | 45..=47 | 50..=58 => NumberFormat::Date,
+        49 => NumberFormat::Text,
+        _ => NumberFormat::General,
+    }
+}
+
+fn classify_format(code: &str) -> NumberFormat {
+    if code == "@" {
+        NumberFormat::Text
+    } else if !code.is_empty() && code.bytes().all(|b| b == b'0') {
+        NumberFormat::ZeroPadded(code.len())
+    } else if is_date_format(code) {
+        NumberFormat::Date
+    } else {
+        NumberFormat::General
+    }
+}
+
+/// Whether a custom format code contains date or time tokens outside quoted
+/// text, escaped characters and bracketed colors or conditions.
+fn is_date_format(code: &str) -> bool {
+    // Only the first section applies to positive numbers
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => return false,
+            '"' => {
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '\\' | '_' | '*' => {
+                chars.next();
+            }
+            '[' => {
+                // [h], [mm] and [ss] are elapsed time, anything else is a color or condition
+                let inner: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if !inner.is_empty() && inner.chars().all(|c| matches!(c.to_ascii_lowercase(), 'h' | 'm' | 's')) {
+                    return true;
+                }
+            }
+            c if matches!(c.to_ascii_lowercase(), 'y' | 'm' | 'd' | 'h' | 's') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Converts an Excel date serial to `YYYY-MM-DD`, with `THH:MM:SS` appended
+/// when the serial has a time of day.
+pub fn serial_to_iso(serial: f64, date1904: bool) -> Option<String> {
+    let (year, month, day, seconds) = serial_to_civil(serial, date1904)?;
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    if seconds == 0 {
+        Some(date)
+    } else {
+        Some(format!(
+            "{}T{:02}:{:02}:{:02}",
+            date,
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ))
+    }
+}
+
+/// Formats an Excel date serial with a strftime style pattern supporting
+/// `%Y`, `%y`, `%m`, `%d`, `%H`, `%M`, `%S` and `%%`.
+pub fn format_serial(serial: f64, date1904: bool, pattern: &str) -> Option<String> {
+    let (year, month, day, seconds) = serial_to_civil(serial, date1904)?;
+    let mut out = String::with_capacity(pattern.len() + 8);
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('y') => out.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", seconds / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", seconds % 3600 / 60)),
+            Some('S') => out.push_str(&format!("{:02}", seconds % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    Some(out)
+}
+
+/// Splits an Excel date serial into year, month, day and seconds of the day.
+fn serial_to_civil(serial: f64, date1904: bool) -> Option<(i64, u32, u32, i64)> {
+    if !(0.0..2_958_466.0).contains(&serial) {
+        return None;
+    }
+    let mut days = serial.floor() as i64;
+    let mut seconds = ((serial - serial.floor()) * 86_400.0).round() as i64;
+    if seconds == 86_400 {
+        days += 1;
+        seconds = 0;
+    }
+
+    // Days since 1970-01-01. The 1900 system counts the non-existent
+    // 1900-02-29, so serials before it start one day later.
+    let unix_days = if date1904 {
+        days - 24_107
+    } else if days < 60 {
+        days - 25_568
+    } else {
+        days - 25_569
+    };
+    let (year, month, day) = civil_from_days(unix_days);
+    Some((year, month, day, seconds))
+}
+
+/// Gregorian date for a count of days since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,46 @@
+//! Decoding of cell text that is not valid UTF-8, see
+//! [`Options::invalid_utf8`](crate::Options::invalid_utf8), and removal of
+//! control characters for [`Options::strip_control_chars`](crate::Options::strip_control_chars).
+
+use quick_xml::escape::unescape;
+use std::borrow::Cow;
+
+use crate::sheet::InvalidUtf8;
+use crate::{Result, Xlsx2CsvError};
+
+/// Decodes the raw bytes of a text node and resolves its entity and character
+/// references. `location` names the cell or string for the error of
+/// [`InvalidUtf8::Error`].
+pub fn unescape_text(raw: &[u8], mode: InvalidUtf8, location: impl FnOnce() -> String) -> Result<Cow<'_, str>> {
+    let text = decode_text(raw, mode, location)?;
+    if let Cow::Owned(unescaped) = unescape(&text).map_err(quick_xml::Error::from)? {
+        return Ok(Cow::Owned(unescaped));
+    }
+    Ok(text)
+}
+
+/// Decodes the raw bytes of a text or CDATA node.
+pub fn decode_text(raw: &[u8], mode: InvalidUtf8, location: impl FnOnce() -> String) -> Result<Cow<'_, str>> {
+    if let Ok(text) = std::str::from_utf8(raw) {
+        return Ok(Cow::Borrowed(text));
+    }
+    match mode {
+        InvalidUtf8::Error => Err(Xlsx2CsvError::InvalidUtf8(location())),
+        InvalidUtf8::Replace => Ok(String::from_utf8_lossy(raw)),
+        InvalidUtf8::Skip => Ok(Cow::Owned(raw.utf8_chunks().map(|chunk| chunk.valid()).collect())),
+    }
+}
+
+/// Whether `c` is a C0 or C1 control character other than tab and line breaks.
+fn is_stripped_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// Removes the control characters of `value`, keeping tabs and line breaks.
+pub fn strip_control_chars(value: Cow<'_, str>) -> Cow<'_, str> {
+    if value.contains(is_stripped_control) {
+        Cow::Owned(value.chars().filter(|&c| !is_stripped_control(c)).collect())
+    } else {
+        value
+    }
+}
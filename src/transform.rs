@@ -0,0 +1,628 @@
+//! Header and row transformations: renaming, `--where` filtering, cell
+//! transformers, `--map-values` dictionaries, `--replace` rules, `--types`,
+//! deduplication and `--explode`.
+
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::expr::Expr;
+use crate::schema::is_iso_date;
+use crate::{CellType, Options, Result, Xlsx2CsvError};
+
+/// Where the cell handed to a [`CellTransformer`] sits.
+#[derive(Clone, Copy, Debug)]
+pub struct CellContext<'a> {
+    pub sheet: &'a str,
+    /// 1-based sheet row
+    pub row: usize,
+    /// 0-based column
+    pub col: usize,
+    /// the column's name in the sheet's header row, empty past its end
+    pub column: &'a str,
+}
+
+/// Rewrites the value of a data cell, see [`ConverterBuilder::transform`](crate::ConverterBuilder::transform).
+pub type CellTransformer = Arc<dyn Fn(&CellContext, String) -> String + Send + Sync>;
+
+/// Row-level transformations compiled once from the options and applied to
+/// every converted sheet.
+pub struct Transforms {
+    rename: HashMap<String, String>,
+    snake_case_headers: bool,
+    filter: Option<Expr>,
+    /// transformers with the header columns they are limited to, empty for all
+    transformers: Vec<(Vec<String>, CellTransformer)>,
+    /// `--map-values` replacements of whole cell values by header column
+    value_maps: HashMap<String, HashMap<String, String>>,
+    replacements: Vec<Replacement>,
+    dedup: bool,
+    dedup_key: Vec<String>,
+    types: Vec<(String, ColumnType)>,
+    /// `--explode` header columns with their delimiters
+    explode: Vec<(String, String)>,
+}
+
+/// The type `--types` gives a column, which its data cells are coerced to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// Date serials become ISO dates, or follow `--dateformat`
+    Date,
+}
+
+/// A `--replace` rule, optionally limited to the named header columns.
+struct Replacement {
+    columns: Vec<String>,
+    pattern: Regex,
+    replacement: String,
+}
+
+/// Per-sheet state of [`Transforms`], resolved against the sheet's header row.
+pub struct SheetTransforms {
+    name: String,
+    header: Vec<String>,
+    filter: Option<Expr>,
+    transform_columns: Vec<Option<HashSet<usize>>>,
+    /// positions of the columns with a value map, with their names
+    mapped_columns: Vec<(usize, String)>,
+    replace_columns: Vec<Option<HashSet<usize>>>,
+    dedup_columns: Vec<usize>,
+    seen_rows: HashSet<Vec<String>>,
+    column_types: Vec<Option<ColumnType>>,
+    /// positions of the `--explode` columns, with their delimiters
+    explode_columns: Vec<(usize, String)>,
+}
+
+impl Transforms {
+    pub fn from_options(options: &Options) -> Result<Self> {
+        Ok(Transforms {
+            rename: options.rename.iter().cloned().collect(),
+            snake_case_headers: options.snake_case_headers,
+            filter: options.where_expr.as_deref().map(Expr::parse).transpose()?,
+            transformers: options
+                .map_columns
+                .iter()
+                .map(|spec| parse_map_column(spec))
+                .chain(options.transformers.iter().cloned().map(Ok))
+                .collect::<Result<_>>()?,
+            value_maps: match &options.map_values {
+                Some(path) => load_value_maps(path)?,
+                None => HashMap::new(),
+            },
+            replacements: options
+                .replace
+                .iter()
+                .map(|spec| parse_replacement(spec))
+                .collect::<Result<_>>()?,
+            dedup: options.dedup || !options.dedup_key.is_empty(),
+            dedup_key: options.dedup_key.clone(),
+            types: options.types.iter().map(|spec| parse_column_type(spec)).collect::<Result<_>>()?,
+            explode: options.explode.iter().map(|spec| parse_explode(spec)).collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn bind(&self, sheet_name: &str, header: &[String]) -> Result<SheetTransforms> {
+        let filter = self.filter.as_ref().map(|f| f.bind(header)).transpose()?;
+        // Scoped rules whose columns are missing from this sheet match nothing
+        let scope = |columns: &[String]| -> Option<HashSet<usize>> {
+            (!columns.is_empty()).then(|| {
+                columns
+                    .iter()
+                    .filter_map(|name| header.iter().position(|h| h == name))
+                    .collect()
+            })
+        };
+        let transform_columns = self.transformers.iter().map(|(columns, _)| scope(columns)).collect();
+        let replace_columns = self.replacements.iter().map(|rule| scope(&rule.columns)).collect();
+        let mapped_columns = header
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| self.value_maps.contains_key(name.as_str()))
+            .map(|(idx, name)| (idx, name.clone()))
+            .collect();
+        let dedup_columns = resolve_columns(header, &self.dedup_key, "--dedup-key")?;
+        let mut column_types = vec![None; header.len()];
+        for (name, column_type) in &self.types {
+            if let Some(idx) = header.iter().position(|h| h == name) {
+                column_types[idx] = Some(*column_type);
+            }
+        }
+
+        let explode_columns = self
+            .explode
+            .iter()
+            .filter_map(|(name, delimiter)| Some((header.iter().position(|h| h == name)?, delimiter.clone())))
+            .collect();
+
+        let mut sheet = SheetTransforms {
+            name: sheet_name.to_string(),
+            header: header.to_vec(),
+            filter,
+            transform_columns,
+            mapped_columns,
+            replace_columns,
+            dedup_columns,
+            seen_rows: HashSet::new(),
+            column_types,
+            explode_columns,
+        };
+        // Repeated copies of the header block are dropped along with duplicate data
+        if self.dedup {
+            sheet.seen_rows.insert(sheet.dedup_key(header));
+        }
+        Ok(sheet)
+    }
+
+    /// Applies the data-row transformations in place, returning `false` when
+    /// the row should be dropped.
+    pub fn apply_row(&self, sheet: &mut SheetTransforms, row_number: usize, row: &mut [String]) -> bool {
+        for ((_, transformer), columns) in self.transformers.iter().zip(&sheet.transform_columns) {
+            for (idx, value) in row.iter_mut().enumerate() {
+                if columns.as_ref().is_some_and(|cols| !cols.contains(&idx)) {
+                    continue;
+                }
+                let context = CellContext {
+                    sheet: &sheet.name,
+                    row: row_number,
+                    col: idx,
+                    column: sheet.header.get(idx).map_or("", String::as_str),
+                };
+                *value = transformer(&context, std::mem::take(value));
+            }
+        }
+
+        for (idx, name) in &sheet.mapped_columns {
+            if let Some(value) = row.get_mut(*idx) {
+                if let Some(mapped) = self.value_maps[name].get(value.as_str()) {
+                    value.clone_from(mapped);
+                }
+            }
+        }
+
+        for (rule, columns) in self.replacements.iter().zip(&sheet.replace_columns) {
+            for (idx, value) in row.iter_mut().enumerate() {
+                if columns.as_ref().is_some_and(|cols| !cols.contains(&idx)) {
+                    continue;
+                }
+                if let std::borrow::Cow::Owned(replaced) = rule.pattern.replace_all(value, rule.replacement.as_str()) {
+                    *value = replaced;
+                }
+            }
+        }
+
+        if sheet.filter.as_ref().is_some_and(|f| !f.matches(row)) {
+            return false;
+        }
+
+        if self.dedup {
+            let key = sheet.dedup_key(row);
+            if !sheet.seen_rows.insert(key) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn apply_header(&self, header: &mut [String]) {
+        for name in header.iter_mut() {
+            if let Some(new) = self.rename.get(name.as_str()) {
+                *name = new.clone();
+            }
+        }
+
+        if self.snake_case_headers {
+            snake_case_names(header);
+        }
+    }
+}
+
+impl SheetTransforms {
+    /// The type `--types` gives the column at this header position, with its name.
+    pub fn column_type(&self, col: usize) -> Option<(ColumnType, &str)> {
+        let column_type = self.column_types.get(col).copied().flatten()?;
+        Some((column_type, &self.header[col]))
+    }
+
+    /// The rows `--explode` makes of a data row, one for each piece of its
+    /// exploded cells, or for each combination of pieces with several
+    /// columns. Pieces are trimmed and empty ones dropped. Empty when no cell
+    /// of the row holds its delimiter, and the row stays as it is.
+    pub fn explode(&self, row: &[String]) -> Vec<Vec<String>> {
+        let splits = |row: &[String], idx: usize, delimiter: &str| row.get(idx).is_some_and(|value| value.contains(delimiter));
+        if !self.explode_columns.iter().any(|(idx, delimiter)| splits(row, *idx, delimiter)) {
+            return Vec::new();
+        }
+        let mut rows = vec![row.to_vec()];
+        for (idx, delimiter) in &self.explode_columns {
+            rows = rows
+                .into_iter()
+                .flat_map(|row| {
+                    if !splits(&row, *idx, delimiter) {
+                        return vec![row];
+                    }
+                    let mut pieces: Vec<String> =
+                        row[*idx].split(delimiter.as_str()).map(str::trim).filter(|piece| !piece.is_empty()).map(str::to_string).collect();
+                    if pieces.is_empty() {
+                        pieces.push(String::new());
+                    }
+                    pieces
+                        .into_iter()
+                        .map(|piece| {
+                            let mut row = row.clone();
+                            row[*idx] = piece;
+                            row
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+        rows
+    }
+
+    fn dedup_key(&self, row: &[String]) -> Vec<String> {
+        if self.dedup_columns.is_empty() {
+            let mut key = row.to_vec();
+            // Trailing empty cells do not make a row distinct
+            while key.last().is_some_and(|s| s.is_empty()) {
+                key.pop();
+            }
+            key
+        } else {
+            self.dedup_columns
+                .iter()
+                .map(|&idx| row.get(idx).cloned().unwrap_or_default())
+                .collect()
+        }
+    }
+}
+
+impl ColumnType {
+    /// The value coerced to this type, `None` when it does not fit. Date
+    /// serials are converted before, so dates only accept ISO dates here.
+    pub fn coerce(self, value: &str) -> Option<Cow<'_, str>> {
+        let trimmed = value.trim();
+        match self {
+            ColumnType::String => Some(Cow::Borrowed(value)),
+            ColumnType::Int if trimmed.parse::<i64>().is_ok() => Some(Cow::Borrowed(trimmed)),
+            // Whole numbers written with a fraction or an exponent, within the exact range of f64
+            ColumnType::Int => trimmed
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64)
+                .map(|n| Cow::Owned((n as i64).to_string())),
+            ColumnType::Float => trimmed.parse::<f64>().ok().filter(|n| n.is_finite()).map(|_| Cow::Borrowed(trimmed)),
+            ColumnType::Bool => match trimmed.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" => Some(Cow::Borrowed("true")),
+                "false" | "0" | "no" => Some(Cow::Borrowed("false")),
+                _ => None,
+            },
+            ColumnType::Date => is_iso_date(trimmed).then_some(Cow::Borrowed(trimmed)),
+        }
+    }
+
+    /// The cell type the coerced values are written as, for `--infer-types`.
+    pub fn cell_type(self) -> CellType {
+        match self {
+            ColumnType::String | ColumnType::Date => CellType::PlainStr,
+            ColumnType::Int | ColumnType::Float => CellType::Number,
+            ColumnType::Bool => CellType::Bool,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ColumnType::String => "a string",
+            ColumnType::Int => "an integer",
+            ColumnType::Float => "a number",
+            ColumnType::Bool => "a boolean",
+            ColumnType::Date => "a date",
+        }
+    }
+}
+
+/// Parses a `Column=type` rule of `--types`.
+fn parse_column_type(s: &str) -> Result<(String, ColumnType)> {
+    let Some((column, name)) = s.rsplit_once('=') else {
+        return Err(Xlsx2CsvError::InvalidOption(format!("Invalid type rule, expected 'Column=type': {}", s)));
+    };
+    let column_type = match name.trim().to_ascii_lowercase().as_str() {
+        "string" | "str" | "text" => ColumnType::String,
+        "int" | "integer" => ColumnType::Int,
+        "float" | "number" => ColumnType::Float,
+        "bool" | "boolean" => ColumnType::Bool,
+        "date" => ColumnType::Date,
+        other => {
+            return Err(Xlsx2CsvError::InvalidOption(format!(
+                "Unknown type '{}' in --types, expected string, int, float, bool or date",
+                other
+            )))
+        }
+    };
+    Ok((column.trim().to_string(), column_type))
+}
+
+/// Parses a `Column=delimiter` rule of `--explode`.
+fn parse_explode(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((column, delimiter)) if !delimiter.is_empty() => Ok((column.trim().to_string(), delimiter.to_string())),
+        _ => Err(Xlsx2CsvError::InvalidOption(format!("Invalid explode rule, expected 'Column=delimiter': {}", s))),
+    }
+}
+
+/// Reads the `column,from,to` rows of a `--map-values` file into the value
+/// maps of each column. A first row naming those three columns is skipped.
+fn load_value_maps(path: &Path) -> Result<HashMap<String, HashMap<String, String>>> {
+    let file = File::open(path).map_err(|source| Xlsx2CsvError::InputFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(file);
+    let mut maps: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for (idx, record) in reader.records().enumerate() {
+        let record = record?;
+        if record.len() != 3 {
+            return Err(Xlsx2CsvError::InvalidOption(format!(
+                "Invalid row {} in {}, expected 'column,from,to'",
+                idx + 1,
+                path.display()
+            )));
+        }
+        if idx == 0 && record.iter().zip(["column", "from", "to"]).all(|(field, name)| field.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        maps.entry(record[0].to_string()).or_default().insert(record[1].to_string(), record[2].to_string());
+    }
+    Ok(maps)
+}
+
+/// Maps header names given to `flag` onto column positions.
+fn resolve_columns(header: &[String], names: &[String], flag: &str) -> Result<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| Xlsx2CsvError::UnknownColumn {
+                    option: flag.to_string(),
+                    name: name.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Converts every name to snake_case, naming empty ones `column_N` and
+/// suffixing repeats with `_2`, `_3`, ... so the result is unique.
+pub fn snake_case_names(names: &mut [String]) {
+    let mut seen = HashSet::new();
+    for (idx, name) in names.iter_mut().enumerate() {
+        let mut base = snake_case(name);
+        if base.is_empty() {
+            base = format!("column_{}", idx + 1);
+        }
+        let mut unique = base.clone();
+        let mut n = 2;
+        while !seen.insert(unique.clone()) {
+            unique = format!("{}_{}", base, n);
+            n += 1;
+        }
+        *name = unique;
+    }
+}
+
+/// Lowercases `s`, strips accents and collapses every run of characters that
+/// are not ASCII alphanumerics into a single underscore.
+fn snake_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfd().filter(|c| !is_combining_mark(*c)) {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    while out.ends_with('_') {
+        out.pop();
+    }
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+/// Parses a `--map-column 'Col1,Col2=trim|upper'` spec into a transformer
+/// running the named steps left to right.
+fn parse_map_column(s: &str) -> Result<(Vec<String>, CellTransformer)> {
+    let Some((columns, steps)) = s.split_once('=') else {
+        return Err(Xlsx2CsvError::InvalidOption(format!(
+            "Invalid map-column spec, expected 'Column=step|step': {}",
+            s
+        )));
+    };
+    let columns = columns.split(',').map(|c| c.trim().to_string()).collect();
+    let steps = steps
+        .split('|')
+        .map(|step| -> Result<fn(String) -> String> {
+            Ok(match step.trim() {
+                "trim" => |v| v.trim().to_string(),
+                "ltrim" => |v| v.trim_start().to_string(),
+                "rtrim" => |v| v.trim_end().to_string(),
+                "upper" => |v| v.to_uppercase(),
+                "lower" => |v| v.to_lowercase(),
+                "collapse" => |v| v.split_whitespace().collect::<Vec<_>>().join(" "),
+                "digits" => |v| v.chars().filter(char::is_ascii_digit).collect(),
+                other => {
+                    return Err(Xlsx2CsvError::InvalidOption(format!(
+                        "Unknown map-column step '{}', expected trim, ltrim, rtrim, upper, lower, collapse or digits",
+                        other
+                    )))
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let transformer: CellTransformer = Arc::new(move |_, value| steps.iter().fold(value, |value, step| step(value)));
+    Ok((columns, transformer))
+}
+
+fn parse_replacement(s: &str) -> Result<Replacement> {
+    let (columns, rule) = match s.split_once("::") {
+        Some((columns, rule)) => (columns.split(',').map(|c| c.trim().to_string()).collect(), rule),
+        None => (Vec::new(), s),
+    };
+    let Some((pattern, replacement)) = rule.split_once("=>") else {
+        return Err(Xlsx2CsvError::InvalidOption(format!(
+            "Invalid replace rule, expected 'regex=>replacement': {}",
+            s
+        )));
+    };
+    Ok(Replacement {
+        columns,
+        pattern: Regex::new(pattern)?,
+        replacement: replacement.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn transforms(options: Options) -> Transforms {
+        Transforms::from_options(&options).unwrap()
+    }
+
+    /// Binds to an `id,name,amount` header and runs `rows` through the data-row
+    /// transformations, returning the rows that are kept.
+    fn apply(transforms: &Transforms, rows: &[&[&str]]) -> Vec<Vec<String>> {
+        let mut sheet = transforms.bind("Sheet1", &strings(&["id", "name", "amount"])).unwrap();
+        rows.iter()
+            .enumerate()
+            .filter_map(|(idx, row)| {
+                let mut row = strings(row);
+                transforms.apply_row(&mut sheet, idx + 2, &mut row).then_some(row)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns_bind_to_the_header_before_renames() {
+        let t = transforms(Options {
+            rename: vec![("name".to_string(), "Name".to_string())],
+            where_expr: Some("col('name') == 'b'".to_string()),
+            ..Options::default()
+        });
+        assert_eq!(apply(&t, &[&["1", "a", "5"], &["2", "b", "6"]]), [strings(&["2", "b", "6"])]);
+        let mut header = strings(&["id", "name"]);
+        t.apply_header(&mut header);
+        assert_eq!(header, ["id", "Name"]);
+    }
+
+    #[test]
+    fn unknown_columns_fail_where_they_must_exist() {
+        let t = transforms(Options { dedup_key: strings(&["missing"]), ..Options::default() });
+        assert!(matches!(
+            t.bind("Sheet1", &strings(&["id"])),
+            Err(Xlsx2CsvError::UnknownColumn { option, name }) if option == "--dedup-key" && name == "missing"
+        ));
+        let t = transforms(Options { where_expr: Some("col('missing') == 1".to_string()), ..Options::default() });
+        assert!(t.bind("Sheet1", &strings(&["id"])).is_err());
+        // Scoped rules on columns the sheet lacks match nothing
+        let t = transforms(Options {
+            replace: strings(&["missing::a=>b"]),
+            types: strings(&["missing=int"]),
+            explode: strings(&["missing=;"]),
+            ..Options::default()
+        });
+        assert_eq!(apply(&t, &[&["1", "a", "x;y"]]), [strings(&["1", "a", "x;y"])]);
+    }
+
+    #[test]
+    fn map_column_steps_run_in_order_on_their_columns() {
+        let t = transforms(Options { map_columns: strings(&["name=trim|upper", "amount=digits"]), ..Options::default() });
+        assert_eq!(apply(&t, &[&[" 7 ", "  ann b ", "$1,200"]]), [strings(&[" 7 ", "ANN B", "1200"])]);
+        assert!(Transforms::from_options(&Options { map_columns: strings(&["name=shout"]), ..Options::default() }).is_err());
+        assert!(Transforms::from_options(&Options { map_columns: strings(&["name"]), ..Options::default() }).is_err());
+    }
+
+    #[test]
+    fn value_maps_replace_whole_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("map.csv");
+        std::fs::write(&path, "column,from,to\nname,a,Ann\nname,b,Bob\nid,1,one\n").unwrap();
+        let t = transforms(Options { map_values: Some(path.clone()), ..Options::default() });
+        assert_eq!(apply(&t, &[&["1", "a", "ab"], &["2", "c", "b"]]), [strings(&["one", "Ann", "ab"]), strings(&["2", "c", "b"])]);
+
+        std::fs::write(&path, "name,a\n").unwrap();
+        assert!(Transforms::from_options(&Options { map_values: Some(path), ..Options::default() }).is_err());
+    }
+
+    #[test]
+    fn replace_rules_apply_to_their_columns() {
+        let t = transforms(Options { replace: strings(&["[0-9]+=>#", "name,amount::^a=>A"]), ..Options::default() });
+        assert_eq!(apply(&t, &[&["a1", "ab2", "a"]]), [strings(&["a#", "Ab#", "A"])]);
+        assert!(Transforms::from_options(&Options { replace: strings(&["no arrow"]), ..Options::default() }).is_err());
+        assert!(Transforms::from_options(&Options { replace: strings(&["(=>x"]), ..Options::default() }).is_err());
+    }
+
+    #[test]
+    fn dedup_drops_repeated_rows_and_header_copies() {
+        let t = transforms(Options { dedup: true, ..Options::default() });
+        let rows = apply(&t, &[&["1", "a", "5"], &["1", "a", "5", ""], &["id", "name", "amount"], &["2", "a", "5"]]);
+        assert_eq!(rows, [strings(&["1", "a", "5"]), strings(&["2", "a", "5"])]);
+
+        let t = transforms(Options { dedup_key: strings(&["name"]), ..Options::default() });
+        assert_eq!(apply(&t, &[&["1", "a", "5"], &["2", "a", "6"], &["3", "b", "7"]]).len(), 2);
+    }
+
+    #[test]
+    fn types_bind_by_column_and_coerce() {
+        let t = transforms(Options { types: strings(&["amount=int", "id = text"]), ..Options::default() });
+        let sheet = t.bind("Sheet1", &strings(&["id", "name", "amount"])).unwrap();
+        assert_eq!(sheet.column_type(0), Some((ColumnType::String, "id")));
+        assert_eq!(sheet.column_type(1), None);
+        assert_eq!(sheet.column_type(2), Some((ColumnType::Int, "amount")));
+        assert_eq!(sheet.column_type(9), None);
+        assert!(Transforms::from_options(&Options { types: strings(&["amount=money"]), ..Options::default() }).is_err());
+
+        assert_eq!(ColumnType::Int.coerce(" 12 ").as_deref(), Some("12"));
+        assert_eq!(ColumnType::Int.coerce("1.0e3").as_deref(), Some("1000"));
+        assert_eq!(ColumnType::Int.coerce("1.5"), None);
+        assert_eq!(ColumnType::Float.coerce("inf"), None);
+        assert_eq!(ColumnType::Bool.coerce("Yes").as_deref(), Some("true"));
+        assert_eq!(ColumnType::Bool.coerce("0").as_deref(), Some("false"));
+        assert_eq!(ColumnType::Date.coerce("2024-02-30").as_deref(), Some("2024-02-30"));
+        assert_eq!(ColumnType::Date.coerce("45000"), None);
+    }
+
+    #[test]
+    fn explode_repeats_rows_per_piece() {
+        let t = transforms(Options { explode: strings(&["name=;", "amount=|"]), ..Options::default() });
+        let sheet = t.bind("Sheet1", &strings(&["id", "name", "amount"])).unwrap();
+        assert!(sheet.explode(&strings(&["1", "a", "5"])).is_empty());
+        assert_eq!(
+            sheet.explode(&strings(&["1", "a; b;", "5|6"])),
+            [strings(&["1", "a", "5"]), strings(&["1", "a", "6"]), strings(&["1", "b", "5"]), strings(&["1", "b", "6"])]
+        );
+        assert_eq!(sheet.explode(&strings(&["1", ";", "5"])), [strings(&["1", "", "5"])]);
+        assert!(Transforms::from_options(&Options { explode: strings(&["name="]), ..Options::default() }).is_err());
+    }
+
+    #[test]
+    fn snake_case_names_are_unique() {
+        let mut names = strings(&["Unit Price (€)", "unit_price", "", "2nd Café", "Unit-Price"]);
+        snake_case_names(&mut names);
+        assert_eq!(names, ["unit_price", "unit_price_2", "column_3", "_2nd_cafe", "unit_price_3"]);
+    }
+}
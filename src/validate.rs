@@ -0,0 +1,176 @@
+//! Structural checks of a workbook for the `validate` subcommand.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufReader, Read, Seek};
+use zip::read::ZipArchive;
+
+use crate::archive::{eq_local, load_relationships, open_part, part_rels_path, read_relationships, resolve_part_path, Bounded};
+use crate::audit::count_shared_strings;
+use crate::sheet::{cell_row, column_index, CellRange};
+use crate::workbook::{load_sheets, SheetInfo};
+use crate::{Result, Xlsx2CsvError};
+
+/// Parts every workbook has.
+const REQUIRED_PARTS: [&str; 3] = ["[Content_Types].xml", "_rels/.rels", "xl/workbook.xml"];
+
+/// Something wrong with one part of the workbook.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub part: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.part, self.message)
+    }
+}
+
+/// Checks that every entry of the archive inflates with a matching checksum,
+/// that the required parts are present, that relationships point to
+/// existing parts, and that the cells of every sheet stay within the shared
+/// string table and the declared dimension. Returns every problem found.
+pub fn validate<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut problem = |part: &str, message: String| {
+        problems.push(Problem {
+            part: part.to_string(),
+            message,
+        })
+    };
+
+    let mut corrupt = HashSet::new();
+    for idx in 0..archive.len() {
+        let file = archive.by_index(idx)?;
+        let name = file.name().to_string();
+        // The checksum is verified once the entry is read to its end
+        if let Err(err) = io::copy(&mut Bounded::entry(file), &mut io::sink()) {
+            problem(&name, format!("corrupt entry: {}", err));
+            corrupt.insert(name);
+        }
+    }
+
+    for part in REQUIRED_PARTS {
+        if archive.index_for_name(part).is_none() {
+            problem(part, "required part is missing".to_string());
+        }
+    }
+    if archive.index_for_name("xl/workbook.xml").is_none() {
+        return Ok(problems);
+    }
+
+    for rel in read_relationships(archive, "xl/_rels/workbook.xml.rels")? {
+        let target = resolve_part_path("xl/workbook.xml", &rel.target);
+        if !rel.external && archive.index_for_name(&target).is_none() {
+            problem("xl/_rels/workbook.xml.rels", format!("relationship {} points to missing part '{}'", rel.id, target));
+        }
+    }
+    let rels = load_relationships(archive)?;
+    let sheets = match load_sheets(archive, &rels, true) {
+        Ok(sheets) => sheets,
+        Err(err @ Xlsx2CsvError::UnresolvedRelationship { .. }) => {
+            problem("xl/workbook.xml", err.to_string());
+            load_sheets(archive, &rels, false)?
+        }
+        Err(err) => return Err(err),
+    };
+
+    let shared_strings = count_shared_strings(archive)?;
+    for sheet in &sheets {
+        if archive.index_for_name(&sheet.path).is_none() || corrupt.contains(&sheet.path) {
+            continue;
+        }
+        let rels_path = part_rels_path(&sheet.path);
+        for rel in read_relationships(archive, &rels_path)? {
+            let target = resolve_part_path(&sheet.path, &rel.target);
+            if !rel.external && archive.index_for_name(&target).is_none() {
+                problem(&rels_path, format!("relationship {} points to missing part '{}'", rel.id, target));
+            }
+        }
+        for message in check_sheet(archive, sheet, shared_strings)? {
+            problem(&sheet.path, message);
+        }
+    }
+    Ok(problems)
+}
+
+/// Scans the cells of one sheet for shared string references past the end
+/// of the table and cells outside its `<dimension>`.
+fn check_sheet<R: Read + Seek>(archive: &mut ZipArchive<R>, sheet: &SheetInfo, shared_strings: usize) -> Result<Vec<String>> {
+    let mut reader = Reader::from_reader(BufReader::new(open_part(archive, &sheet.path)?));
+    let mut buf = Vec::new();
+    let mut dimension: Option<(String, CellRange)> = None;
+    let mut cell_ref = String::new();
+    let mut shared_string_cell = false;
+    let mut in_value = false;
+    let mut outside: Option<String> = None;
+    // Cells referring to a missing shared string, and the first of them
+    let mut missing = (0usize, String::new());
+
+    loop {
+        let event = match reader.read_event_into(&mut buf) {
+            Ok(event) => event,
+            Err(err) => return Ok(vec![format!("malformed XML in sheet '{}': {}", sheet.name, err)]),
+        };
+        match event {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"dimension") => {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"ref" {
+                        let declared = attr.unescape_value()?.into_owned();
+                        dimension = declared.parse().ok().map(|range| (declared, range));
+                    }
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"c") => {
+                cell_ref.clear();
+                shared_string_cell = false;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"r" => cell_ref.push_str(&attr.unescape_value()?),
+                        b"t" => shared_string_cell = attr.unescape_value()? == "s",
+                        _ => {}
+                    }
+                }
+                let position = column_index(&cell_ref).zip(cell_row(&cell_ref));
+                if let (Some((_, range)), Some((col, row)), None) = (&dimension, position, &outside) {
+                    let (first_row, last_row) = range.rows;
+                    let (first_col, last_col) = range.columns;
+                    if row < first_row || row > last_row || col < first_col || col > last_col {
+                        outside = Some(cell_ref.clone());
+                    }
+                }
+            }
+            Event::Start(e) if eq_local(e.name().as_ref(), b"v") => in_value = true,
+            Event::End(e) if eq_local(e.name().as_ref(), b"v") => in_value = false,
+            Event::Text(t) if in_value && shared_string_cell => {
+                let index = t.unescape()?.trim().parse::<usize>();
+                if index.is_ok_and(|index| index >= shared_strings) {
+                    if missing.0 == 0 {
+                        missing.1 = cell_ref.clone();
+                    }
+                    missing.0 += 1;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    let mut messages = Vec::new();
+    if missing.0 > 0 {
+        messages.push(format!(
+            "{} cell(s) of sheet '{}' refer to shared strings past the end of the table of {} (first at {})",
+            missing.0, sheet.name, shared_strings, missing.1
+        ));
+    }
+    if let (Some(cell), Some((declared, _))) = (outside, dimension) {
+        messages.push(format!("cell {} of sheet '{}' lies outside its dimension {}", cell, sheet.name, declared));
+    }
+    Ok(messages)
+}
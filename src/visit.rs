@@ -0,0 +1,69 @@
+//! Callback style reading of a whole workbook, see [`Visitor`].
+
+use std::fmt;
+
+use crate::rows::Cell;
+use crate::sheet::column_name;
+use crate::Result;
+
+/// Position of a cell, displayed as its A1 style reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellRef {
+    /// 1-based sheet row
+    pub row: usize,
+    /// 0-based column
+    pub col: usize,
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", column_name(self.col), self.row)
+    }
+}
+
+/// Receives the contents of a workbook from [`Workbook::visit`](crate::Workbook::visit)
+/// as they are parsed, for sinks that do not need the csv output, ex. inserting
+/// rows into a database. Every callback has an empty default, and an error
+/// returned from any of them stops the walk.
+///
+/// ```no_run
+/// use xlsx2csv::{Cell, CellRef, Visitor, Workbook};
+///
+/// struct Totals(f64);
+///
+/// impl Visitor for Totals {
+///     fn on_cell(&mut self, _cell_ref: CellRef, cell: &Cell) -> xlsx2csv::Result<()> {
+///         if let Cell::Number(n) = cell {
+///             self.0 += n;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let mut totals = Totals(0.0);
+/// Workbook::open("input.xlsx")?.visit(&mut totals)?;
+/// println!("{}", totals.0);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait Visitor {
+    /// Called before the rows of each sheet, in workbook order. Returning
+    /// false skips the sheet.
+    fn on_sheet_start(&mut self, _name: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Called with every non-empty cell of a row, before [`on_row`](Self::on_row).
+    fn on_cell(&mut self, _cell_ref: CellRef, _cell: &Cell) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called with each row present in the sheet and its 1-based row number.
+    fn on_row(&mut self, _row_number: usize, _cells: &[Cell]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the last row of a visited sheet.
+    fn on_sheet_end(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! Warnings about data the conversion worked around instead of failing, see
+//! [`Options::warning`](crate::Options::warning).
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Options;
+
+/// Something the conversion converted only in part, ex. a cell referring to
+/// a missing shared string that is written empty.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    /// The whole message, as printed to stderr without a callback
+    pub message: String,
+    /// Name of the sheet the warning is about
+    pub sheet: Option<String>,
+    /// Reference of the cell the warning is about, ex. `B3`
+    pub cell: Option<String>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Called with every warning instead of printing it.
+pub type WarningCallback = Arc<dyn Fn(&Warning) + Send + Sync>;
+
+/// Passes `warning` to [`Options::warning`], or prints it to stderr.
+pub(crate) fn warn(options: &Options, warning: Warning) {
+    match &options.warning {
+        Some(callback) => callback(&warning),
+        None => eprintln!("Warning: {}", warning),
+    }
+}
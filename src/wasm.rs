@@ -0,0 +1,39 @@
+//! JavaScript bindings for `wasm32-unknown-unknown`, behind the `wasm` feature.
+//!
+//! ```js
+//! import init, { convert } from "./pkg/xlsx2csv.js";
+//!
+//! await init();
+//! const bytes = new Uint8Array(await file.arrayBuffer());
+//! const csv = convert(bytes, { sheet: "Data", delimiter: ";" });
+//! ```
+
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+use crate::builder::OptionsSpec;
+use crate::{Result, Xlsx2CsvError};
+
+/// Converts the workbook in `data` and returns the output as a string.
+#[wasm_bindgen(js_name = convert)]
+pub fn convert_js(data: &[u8], options: JsValue) -> std::result::Result<String, JsError> {
+    let output = convert_bytes(data, options)?;
+    Ok(String::from_utf8(output)?)
+}
+
+/// Converts the workbook in `data` and returns the output as a `Uint8Array`.
+#[wasm_bindgen(js_name = convertToBytes)]
+pub fn convert_to_bytes_js(data: &[u8], options: JsValue) -> std::result::Result<Vec<u8>, JsError> {
+    Ok(convert_bytes(data, options)?)
+}
+
+fn convert_bytes(data: &[u8], options: JsValue) -> Result<Vec<u8>> {
+    let options: OptionsSpec = if options.is_undefined() || options.is_null() {
+        OptionsSpec::default()
+    } else {
+        serde_wasm_bindgen::from_value(options).map_err(|err| Xlsx2CsvError::InvalidOption(err.to_string()))?
+    };
+    let mut output = Vec::new();
+    options.builder()?.convert(Cursor::new(data), &mut output)?;
+    Ok(output)
+}
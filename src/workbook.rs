@@ -0,0 +1,520 @@
+//! Workbook-level parts: the sheet list and external workbook links, and the
+//! [`Workbook`] reading API.
+
+use quick_xml::events::Event;
+use quick_xml::{NsReader, Reader};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::instrument;
+use zip::read::ZipArchive;
+
+use crate::archive::{
+    eq_local, is_relationship_id, load_relationships, open_part, part_rels_path, read_relationships, resolve_part_path,
+};
+use crate::events::SheetEventReader;
+use crate::richtext::{Phonetic, RichText};
+use crate::rows::{Cell, DeserializeRows, Rows};
+use crate::shared_strings::{load_shared_strings, StringArena};
+use crate::visit::{CellRef, Visitor};
+use crate::{meta, styles, Result, Xlsx2CsvError};
+
+/// An opened workbook whose sheets can be read row by row without
+/// materializing them:
+///
+/// ```no_run
+/// let mut workbook = xlsx2csv::Workbook::open("input.xlsx")?;
+/// for row in workbook.sheet("Data")?.rows() {
+///     let row = row?;
+///     println!("{}", row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>().join("\t"));
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Workbook<R: Read + Seek> {
+    archive: ZipArchive<R>,
+    sheets: Vec<SheetInfo>,
+    shared_strings: Arc<StringArena>,
+    date_styles: Arc<[bool]>,
+    date1904: bool,
+}
+
+impl Workbook<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| Xlsx2CsvError::InputFile { path: path.to_path_buf(), source })?;
+        Workbook::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read + Seek> Workbook<R> {
+    /// Reads the sheet list, shared strings and cell styles of the workbook.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut archive = ZipArchive::new(reader)?;
+        let rels = load_relationships(&mut archive)?;
+        let sheets = load_sheets(&mut archive, &rels, false)?;
+        let shared_strings = load_shared_strings(&mut archive, RichText::Plain, Phonetic::Strip)?;
+        let date_styles = styles::load_date_styles(&mut archive)?;
+        let date1904 = meta::load_workbook_info(&mut archive)?.date1904;
+        Ok(Workbook {
+            archive,
+            sheets,
+            shared_strings: Arc::new(shared_strings),
+            date_styles: date_styles.into(),
+            date1904,
+        })
+    }
+
+    /// Sheet names in workbook order.
+    pub fn sheet_names(&self) -> impl Iterator<Item = &str> {
+        self.sheets.iter().map(|sheet| sheet.name.as_str())
+    }
+
+    /// The shared string table, indexed by the `raw` value of
+    /// [`CellType::SharedString`](crate::CellType::SharedString) cell events.
+    pub fn shared_strings(&self) -> &StringArena {
+        &self.shared_strings
+    }
+
+    /// Name, index, visibility, relationship id, part path and declared
+    /// dimension of every sheet, in workbook order.
+    pub fn sheets(&mut self) -> Result<Vec<SheetInfo>> {
+        let mut sheets = self.sheets.clone();
+        load_dimensions(&mut self.archive, &mut sheets)?;
+        Ok(sheets)
+    }
+
+    /// Walks every sheet in workbook order, passing its rows and cells to `visitor`.
+    pub fn visit<V: Visitor + ?Sized>(&mut self, visitor: &mut V) -> Result<()> {
+        for idx in 0..self.sheets.len() {
+            let name = self.sheets[idx].name.clone();
+            if !visitor.on_sheet_start(&name)? {
+                continue;
+            }
+            let mut rows = self.sheet(&name)?.rows();
+            while let Some(row) = rows.next().transpose()? {
+                let row_number = rows.row_number();
+                for (col, cell) in row.iter().enumerate() {
+                    if *cell != Cell::Empty {
+                        visitor.on_cell(CellRef { row: row_number, col }, cell)?;
+                    }
+                }
+                visitor.on_row(row_number, &row)?;
+            }
+            visitor.on_sheet_end(&name)?;
+        }
+        Ok(())
+    }
+
+    /// The name of the sheet `sheet` designates by name or 1-based number, the
+    /// first sheet without it.
+    pub(crate) fn resolve_sheet(&self, sheet: Option<&str>) -> Result<String> {
+        let names: Vec<String> = self.sheet_names().map(str::to_string).collect();
+        match sheet {
+            None => names.first().cloned().ok_or(Xlsx2CsvError::NoSheets),
+            Some(requested) if names.iter().any(|name| name == requested) => Ok(requested.to_string()),
+            Some(requested) => match requested.parse::<usize>() {
+                Ok(index) if index >= 1 && index <= names.len() => Ok(names[index - 1].clone()),
+                Ok(index) => Err(Xlsx2CsvError::SheetIndexOutOfRange { index, count: names.len() }),
+                Err(_) => Err(Xlsx2CsvError::SheetNotFound {
+                    requested: requested.to_string(),
+                    available: names,
+                }),
+            },
+        }
+    }
+
+    pub fn sheet(&mut self, name: &str) -> Result<Sheet<'_>> {
+        let Some(SheetInfo { path, .. }) = self.sheets.iter().find(|sheet| sheet.name == name) else {
+            return Err(Xlsx2CsvError::SheetNotFound {
+                requested: name.to_string(),
+                available: self.sheet_names().map(str::to_string).collect(),
+            });
+        };
+        Ok(Sheet {
+            name: name.to_string(),
+            file: Box::new(open_part(&mut self.archive, path)?),
+            shared_strings: Arc::clone(&self.shared_strings),
+            date_styles: Arc::clone(&self.date_styles),
+            date1904: self.date1904,
+        })
+    }
+}
+
+/// A sheet of a [`Workbook`], ready to be read with [`Sheet::rows`].
+pub struct Sheet<'a> {
+    name: String,
+    file: Box<dyn Read + 'a>,
+    shared_strings: Arc<StringArena>,
+    date_styles: Arc<[bool]>,
+    date1904: bool,
+}
+
+impl<'a> Sheet<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Decompresses the sheet into memory, so that it no longer borrows the
+    /// workbook, ex. to keep its rows in a struct or pass them to another thread.
+    pub fn into_owned(mut self) -> Result<Sheet<'static>> {
+        let mut data = Vec::new();
+        self.file.read_to_end(&mut data)?;
+        Ok(Sheet {
+            name: self.name,
+            file: Box::new(Cursor::new(data)),
+            shared_strings: self.shared_strings,
+            date_styles: self.date_styles,
+            date1904: self.date1904,
+        })
+    }
+
+    /// Streams the rows of the sheet, parsing each one as it is requested.
+    pub fn rows(self) -> Rows<'a> {
+        Rows::new(self.file, self.shared_strings, self.date_styles, self.date1904)
+    }
+
+    /// Iterates the raw row and cell events of the sheet, see [`SheetEventReader`].
+    pub fn events(self) -> SheetEventReader<'a> {
+        SheetEventReader::new(self.file)
+    }
+
+    /// Deserializes the rows below the header row into `T`, matching struct
+    /// fields to header names:
+    ///
+    /// ```no_run
+    /// #[derive(serde::Deserialize)]
+    /// struct Record {
+    ///     #[serde(rename = "Name")]
+    ///     name: String,
+    ///     #[serde(rename = "Amount")]
+    ///     amount: Option<f64>,
+    /// }
+    ///
+    /// let mut workbook = xlsx2csv::Workbook::open("input.xlsx")?;
+    /// for record in workbook.sheet("Data")?.deserialize::<Record>()? {
+    ///     let record = record?;
+    ///     println!("{}: {:?}", record.name, record.amount);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(self) -> Result<DeserializeRows<'a, T>> {
+        DeserializeRows::new(self.rows())
+    }
+}
+
+/// Selects the sheets to convert: the named or numbered sheet, or the first
+/// sheet (all sheets with `all`) narrowed by the include/exclude patterns.
+pub fn filter_sheets(
+    sheets: Vec<SheetInfo>,
+    sheetname: &Option<String>,
+    sheet_id: Option<usize>,
+    all: bool,
+    include_patterns: &[String],
+    exclude_patterns: &[String],
+) -> Result<Vec<SheetInfo>> {
+    // If specific sheet name is requested
+    if let Some(name) = sheetname {
+        if !sheets.iter().any(|sheet| &sheet.name == name) {
+            return Err(Xlsx2CsvError::SheetNotFound {
+                requested: name.clone(),
+                available: sheets.into_iter().map(|sheet| sheet.name).collect(),
+            });
+        }
+        let targets: Vec<SheetInfo> = sheets
+            .into_iter()
+            .filter(|sheet| &sheet.name == name)
+            .collect();
+        return Ok(targets);
+    }
+
+    // If specific sheet ID is requested
+    if let Some(id) = sheet_id {
+        if id == 0 || id > sheets.len() {
+            return Err(Xlsx2CsvError::SheetIndexOutOfRange {
+                index: id,
+                count: sheets.len(),
+            });
+        }
+        return Ok(vec![sheets[id - 1].clone()]);
+    }
+
+    // If all sheets or pattern matching
+    let mut targets = if all {
+        sheets
+    } else {
+        // Default to first sheet if not using -a
+        sheets.into_iter().take(1).collect()
+    };
+
+    // Apply include patterns if specified
+    if !include_patterns.is_empty() {
+        let patterns: Vec<Regex> = include_patterns
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        targets.retain(|sheet| patterns.iter().any(|p| p.is_match(&sheet.name)));
+    }
+
+    // Apply exclude patterns if specified
+    if !exclude_patterns.is_empty() {
+        let patterns: Vec<Regex> = exclude_patterns
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        targets.retain(|sheet| !patterns.iter().any(|p| p.is_match(&sheet.name)));
+    }
+
+    if targets.is_empty() {
+        return Err(Xlsx2CsvError::NoMatchingSheets);
+    }
+
+    Ok(targets)
+}
+
+/// Fails on two sheets with the same name or the same worksheet part, which
+/// would mix their rows or convert one part twice.
+pub fn check_conflicting_sheets(sheets: &[SheetInfo]) -> Result<()> {
+    let mut names: HashMap<String, &str> = HashMap::new();
+    let mut paths: HashMap<&str, &str> = HashMap::new();
+    for sheet in sheets {
+        if let Some(first) = names.insert(sheet.name.to_lowercase(), &sheet.name) {
+            return Err(Xlsx2CsvError::ConflictingSheets {
+                first: first.to_string(),
+                second: sheet.name.clone(),
+                conflict: "have the same name".to_string(),
+            });
+        }
+        if let Some(first) = paths.insert(&sheet.path, &sheet.name) {
+            return Err(Xlsx2CsvError::ConflictingSheets {
+                first: first.to_string(),
+                second: sheet.name.clone(),
+                conflict: format!("both point to {}", sheet.path),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Lists the targets of the workbook's `<externalReferences>`, in order, so
+/// that the `[N]` prefixes used in formulas index into it (1-based).
+pub fn load_external_links<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>> {
+    let rels = load_relationships(archive)?;
+    let file = open_part(archive, "xl/workbook.xml")?;
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut parts = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"externalReference") => {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if eq_local(attr.key.as_ref(), b"id") {
+                        let target = rels.get(attr.unescape_value()?.as_ref()).cloned().unwrap_or_default();
+                        parts.push(resolve_part_path("xl/workbook.xml", &target));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    drop(reader);
+
+    let mut links = Vec::with_capacity(parts.len());
+    for part in parts {
+        let target = read_relationships(archive, &part_rels_path(&part))?
+            .into_iter()
+            .find(|rel| rel.is("externalLinkPath") || rel.external)
+            .map(|rel| rel.target)
+            .unwrap_or(part);
+        links.push(target);
+    }
+    Ok(links)
+}
+
+/// Finds the external workbook indexes (`[1]Sheet!A1`) referenced by a formula.
+pub fn external_link_indexes(formula: &str) -> Vec<usize> {
+    let mut indexes = Vec::new();
+    let mut rest = formula;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        if let Ok(idx) = rest[..end].parse::<usize>() {
+            if idx > 0 && !indexes.contains(&idx) {
+                indexes.push(idx);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    indexes
+}
+
+/// Whether a sheet tab is shown in Excel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetVisibility {
+    Visible,
+    /// hidden, can be unhidden from the Excel UI
+    Hidden,
+    /// hidden, can only be unhidden from VBA
+    VeryHidden,
+}
+
+impl SheetVisibility {
+    fn from_state(state: &str) -> Self {
+        match state {
+            "hidden" => SheetVisibility::Hidden,
+            "veryHidden" => SheetVisibility::VeryHidden,
+            _ => SheetVisibility::Visible,
+        }
+    }
+
+    /// The `state` attribute value, "visible" for sheets without one.
+    pub fn name(self) -> &'static str {
+        match self {
+            SheetVisibility::Visible => "visible",
+            SheetVisibility::Hidden => "hidden",
+            SheetVisibility::VeryHidden => "veryHidden",
+        }
+    }
+}
+
+/// A sheet declared in `xl/workbook.xml`.
+#[derive(Clone, Debug)]
+pub struct SheetInfo {
+    pub name: String,
+    /// 1-based position in workbook order, as taken by `-s/--sheet`
+    pub index: usize,
+    pub visibility: SheetVisibility,
+    /// relationship id linking the sheet to its part
+    pub rel_id: String,
+    /// path of the worksheet part in the archive, ex. `xl/worksheets/sheet1.xml`
+    pub path: String,
+    /// the `<dimension ref>` of the worksheet, ex. `A1:D20`, only read by
+    /// [`Workbook::sheets`] and [`list_sheets`]
+    pub dimension: Option<String>,
+}
+
+/// Every sheet of the workbook with its declared dimension, in workbook order.
+pub fn list_sheets<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<SheetInfo>> {
+    let rels = load_relationships(archive)?;
+    let mut sheets = load_sheets(archive, &rels, false)?;
+    load_dimensions(archive, &mut sheets)?;
+    Ok(sheets)
+}
+
+fn load_dimensions<R: Read + Seek>(archive: &mut ZipArchive<R>, sheets: &mut [SheetInfo]) -> Result<()> {
+    for sheet in sheets {
+        sheet.dimension = load_dimension(archive, &sheet.path)?;
+    }
+    Ok(())
+}
+
+/// Reads the `<dimension ref>` that precedes the cells of a worksheet.
+fn load_dimension<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<Option<String>> {
+    let Ok(file) = open_part(archive, path) else {
+        return Ok(None);
+    };
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"dimension") => {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    if attr.key.as_ref() == b"ref" {
+                        return Ok(Some(attr.unescape_value()?.into_owned()));
+                    }
+                }
+                return Ok(None);
+            }
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"sheetData") => return Ok(None),
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Lists every sheet in workbook order, without its dimension. Sheets whose
+/// relationship id is not defined are left out, or fail with `strict`.
+#[instrument(skip_all)]
+pub fn load_sheets<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    rels: &HashMap<String, String>,
+    strict: bool,
+) -> Result<Vec<SheetInfo>> {
+    let file = open_part(archive, "xl/workbook.xml")?;
+    let mut reader = NsReader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    let mut sheets = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if eq_local(e.name().as_ref(), b"sheet") => {
+                let mut name = None;
+                let mut rel_id = None;
+                let mut visibility = SheetVisibility::Visible;
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    match attr.key.as_ref() {
+                        b"name" => name = Some(attr.unescape_value()?.into_owned()),
+                        b"state" => visibility = SheetVisibility::from_state(&attr.unescape_value()?),
+                        _ if is_relationship_id(&reader, attr.key) => {
+                            rel_id = Some(attr.unescape_value()?.into_owned());
+                        }
+                        _ => {}
+                    }
+                }
+                if let (Some(name), Some(rel_id)) = (name, rel_id) {
+                    if strict && !rels.contains_key(&rel_id) {
+                        return Err(Xlsx2CsvError::UnresolvedRelationship {
+                            part: "xl/workbook.xml".to_string(),
+                            id: rel_id,
+                        });
+                    }
+                    if let Some(target) = rels.get(&rel_id) {
+                        let path = normalize_sheet_path(target);
+                        sheets.push(SheetInfo {
+                            name,
+                            index: sheets.len() + 1,
+                            visibility,
+                            rel_id,
+                            path,
+                            dimension: None,
+                        });
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if sheets.is_empty() {
+        return Err(Xlsx2CsvError::NoSheets);
+    }
+
+    Ok(sheets)
+}
+
+fn normalize_sheet_path(target: &str) -> String {
+    let cleaned = target.trim_start_matches('/');
+    if cleaned.starts_with("xl/") {
+        cleaned.to_string()
+    } else {
+        format!("xl/{}", cleaned)
+    }
+}
+
@@ -0,0 +1,118 @@
+//! Output sinks for converted rows.
+
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+use crate::json::JsonRows;
+use crate::sheet::{column_name, CellType};
+use crate::styles::CellStyle;
+use crate::{Result, Xlsx2CsvError};
+
+/// Layout of the converted rows.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// the sheet grid as csv
+    Csv,
+    /// one csv record per non-empty cell (sheet,ref,row,col,type,value)
+    Long,
+    /// an array of objects keyed by the header row
+    Json,
+    /// one object per line, keyed by the header row
+    Jsonl,
+}
+
+/// Where [`convert_sheet`](crate::sheet::convert_sheet) sends its rows.
+pub enum RowWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json(JsonRows<W>),
+    /// Rows are only scanned, ex. for `--ddl` which prints statements afterwards
+    Discard(W),
+}
+
+impl<W: Write> RowWriter<W> {
+    /// Writes out the buffered rows, for a consistent output at checkpoints
+    /// and for readers of a pipe.
+    pub fn flush(&mut self) -> Result<()> {
+        match self {
+            RowWriter::Csv(w) => w.flush()?,
+            RowWriter::Json(json) => json.flush()?,
+            RowWriter::Discard(w) => w.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Completes the output and hands back the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        match self {
+            RowWriter::Csv(w) => Ok(w.into_inner().map_err(|err| err.into_error())?),
+            RowWriter::Json(json) => json.finish(),
+            RowWriter::Discard(w) => Ok(w),
+        }
+    }
+}
+
+/// Writes one `--format long` record per non-empty cell of a row, with the
+/// formula column for `--formulas` and the style columns for
+/// `--style-columns`, given the style index of each cell and the styles.
+pub fn write_long_cells<W: Write>(
+    writer: &mut csv::Writer<W>,
+    sheet_name: &str,
+    row_number: usize,
+    row: &[String],
+    types: &[CellType],
+    formulas: Option<&[String]>,
+    styles: Option<(&[usize], &[CellStyle])>,
+) -> Result<()> {
+    let row_text = row_number.to_string();
+    for (idx, value) in row.iter().enumerate() {
+        if value.is_empty() {
+            continue;
+        }
+        let reference = format!("{}{}", column_name(idx), row_number);
+        let col_text = (idx + 1).to_string();
+        let cell_type = types.get(idx).copied().unwrap_or_default().name();
+        let formula = match formulas.and_then(|formulas| formulas.get(idx)) {
+            Some(text) if !text.is_empty() => format!("={}", text),
+            _ => String::new(),
+        };
+        let mut record = vec![sheet_name, &reference, &row_text, &col_text, cell_type, value];
+        if formulas.is_some() {
+            record.push(&formula);
+        }
+        if let Some((indexes, cell_styles)) = styles {
+            let style = indexes.get(idx).and_then(|&style| cell_styles.get(style));
+            record.push(style.map_or("", |style| style.number_format.as_str()));
+            record.push(style.map_or("", |style| style.fill.as_str()));
+            record.push(if style.is_some_and(|style| style.bold) { "true" } else { "false" });
+        }
+        writer.write_record(&record)?;
+    }
+    Ok(())
+}
+
+/// Whether `err` or one of its sources is a broken pipe, which means the
+/// consumer of the output went away and conversion can stop quietly.
+pub fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    // Transparent variants hand out the source of the wrapped error, not the error itself
+    match err.downcast_ref::<Xlsx2CsvError>() {
+        Some(Xlsx2CsvError::Io(io_err)) => return is_broken_pipe(io_err),
+        Some(Xlsx2CsvError::Csv(csv_err)) => return is_broken_pipe(csv_err),
+        _ => {}
+    }
+    if let Some(io_err) = err.downcast_ref::<io::Error>() {
+        if io_err.kind() == io::ErrorKind::BrokenPipe {
+            return true;
+        }
+    }
+    if let Some(csv_err) = err.downcast_ref::<csv::Error>() {
+        if let csv::ErrorKind::Io(io_err) = csv_err.kind() {
+            if io_err.kind() == io::ErrorKind::BrokenPipe {
+                return true;
+            }
+        }
+    }
+    if let Some(source) = err.source() {
+        return is_broken_pipe(source);
+    }
+    false
+}